@@ -1,14 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use serde::{Serialize};
 
+use crate::span::Span;
+
+// identifies an expression so later passes can attach information to it,
+// spans can't since a group and its only item share one
+#[derive(Debug, Clone, Copy)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    // unique across parses, the repl checks entries from several sources together
+    pub fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NodeId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+// ids don't take part in comparing trees, parsing the same source twice gives equal trees
+impl PartialEq for NodeId {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+// type suffix of a number literal, e.g. the `u8` in `255u8`
+#[derive(Debug, PartialEq, Eq, Serialize, Clone, Copy)]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    F32,
+    F64,
+}
+
+impl NumberSuffix {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "i8" => NumberSuffix::I8,
+            "i16" => NumberSuffix::I16,
+            "i32" => NumberSuffix::I32,
+            "i64" => NumberSuffix::I64,
+            "i128" => NumberSuffix::I128,
+            "isize" => NumberSuffix::Isize,
+            "u8" => NumberSuffix::U8,
+            "u16" => NumberSuffix::U16,
+            "u32" => NumberSuffix::U32,
+            "u64" => NumberSuffix::U64,
+            "u128" => NumberSuffix::U128,
+            "usize" => NumberSuffix::Usize,
+            "f32" => NumberSuffix::F32,
+            "f64" => NumberSuffix::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumberSuffix::I8 => "i8",
+            NumberSuffix::I16 => "i16",
+            NumberSuffix::I32 => "i32",
+            NumberSuffix::I64 => "i64",
+            NumberSuffix::I128 => "i128",
+            NumberSuffix::Isize => "isize",
+            NumberSuffix::U8 => "u8",
+            NumberSuffix::U16 => "u16",
+            NumberSuffix::U32 => "u32",
+            NumberSuffix::U64 => "u64",
+            NumberSuffix::U128 => "u128",
+            NumberSuffix::Usize => "usize",
+            NumberSuffix::F32 => "f32",
+            NumberSuffix::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    // width in bits, pointer sized types are 64 bits
+    pub fn bits(&self) -> u32 {
+        match self {
+            NumberSuffix::I8 | NumberSuffix::U8 => 8,
+            NumberSuffix::I16 | NumberSuffix::U16 => 16,
+            NumberSuffix::I32 | NumberSuffix::U32 | NumberSuffix::F32 => 32,
+            NumberSuffix::I64 | NumberSuffix::U64 | NumberSuffix::F64 => 64,
+            NumberSuffix::Isize | NumberSuffix::Usize => 64,
+            NumberSuffix::I128 | NumberSuffix::U128 => 128,
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            NumberSuffix::I8
+                | NumberSuffix::I16
+                | NumberSuffix::I32
+                | NumberSuffix::I64
+                | NumberSuffix::I128
+                | NumberSuffix::Isize
+        )
+    }
+
+    // largest integer literal that fits, None for float suffixes
+    pub fn max_int(&self) -> Option<u128> {
+        match self.is_float() {
+            true => None,
+            false => Some(u128::MAX >> (128 - self.bits() + self.is_signed() as u32)),
+        }
+    }
+}
+
+// integer literals keep their radix so the source can be printed back as written,
+// a literal never includes a sign so the values are unsigned
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum Literal {
-    IntLiteral(i64),
-    FloatLiteral(f64),
+    IntLiteral(u128, Option<NumberSuffix>),
+    FloatLiteral(f64, Option<NumberSuffix>),
     BoolLiteral(bool),
     CharLiteral(char),
-    OctalLiteral(u32),
-    HexLiteral(u8),
-    BinaryLiteral(u32),
+    OctalLiteral(u128, Option<NumberSuffix>),
+    HexLiteral(u128, Option<NumberSuffix>),
+    BinaryLiteral(u128, Option<NumberSuffix>),
     StringLiteral(String),
 }
 
@@ -22,23 +148,111 @@ pub enum Atom {
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum UnaryOp {
-    SpreadExpr
+    SpreadExpr,
+    Negate,     // -a
+    Not,        // !a
+    BitwiseNot, // ~a
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::SpreadExpr => "...",
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitwiseNot => "~",
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct Unary {
     pub op: UnaryOp,
     pub expr: Box<Expr>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum BinaryOp {
     Assignment,
+    Reassignment, // `a = b`, `Assignment` is the `a: b` declaration
     Accessor,
     Invoke,  // can be called on any Body defines
     FieldDef,
     TypeDef,
     // BodyDef //  Body {} an invokable defined, has access to sibling values
+
+    // Arithmetic
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+
+    // Logical
+    And,
+    Or,
+
+    // Bitwise
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+
+    // Comparison
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl BinaryOp {
+    // how the operator is written in source
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Assignment => ":",
+            BinaryOp::Reassignment => "=",
+            BinaryOp::Accessor => ".",
+            BinaryOp::Invoke => "invoke",
+            BinaryOp::FieldDef => "field",
+            BinaryOp::TypeDef => "typedef",
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::BitwiseAnd => "&",
+            BinaryOp::BitwiseOr => "|",
+            BinaryOp::BitwiseXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessThanOrEqual => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterThanOrEqual => ">=",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::LessThan
+                | BinaryOp::LessThanOrEqual
+                | BinaryOp::GreaterThan
+                | BinaryOp::GreaterThanOrEqual
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
@@ -46,7 +260,21 @@ pub struct Binary {
     pub op: BinaryOp,
     pub left: Box<Expr>,
     pub right: Box<Expr>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
 }
+// a chain of two or more comparisons, `a < b < c` means `a < b && b < c`
+// with `b` evaluated once, `ops[i]` compares `operands[i]` with `operands[i + 1]`
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Comparison {
+    pub operands: Vec<Expr>,
+    pub ops: Vec<BinaryOp>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
+}
+
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum TernaryOp {
     // Conditional,
@@ -59,6 +287,9 @@ pub struct Ternary {
     pub left: Box<Expr>,
     pub middle: Box<Expr>,
     pub right: Box<Expr>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
@@ -73,18 +304,78 @@ pub enum GroupOp{
 pub struct Group {
     pub op: GroupOp,
     pub exprs: Vec<Expr>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
+}
+
+// `@name`, with the string or block that directly follows it as the argument:
+// `@std`, `@import "io.zen"`, `@{ ... }`
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Directive {
+    pub name: String,
+    pub args: Option<Box<Expr>>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct InterpolatedString {
+    // string literals and the interpolated expressions, in order
+    pub parts: Vec<Expr>,
+    pub span: Span,
+    #[serde(skip)]
+    pub id: NodeId,
 }
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum Expr {
     // Atom
-    Atom(Atom),             // Identifier, Literal, EndOfFile
+    Atom(Atom, Span, #[serde(skip)] NodeId), // Identifier, Literal, EndOfFile
     // Unary
     Unary(Unary),           // ...`a`
     // Binary
     Binary(Binary),         // `a` = `b`
+    Comparison(Comparison), // `a` < `b` < `c`
     // Ternary
     Ternary(Ternary),       // `a` ? `b` : `c`  or  `a` ( `b` ) { `c` }
     // Grouping
     Group(Group),           // ( ... ) or { ... }
-}
\ No newline at end of file
+    // String interpolation
+    InterpolatedString(InterpolatedString), // "a \(b) c" or "a ${b} c"
+    // Compiler directive
+    Directive(Directive),   // @import "file.zen"
+    // Placeholder for something that failed to parse
+    Error(Span, #[serde(skip)] NodeId),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Atom(_, span, _) => *span,
+            Expr::Unary(unary) => unary.span,
+            Expr::Binary(binary) => binary.span,
+            Expr::Comparison(comparison) => comparison.span,
+            Expr::Ternary(ternary) => ternary.span,
+            Expr::Group(group) => group.span,
+            Expr::InterpolatedString(string) => string.span,
+            Expr::Directive(directive) => directive.span,
+            Expr::Error(span, _) => *span,
+        }
+    }
+
+    pub fn id(&self) -> NodeId {
+        match self {
+            Expr::Atom(_, _, id) => *id,
+            Expr::Unary(unary) => unary.id,
+            Expr::Binary(binary) => binary.id,
+            Expr::Comparison(comparison) => comparison.id,
+            Expr::Ternary(ternary) => ternary.id,
+            Expr::Group(group) => group.id,
+            Expr::InterpolatedString(string) => string.id,
+            Expr::Directive(directive) => directive.id,
+            Expr::Error(_, id) => *id,
+        }
+    }
+}
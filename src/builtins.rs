@@ -0,0 +1,82 @@
+// The names a program can use without declaring them, shared by name
+// resolution, the type checker and the interpreter so they agree on what is
+// defined.
+
+/// Types and functions that are not written in Zen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Int,
+    Float,
+    Bool,
+    Char,
+    String,
+    Type,
+    Fn,
+    Enum,
+    If,
+    While,
+    Return,
+    Exit,
+    Write,
+    WriteLine,
+    ReadLine,
+}
+
+/// Whether `name` is defined without being declared in the program.
+pub fn is_builtin(name: &str) -> bool {
+    name == "io" || name == "std" || Builtin::from_name(name).is_some()
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Some(match name {
+            "Int" => Builtin::Int,
+            "Float" => Builtin::Float,
+            "Bool" => Builtin::Bool,
+            "Char" => Builtin::Char,
+            "String" => Builtin::String,
+            "Type" => Builtin::Type,
+            "Fn" | "Function" => Builtin::Fn,
+            "Enum" => Builtin::Enum,
+            "if" => Builtin::If,
+            "while" => Builtin::While,
+            "return" => Builtin::Return,
+            "exit" => Builtin::Exit,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Int => "Int",
+            Builtin::Float => "Float",
+            Builtin::Bool => "Bool",
+            Builtin::Char => "Char",
+            Builtin::String => "String",
+            Builtin::Type => "Type",
+            Builtin::Fn => "Fn",
+            Builtin::Enum => "Enum",
+            Builtin::If => "if",
+            Builtin::While => "while",
+            Builtin::Return => "return",
+            Builtin::Exit => "exit",
+            Builtin::Write => "write",
+            Builtin::WriteLine => "writeLine",
+            Builtin::ReadLine => "readLine",
+        }
+    }
+
+    pub fn is_type(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Int
+                | Builtin::Float
+                | Builtin::Bool
+                | Builtin::Char
+                | Builtin::String
+                | Builtin::Type
+                | Builtin::Fn
+                | Builtin::Enum
+        )
+    }
+}
@@ -1,85 +1,56 @@
+use inkwell::attributes::{Attribute, AttributeLoc};
 use inkwell::builder::{Builder, BuilderError};
 use inkwell::context::Context;
 use inkwell::execution_engine::JitFunction;
-use inkwell::module::{Linkage, Module};
-use inkwell::passes::PassManager;
-use inkwell::targets::Target;
-use inkwell::types::{BasicMetadataTypeEnum, BasicTypeEnum};
+use inkwell::module::Module;
+use inkwell::passes::{PassManager, PassManagerBuilder};
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType};
 use inkwell::values::{
-    AggregateValueEnum, AnyValueEnum, BasicMetadataValueEnum, BasicValue, BasicValueEnum,
-    FloatValue, FunctionValue, PointerValue,
+    BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue,
 };
-use inkwell::{AddressSpace, FloatPredicate, OptimizationLevel};
-use std::convert::Into;
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate, OptimizationLevel};
 
-use crate::ast::{
-    Atom, Binary, BinaryOp, Expr, Group, GroupOp, Literal, Ternary, TernaryOp, Unary, UnaryOp,
-};
-use crate::parser;
+use crate::ast::{BinaryOp, NumberSuffix, UnaryOp};
+use crate::diagnostics::Diagnostic;
+use crate::ir::{self, Callee, Node, Symbol};
+use crate::typeck::{self, RecordType, Type, VariantKind};
 
 use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Debug, Clone)]
-pub enum SymbolKind {
-    Function,
-    Variable,
-    Type,
-    FieldDef,
-}
-
-#[derive(Debug, Clone)]
-pub struct Symbol<'ctx> {
-    name: String,
-    ptr: PointerValue<'ctx>,
-    kind: SymbolKind,
-}
-
-#[derive(Debug)]
-struct SymbolTable<'ctx> {
-    parent: Option<&'ctx SymbolTable<'ctx>>,
-    symbols: HashMap<String, Symbol<'ctx>>,
-}
-
-impl<'ctx> SymbolTable<'ctx> {
-    fn new() -> Self {
-        Self {
-            parent: None,
-            symbols: HashMap::new(),
-        }
-    }
-
-    fn with_parent(parent: &'ctx SymbolTable<'ctx>) -> Self {
-        Self {
-            parent: Some(parent),
-            symbols: HashMap::new(),
-        }
-    }
-
-    fn insert(&mut self, name: String, value: Symbol<'ctx>) {
-        self.symbols.insert(name, value);
-    }
-
-    fn get(&self, name: &str) -> Option<Symbol> {
-        self.symbols
-            .get(name)
-            .cloned()
-            .or_else(|| self.parent?.get(name))
-    }
-}
+// the JIT's entry point, and where `exit` leaves its status for it
+const JIT_MAIN: &str = "zen.jit_main";
+const EXIT_BUF: &str = "zen.exit_buf";
+const EXIT_STATUS: &str = "zen.exit_status";
 
 #[derive(Debug)]
 pub struct CodeGen<'a, 'ctx> {
     pub context: &'ctx Context,
     pub builder: &'a Builder<'ctx>,
     pub module: &'a Module<'ctx>,
-    symbol_table: SymbolTable<'ctx>,
+    // symbol id -> the slot the variable lives in and the type it holds
+    variables: HashMap<usize, (PointerValue<'ctx>, Type)>,
+    // record types declared by name, `%Person = type { ptr, i32 }`
+    structs: HashMap<String, StructType<'ctx>>,
+    // function name -> its signature, for coercing arguments
+    signatures: HashMap<String, typeck::FunctionType>,
+    function: Option<FunctionValue<'ctx>>,
+    ret: Type,
+    // `exit` can't end the process when it's the host's, in the JIT it jumps
+    // back to `zen.jit_main` instead
+    jit: bool,
 }
 
 #[derive(Debug)]
 pub enum CodeGenError {
-    UnexpectedExpr(Expr),
-    UnexpectedEOF,
+    // what the LLVM backend can't compile yet, for the message
+    Unsupported(String),
     BuilderError(BuilderError),
+    // setting up the target or writing the output failed
+    Target(String),
 }
 impl From<BuilderError> for CodeGenError {
     fn from(error: BuilderError) -> Self {
@@ -87,229 +58,1173 @@ impl From<BuilderError> for CodeGenError {
     }
 }
 
+impl From<&CodeGenError> for Diagnostic {
+    fn from(error: &CodeGenError) -> Self {
+        match error {
+            CodeGenError::Unsupported(what) => {
+                Diagnostic::error(format!("the LLVM backend can't compile {} yet", what))
+                    .with_note("`zen run` can still run it with the interpreter")
+            }
+            CodeGenError::BuilderError(error) => {
+                Diagnostic::error(format!("LLVM builder error: {:?}", error))
+            }
+            CodeGenError::Target(message) => Diagnostic::error(message.clone()),
+        }
+    }
+}
+
+fn unsupported<T>(what: impl Into<String>) -> Result<T, CodeGenError> {
+    Err(CodeGenError::Unsupported(what.into()))
+}
+
+// the program's `main` can't take the name the C runtime calls
+fn llvm_name(name: &str) -> String {
+    match name {
+        "main" => "zen.main".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn is_signed(ty: &Type) -> bool {
+    match ty {
+        Type::Int(Some(suffix)) => suffix.is_signed(),
+        _ => true,
+    }
+}
+
 impl<'a, 'ctx> CodeGen<'a, 'ctx> {
     fn new(context: &'ctx Context, builder: &'a Builder<'ctx>, module: &'a Module<'ctx>) -> Self {
-        let symbol_table = SymbolTable::new();
         Self {
             context,
             builder,
             module,
-            symbol_table,
+            variables: HashMap::new(),
+            structs: HashMap::new(),
+            signatures: HashMap::new(),
+            function: None,
+            ret: Type::Void,
+            jit: false,
         }
     }
 
-    fn gen_literal(&mut self, literal: &Literal) -> Result<BasicValueEnum, CodeGenError> {
-        // create and store the values in symbol table
-        // get the ptr value
-        Ok(match literal {
-            // Other Literal variants ...
-            Literal::IntLiteral(value) => self
+    // integers without a suffix are i64, chars are unicode scalar values
+    fn llvm_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>, CodeGenError> {
+        Ok(match ty {
+            Type::Int(suffix) => self
                 .context
-                .i64_type()
-                .const_int(*value as u64, false)
+                .custom_width_int_type(suffix.map_or(64, |suffix| suffix.bits()))
                 .into(),
-            Literal::FloatLiteral(value) => {
-                self.context.f64_type().const_float(*value as f64).into()
-            }
-            Literal::BoolLiteral(value) => self
-                .context
-                .bool_type()
-                .const_int(*value as u64, false)
-                .into(),
-            Literal::CharLiteral(value) => self
+            Type::Float(Some(NumberSuffix::F32)) => self.context.f32_type().into(),
+            Type::Float(_) => self.context.f64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::Char => self.context.i32_type().into(),
+            Type::String => self
                 .context
                 .i8_type()
-                .const_int(*value as u64, false)
+                .ptr_type(AddressSpace::default())
                 .into(),
-            Literal::OctalLiteral(value) => self
-                .context
-                .i32_type()
-                .const_int(*value as u64, false)
-                .into(),
-            Literal::HexLiteral(value) => self
+            // records and enums live on the heap and are passed around as
+            // pointers to them, like functions
+            Type::Record(_) | Type::Enum(_) | Type::Function(_) => self
                 .context
                 .i8_type()
-                .const_int(*value as u64, false)
-                .into(),
-            Literal::BinaryLiteral(value) => self
-                .context
-                .i32_type()
-                .const_int(*value as u64, false)
+                .ptr_type(AddressSpace::default())
                 .into(),
-            Literal::StringLiteral(value) => {
-                let i8_type = self.context.i8_type();
-                let str_type = i8_type.array_type(value.len() as u32);
+            ty => return unsupported(format!("values of type `{}`", ty)),
+        })
+    }
 
-                let string = self.context.const_string(value.as_bytes(), false);
+    // `None` for functions that don't return anything
+    fn return_type(&self, ty: &Type) -> Result<Option<BasicTypeEnum<'ctx>>, CodeGenError> {
+        match ty {
+            Type::Void | Type::Unknown => Ok(None),
+            ty => self.llvm_type(ty).map(Some),
+        }
+    }
 
-                let global = self.module.add_global(str_type, None, "str");
-                global.set_initializer(&string);
-                global.as_pointer_value().into()
+    fn fn_type(&self, function: &typeck::FunctionType) -> Result<FunctionType<'ctx>, CodeGenError> {
+        let mut params: Vec<BasicMetadataTypeEnum> = Vec::new();
+        for (name, ty) in &function.params {
+            match ty {
+                Type::Unknown => {
+                    return unsupported(format!("the parameter `{}` without a type", name))
+                }
+                ty => params.push(self.llvm_type(ty)?.into()),
             }
+        }
+        Ok(match self.return_type(&function.ret)? {
+            Some(ret) => ret.fn_type(&params, false),
+            None => self.context.void_type().fn_type(&params, false),
         })
     }
 
-    fn gen_identifier(&mut self, identifier: &str) -> Result<BasicValueEnum, CodeGenError> {
-        match self.symbol_table.get(identifier) {
-            Some(symbol) => Ok(symbol.ptr.into()),
-            None => Err(CodeGenError::UnexpectedExpr(Expr::Atom(Atom::Identifier(
-                identifier.to_string(),
-            )))),
+    fn gen_int(&self, value: i128, ty: &Type) -> Result<IntValue<'ctx>, CodeGenError> {
+        let int_type = self.llvm_type(ty)?.into_int_type();
+        Ok(match int_type.get_bit_width() {
+            128 => int_type.const_int_arbitrary_precision(&[value as u64, (value >> 64) as u64]),
+            _ => int_type.const_int(value as u64, value < 0),
+        })
+    }
+
+    fn variable(&self, symbol: &Symbol) -> Result<(PointerValue<'ctx>, Type), CodeGenError> {
+        match self.variables.get(&symbol.id) {
+            Some(variable) => Ok(variable.clone()),
+            None => unsupported(format!("`{}`", symbol.name)),
         }
     }
 
-    fn gen_atom(&mut self, atom: &Atom) -> Result<BasicValueEnum, CodeGenError> {
-        match atom {
-            Atom::Literal(literal) => self.gen_literal(literal),
-            Atom::Identifier(identifier) => self.gen_identifier(identifier),
-            Atom::EndOfFile => todo!(),
+    // a named record has its declared layout, an anonymous one is a literal
+    // struct of its fields in order
+    fn struct_type(&self, record: &RecordType) -> Result<StructType<'ctx>, CodeGenError> {
+        if let Some(struct_type) = record.name.as_ref().and_then(|name| self.structs.get(name)) {
+            return Ok(*struct_type);
         }
+        let fields = record
+            .fields
+            .iter()
+            .map(|field| self.llvm_type(&field.ty))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.context.struct_type(&fields, false))
     }
 
-    fn gen_assignment(&mut self, expr: &Binary) -> Result<(), CodeGenError> {
-        match expr {
-            Binary { op, left, right } => match *op {
-
-
-                BinaryOp::Assignment => {
-                    match &**left {
-                        Expr::Atom(Atom::Identifier(ident)) => {
-                            Ok(())
-                        }
-                        Expr::Group(Group {
-                            op: GroupOp::AssignmentBlock,
-                            exprs,
-                        }) => {
-                            // Spread
-                            // { ident, ident... } : expr
-                            todo!("Handle spread")
-                        }
-                        Expr::Binary(Binary {
-                            op: BinaryOp::Accessor,
-                            left,
-                            right,
-                        }) => {
-                            // Field accessor
-                            // ident.ident : expr
-                            todo!("Handle field accessor")
-                        }
-                        _ => Err(CodeGenError::UnexpectedExpr(*left.clone()))?,
-                    }
-                }
-                _ => Err(CodeGenError::UnexpectedExpr(Expr::Binary(expr.clone()))),
-            },
+    // fields are laid out in declaration order, spread fields where the
+    // spread is, so every `Person` has the same layout
+    fn gen_types(&mut self, types: &[ir::TypeDecl]) -> Result<(), CodeGenError> {
+        for decl in types {
+            let struct_type = self.context.opaque_struct_type(&decl.name);
+            self.structs.insert(decl.name.clone(), struct_type);
+        }
+        for decl in types {
+            let fields = decl
+                .fields
+                .iter()
+                .map(|field| self.llvm_type(&field.ty))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.structs[&decl.name].set_body(&fields, false);
         }
+        Ok(())
     }
-    fn gen_unary(&mut self, expr: &Unary) -> Result<BasicValueEnum, CodeGenError> {
-        match expr {
-            Unary { op, expr } => match op {
-                UnaryOp::SpreadExpr => {
-                    // Generate your BasicValueEnum here
-                    // For example:
-                    let value = self.context.i32_type().const_int(0, false).into();
-                    Ok(value)
-                }
-            },
+
+    fn field_pointer(
+        &mut self,
+        record: &Node,
+        field: &str,
+    ) -> Result<(PointerValue<'ctx>, Type), CodeGenError> {
+        let record_type = match record.ty() {
+            Type::Record(record_type) => record_type,
+            ty => return unsupported(format!("the fields of `{}`", ty)),
+        };
+        let index = match record_type.fields.iter().position(|f| f.name == field) {
+            Some(index) => index,
+            None => return unsupported(format!("the field `{}`", field)),
+        };
+        let struct_type = self.struct_type(&record_type)?;
+        let object = self.gen_value(record)?.into_pointer_value();
+        let pointer = self
+            .builder
+            .build_struct_gep(struct_type, object, index as u32, field)?;
+        Ok((pointer, record_type.fields[index].ty.clone()))
+    }
+
+    // a new record on the heap, so it can outlive the function making it
+    fn gen_construct(
+        &mut self,
+        ty: &Type,
+        fields: &[(String, Node)],
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let record = match ty {
+            Type::Record(record) => record,
+            ty => return unsupported(format!("building a `{}`", ty)),
+        };
+        let struct_type = self.struct_type(record)?;
+        let object = self.builder.build_malloc(struct_type, "record")?;
+        for (index, field) in record.fields.iter().enumerate() {
+            let value = match fields.iter().find(|(name, _)| *name == field.name) {
+                Some((_, value)) => value,
+                None => return unsupported(format!("a record without `{}`", field.name)),
+            };
+            let value = self.gen_coerced(value, &field.ty)?;
+            let pointer =
+                self.builder
+                    .build_struct_gep(struct_type, object, index as u32, &field.name)?;
+            self.builder.build_store(pointer, value)?;
         }
+        Ok(object.into())
     }
 
-    fn gen_binary(&mut self, expr: &Binary) -> Result<(), CodeGenError> {
-        match expr {
-            Binary { op, left, right } => match op {
-                BinaryOp::Assignment => self.gen_assignment(expr),
-                BinaryOp::Accessor => todo!("Accessor"),
-                BinaryOp::FieldDef => todo!("FieldDef"),
-                BinaryOp::TypeDef => todo!("TypeDef"),
-                BinaryOp::Invoke => todo!("Invoke"),
-            },
+    // every enum is `{ i32, [2 x i64] }`, the index of the variant and room
+    // for whatever it carries
+    fn enum_type(&self) -> StructType<'ctx> {
+        let payload = self.context.i64_type().array_type(2);
+        self.context
+            .struct_type(&[self.context.i32_type().into(), payload.into()], false)
+    }
+
+    fn gen_variant(
+        &mut self,
+        ty: &Type,
+        tag: usize,
+        payload: Option<&Node>,
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let enumeration = match ty {
+            Type::Enum(enumeration) => enumeration,
+            ty => return unsupported(format!("building a `{}`", ty)),
+        };
+        let enum_type = self.enum_type();
+        let object = self.builder.build_malloc(enum_type, "enum")?;
+        let pointer = self.builder.build_struct_gep(enum_type, object, 0, "tag")?;
+        let tag_value = self.context.i32_type().const_int(tag as u64, false);
+        self.builder.build_store(pointer, tag_value)?;
+        if let Some(payload) = payload {
+            let payload_ty = match &enumeration.variants[tag].kind {
+                VariantKind::Value(ty) | VariantKind::Payload(ty) => ty.clone(),
+                VariantKind::Plain => payload.ty(),
+            };
+            let value = self.gen_coerced(payload, &payload_ty)?;
+            let pointer = self
+                .builder
+                .build_struct_gep(enum_type, object, 1, "payload")?;
+            self.builder.build_store(pointer, value)?;
         }
+        Ok(object.into())
     }
 
-    fn gen_group(&mut self, group: &Group) -> Result<(), CodeGenError> {
-        match group {
-            Group { op, exprs } => match op {
-                GroupOp::AssignmentBlock => {
-                    for expr in exprs {
-                        match expr {
-                            Expr::Binary(binary) => {
-                                println!("About to call gen_assignment");
-                                self.gen_assignment(binary)?
-                            }
-                            _ => {
-                                println!("About to return UnexpectedExpr error");
-                                return Err(CodeGenError::UnexpectedExpr(expr.clone()));
-                            }
-                        }
-                    }
-                    Ok(())
+    // a literal without a suffix takes the width of the sized number it is
+    // used with, `age + 1` with `age: Int.I32` adds two i32s.
+    // a record fits wherever its fields do, when the fields are laid out
+    // differently it is copied into the layout `to` expects
+    fn gen_coerced(
+        &mut self,
+        node: &Node,
+        to: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let value = self.gen_value(node)?;
+        let (from, to) = match (node.ty(), to) {
+            (Type::Record(from), Type::Record(to)) => (from, to),
+            (from @ Type::Int(_), Type::Int(_)) if from != *to => {
+                let int_type = self.llvm_type(to)?.into_int_type();
+                let value = self.builder.build_int_cast_sign_flag(
+                    value.into_int_value(),
+                    int_type,
+                    is_signed(&from),
+                    "int",
+                )?;
+                return Ok(value.into());
+            }
+            (from @ Type::Float(_), Type::Float(_)) if from != *to => {
+                let float_type = self.llvm_type(to)?.into_float_type();
+                let value =
+                    self.builder
+                        .build_float_cast(value.into_float_value(), float_type, "float")?;
+                return Ok(value.into());
+            }
+            _ => return Ok(value),
+        };
+        let same_layout = from
+            .fields
+            .iter()
+            .map(|field| &field.name)
+            .eq(to.fields.iter().map(|field| &field.name));
+        if same_layout {
+            return Ok(value);
+        }
+        let (from_type, to_type) = (self.struct_type(&from)?, self.struct_type(to)?);
+        let object = self.builder.build_malloc(to_type, "record")?;
+        for (index, field) in to.fields.iter().enumerate() {
+            let from_index = match from.fields.iter().position(|f| f.name == field.name) {
+                Some(from_index) => from_index,
+                None => return unsupported(format!("a record without `{}`", field.name)),
+            };
+            let source = self.builder.build_struct_gep(
+                from_type,
+                value.into_pointer_value(),
+                from_index as u32,
+                &field.name,
+            )?;
+            let field_value =
+                self.builder
+                    .build_load(self.llvm_type(&field.ty)?, source, &field.name)?;
+            let target =
+                self.builder
+                    .build_struct_gep(to_type, object, index as u32, &field.name)?;
+            self.builder.build_store(target, field_value)?;
+        }
+        Ok(object.into())
+    }
+
+    // the globals start zeroed, `zen.init` gives them their values
+    fn gen_globals(&mut self, globals: &[(Symbol, Type)]) -> Result<(), CodeGenError> {
+        for (symbol, ty) in globals {
+            let llvm_type = self.llvm_type(ty)?;
+            let global = self.module.add_global(llvm_type, None, &symbol.name);
+            global.set_initializer(&llvm_type.const_zero());
+            self.variables
+                .insert(symbol.id, (global.as_pointer_value(), ty.clone()));
+        }
+        Ok(())
+    }
+
+    // declared before any body is generated, so calls can come before the callee
+    fn declare_function(
+        &mut self,
+        function: &ir::FnDecl,
+    ) -> Result<FunctionValue<'ctx>, CodeGenError> {
+        let signature = typeck::FunctionType {
+            params: function
+                .params
+                .iter()
+                .map(|(symbol, ty)| (symbol.name.clone(), ty.clone()))
+                .collect(),
+            ret: function.ret.clone(),
+        };
+        let fn_type = self.fn_type(&signature)?;
+        self.signatures.insert(function.name.clone(), signature);
+        Ok(self
+            .module
+            .add_function(&llvm_name(&function.name), fn_type, None))
+    }
+
+    fn gen_function(&mut self, function: &ir::FnDecl) -> Result<FunctionValue<'ctx>, CodeGenError> {
+        let llvm_function = self
+            .module
+            .get_function(&llvm_name(&function.name))
+            .unwrap();
+        let entry = self.context.append_basic_block(llvm_function, "entry");
+        self.builder.position_at_end(entry);
+        self.function = Some(llvm_function);
+        self.ret = function.ret.clone();
+        // every parameter and local gets its slot up front, in the entry block
+        for ((symbol, ty), param) in function.params.iter().zip(llvm_function.get_param_iter()) {
+            param.set_name(&symbol.name);
+            let slot = self
+                .builder
+                .build_alloca(self.llvm_type(ty)?, &symbol.name)?;
+            self.builder.build_store(slot, param)?;
+            self.variables.insert(symbol.id, (slot, ty.clone()));
+        }
+        for (symbol, ty) in &function.locals {
+            let slot = self
+                .builder
+                .build_alloca(self.llvm_type(ty)?, &symbol.name)?;
+            self.variables.insert(symbol.id, (slot, ty.clone()));
+        }
+
+        // what `return:` was given, or else the body's last value
+        let body = match &function.result {
+            Some(result) => Node::Block(
+                vec![
+                    function.body.clone(),
+                    Node::Var(result.clone(), function.ret.clone()),
+                ],
+                function.ret.clone(),
+            ),
+            None => function.body.clone(),
+        };
+        match self.return_type(&function.ret)? {
+            Some(_) => {
+                let value = self.gen_coerced(&body, &function.ret)?;
+                if !self.terminated() {
+                    self.builder.build_return(Some(&value))?;
                 }
-                GroupOp::StatementBlock => {
-                    for expr in exprs {
-                        println!("About to call gen_expr");
-                        self.gen_expr(expr)?;
-                    }
-                    Ok(())
+            }
+            None => {
+                self.gen_node(&body)?;
+                if !self.terminated() {
+                    self.builder.build_return(None)?;
                 }
-                _ => {
-                    println!("About to hit todo! macro");
-                    todo!(" other group ops ")
+            }
+        }
+        Ok(llvm_function)
+    }
+
+    // whether the current block already ends, after a `return` or `exit`
+    fn terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .is_some_and(|block| block.get_terminator().is_some())
+    }
+
+    // code after a `return` or `exit` still needs a block to go in
+    fn unreachable_block(&mut self) {
+        let block = self
+            .context
+            .append_basic_block(self.function.unwrap(), "unreachable");
+        self.builder.position_at_end(block);
+    }
+
+    // the C entry point, runs the file's statements and then the program's `main`
+    fn gen_main(&mut self, program: &ir::Program) -> Result<(), CodeGenError> {
+        let i32_type = self.context.i32_type();
+        let main = self
+            .module
+            .add_function("main", i32_type.fn_type(&[], false), None);
+        let entry = self.context.append_basic_block(main, "entry");
+        self.builder.position_at_end(entry);
+        let init = self.module.get_function(ir::INIT).unwrap();
+        self.builder.build_call(init, &[], "")?;
+        let status = match &program.main {
+            Some(name) => {
+                let function = self.module.get_function(&llvm_name(name)).unwrap();
+                if function.count_params() > 0 {
+                    return unsupported("a `main` with parameters");
                 }
-            },
+                let result = self.builder.build_call(function, &[], "status")?;
+                match result.try_as_basic_value().left() {
+                    Some(BasicValueEnum::IntValue(status)) => {
+                        self.builder.build_int_cast(status, i32_type, "status")?
+                    }
+                    _ => i32_type.const_zero(),
+                }
+            }
+            None => i32_type.const_zero(),
+        };
+        self.builder.build_return(Some(&status))?;
+        Ok(())
+    }
+
+    // the jump buffer `zen.jit_main` sets and the status `exit` stores before jumping
+    fn exit_globals(&self) -> (PointerValue<'ctx>, PointerValue<'ctx>) {
+        let buf = self.module.get_global(EXIT_BUF).unwrap_or_else(|| {
+            // larger than a `jmp_buf` on any of the hosts
+            let ty = self.context.i8_type().array_type(512);
+            let global = self.module.add_global(ty, None, EXIT_BUF);
+            global.set_initializer(&ty.const_zero());
+            global.set_alignment(16);
+            global
+        });
+        let status = self.module.get_global(EXIT_STATUS).unwrap_or_else(|| {
+            let ty = self.context.i32_type();
+            let global = self.module.add_global(ty, None, EXIT_STATUS);
+            global.set_initializer(&ty.const_zero());
+            global
+        });
+        (buf.as_pointer_value(), status.as_pointer_value())
+    }
+
+    // calls `main` after `_setjmp`, an `exit` jumps back and its status is returned
+    fn gen_jit_main(&mut self) -> Result<(), CodeGenError> {
+        let i32_type = self.context.i32_type();
+        let (buf, slot) = self.exit_globals();
+        let jit_main = self
+            .module
+            .add_function(JIT_MAIN, i32_type.fn_type(&[], false), None);
+        let entry = self.context.append_basic_block(jit_main, "entry");
+        let run = self.context.append_basic_block(jit_main, "run");
+        let exited = self.context.append_basic_block(jit_main, "exited");
+        self.builder.position_at_end(entry);
+
+        let returns_twice = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("returns_twice"), 0);
+        let setjmp = self.module.get_function("_setjmp").unwrap_or_else(|| {
+            let fn_type = i32_type.fn_type(&[buf.get_type().into()], false);
+            let setjmp = self.module.add_function("_setjmp", fn_type, None);
+            setjmp.add_attribute(AttributeLoc::Function, returns_twice);
+            setjmp
+        });
+        let call = self.builder.build_call(setjmp, &[buf.into()], "jumped")?;
+        call.add_attribute(AttributeLoc::Function, returns_twice);
+        let jumped = call.try_as_basic_value().left().unwrap().into_int_value();
+        let is_exit = self.builder.build_int_compare(
+            IntPredicate::NE,
+            jumped,
+            i32_type.const_zero(),
+            "is_exit",
+        )?;
+        self.builder
+            .build_conditional_branch(is_exit, exited, run)?;
+
+        self.builder.position_at_end(run);
+        let main = self.module.get_function("main").unwrap();
+        let status = self.builder.build_call(main, &[], "status")?;
+        let status = status.try_as_basic_value().left().unwrap();
+        self.builder.build_return(Some(&status))?;
+
+        self.builder.position_at_end(exited);
+        let status = self.builder.build_load(i32_type, slot, "status")?;
+        self.builder.build_return(Some(&status))?;
+        Ok(())
+    }
+
+    fn gen_value(&mut self, node: &Node) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        match self.gen_node(node)? {
+            Some(value) => Ok(value),
+            None => unsupported(format!("`{}` as a value", node)),
         }
     }
 
-    fn gen_ternary(&mut self, ternary: &Ternary) -> Result<(), CodeGenError> {
-        match ternary {
-            Ternary {
-                op,
+    // `None` for nodes without a value
+    fn gen_node(&mut self, node: &Node) -> Result<Option<BasicValueEnum<'ctx>>, CodeGenError> {
+        Ok(Some(match node {
+            Node::Int(value, ty) => self.gen_int(*value, ty)?.into(),
+            Node::Float(value, ty) => self
+                .llvm_type(ty)?
+                .into_float_type()
+                .const_float(*value)
+                .into(),
+            Node::Bool(value) => self
+                .context
+                .bool_type()
+                .const_int(*value as u64, false)
+                .into(),
+            Node::Char(value) => self
+                .context
+                .i32_type()
+                .const_int(*value as u64, false)
+                .into(),
+            Node::String(value) => self
+                .builder
+                .build_global_string_ptr(value, "str")?
+                .as_pointer_value()
+                .into(),
+            Node::Var(symbol, _) => {
+                let (slot, ty) = self.variable(symbol)?;
+                self.builder
+                    .build_load(self.llvm_type(&ty)?, slot, &symbol.name)?
+            }
+            Node::Let(symbol, value) | Node::Set(symbol, value) => {
+                let (slot, ty) = self.variable(symbol)?;
+                let value = self.gen_coerced(value, &ty)?;
+                self.builder.build_store(slot, value)?;
+                return Ok(None);
+            }
+            Node::Construct { ty, fields } => self.gen_construct(ty, fields)?,
+            Node::FieldGet { record, field, .. } => {
+                let (pointer, ty) = self.field_pointer(record, field)?;
+                self.builder
+                    .build_load(self.llvm_type(&ty)?, pointer, field)?
+            }
+            Node::SetField {
+                record,
+                field,
+                value,
+            } => {
+                let (pointer, ty) = self.field_pointer(record, field)?;
+                let value = self.gen_coerced(value, &ty)?;
+                self.builder.build_store(pointer, value)?;
+                return Ok(None);
+            }
+            Node::Variant { ty, tag, payload } => self.gen_variant(ty, *tag, payload.as_deref())?,
+            Node::Tag(value, ty) => {
+                let object = self.gen_value(value)?.into_pointer_value();
+                let tag = self
+                    .builder
+                    .build_load(self.context.i32_type(), object, "tag")?
+                    .into_int_value();
+                let int_type = self.llvm_type(ty)?.into_int_type();
+                self.builder.build_int_cast(tag, int_type, "tag")?.into()
+            }
+            Node::Payload(value, ty) => {
+                let object = self.gen_value(value)?.into_pointer_value();
+                let pointer =
+                    self.builder
+                        .build_struct_gep(self.enum_type(), object, 1, "payload")?;
+                self.builder
+                    .build_load(self.llvm_type(ty)?, pointer, "value")?
+            }
+            Node::Unary { op, operand, ty } => self.gen_unary(op, operand, ty)?,
+            Node::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
                 left,
-                middle,
                 right,
-            } => match op {
-                TernaryOp::FuncDefInvoke => todo!("InvokeDefine"),
+                ..
+            } => self.gen_logical(op, left, right)?,
+            Node::Binary {
+                op, left, right, ..
+            } => self.gen_binary(op, left, right)?,
+            Node::Block(nodes, _) => {
+                let mut last = None;
+                for node in nodes {
+                    last = self.gen_node(node)?;
+                }
+                return Ok(last);
+            }
+            Node::FnRef(name, _) => self
+                .module
+                .get_function(&llvm_name(name))
+                .unwrap()
+                .as_global_value()
+                .as_pointer_value()
+                .into(),
+            Node::Call { callee, args, .. } => return self.gen_call(callee, args),
+            Node::If {
+                cond,
+                then,
+                otherwise,
+                ty,
+            } => return self.gen_if(cond, then, otherwise.as_deref(), ty),
+            Node::Loop { cond, body } => {
+                self.gen_loop(cond, body)?;
+                return Ok(None);
+            }
+            Node::Return(value) => {
+                let value = match self.ret.clone() {
+                    Type::Void | Type::Unknown => self.gen_node(value)?,
+                    ret => Some(self.gen_coerced(value, &ret)?),
+                };
+                self.builder
+                    .build_return(value.as_ref().map(|value| value as &dyn BasicValue))?;
+                self.unreachable_block();
+                return Ok(None);
+            }
+            Node::Exit(status) => {
+                let status = self.gen_value(status)?.into_int_value();
+                let i32_type = self.context.i32_type();
+                let status = self.builder.build_int_cast(status, i32_type, "status")?;
+                match self.jit {
+                    true => {
+                        let (buf, slot) = self.exit_globals();
+                        self.builder.build_store(slot, status)?;
+                        let longjmp = self.module.get_function("_longjmp").unwrap_or_else(|| {
+                            let fn_type = self
+                                .context
+                                .void_type()
+                                .fn_type(&[buf.get_type().into(), i32_type.into()], false);
+                            self.module.add_function("_longjmp", fn_type, None)
+                        });
+                        let one = i32_type.const_int(1, false);
+                        self.builder
+                            .build_call(longjmp, &[buf.into(), one.into()], "")?;
+                    }
+                    false => {
+                        let exit = self.module.get_function("exit").unwrap_or_else(|| {
+                            let fn_type =
+                                self.context.void_type().fn_type(&[i32_type.into()], false);
+                            self.module.add_function("exit", fn_type, None)
+                        });
+                        self.builder.build_call(exit, &[status.into()], "")?;
+                    }
+                }
+                self.builder.build_unreachable()?;
+                self.unreachable_block();
+                return Ok(None);
+            }
+            Node::Void => return Ok(None),
+            node => return unsupported(format!("`{}`", node)),
+        }))
+    }
+
+    fn gen_unary(
+        &mut self,
+        op: &UnaryOp,
+        operand: &Node,
+        ty: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let value = self.gen_value(operand)?;
+        Ok(match (op, ty) {
+            (UnaryOp::Negate, Type::Float(_)) => self
+                .builder
+                .build_float_neg(value.into_float_value(), "neg")?
+                .into(),
+            (UnaryOp::Negate, Type::Int(_)) => self
+                .builder
+                .build_int_neg(value.into_int_value(), "neg")?
+                .into(),
+            (UnaryOp::Not, Type::Bool) | (UnaryOp::BitwiseNot, Type::Int(_)) => self
+                .builder
+                .build_not(value.into_int_value(), "not")?
+                .into(),
+            (op, ty) => return unsupported(format!("`{}` on `{}`", op.symbol(), ty)),
+        })
+    }
+
+    // the right side is only evaluated when the left doesn't decide it
+    fn gen_logical(
+        &mut self,
+        op: &BinaryOp,
+        left: &Node,
+        right: &Node,
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let function = self.function.unwrap();
+        let left = self.gen_value(left)?.into_int_value();
+        let left_block = self.builder.get_insert_block().unwrap();
+        let right_block = self.context.append_basic_block(function, "rhs");
+        let merge_block = self.context.append_basic_block(function, "merge");
+        match op {
+            BinaryOp::And => {
+                self.builder
+                    .build_conditional_branch(left, right_block, merge_block)?
+            }
+            _ => self
+                .builder
+                .build_conditional_branch(left, merge_block, right_block)?,
+        };
+
+        self.builder.position_at_end(right_block);
+        let right = self.gen_value(right)?;
+        let right_block = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block)?;
+
+        self.builder.position_at_end(merge_block);
+        let phi = self
+            .builder
+            .build_phi(self.context.bool_type(), "logical")?;
+        phi.add_incoming(&[
+            (&left as &dyn BasicValue, left_block),
+            (&right, right_block),
+        ]);
+        Ok(phi.as_basic_value())
+    }
+
+    fn gen_binary(
+        &mut self,
+        op: &BinaryOp,
+        left: &Node,
+        right: &Node,
+    ) -> Result<BasicValueEnum<'ctx>, CodeGenError> {
+        let ty = match (left.ty(), right.ty()) {
+            (Type::Int(None), ty @ Type::Int(Some(_))) => ty,
+            (Type::Float(None), ty @ Type::Float(Some(_))) => ty,
+            (ty, _) => ty,
+        };
+        let (lhs, rhs) = (self.gen_coerced(left, &ty)?, self.gen_coerced(right, &ty)?);
+        let builder = self.builder;
+        if let Type::Float(_) = ty {
+            let (l, r) = (lhs.into_float_value(), rhs.into_float_value());
+            let predicate = match op {
+                BinaryOp::Add => return Ok(builder.build_float_add(l, r, "add")?.into()),
+                BinaryOp::Subtract => return Ok(builder.build_float_sub(l, r, "sub")?.into()),
+                BinaryOp::Multiply => return Ok(builder.build_float_mul(l, r, "mul")?.into()),
+                BinaryOp::Divide => return Ok(builder.build_float_div(l, r, "div")?.into()),
+                BinaryOp::Modulo => return Ok(builder.build_float_rem(l, r, "rem")?.into()),
+                BinaryOp::Equal => FloatPredicate::OEQ,
+                BinaryOp::NotEqual => FloatPredicate::UNE,
+                BinaryOp::LessThan => FloatPredicate::OLT,
+                BinaryOp::LessThanOrEqual => FloatPredicate::OLE,
+                BinaryOp::GreaterThan => FloatPredicate::OGT,
+                BinaryOp::GreaterThanOrEqual => FloatPredicate::OGE,
+                op => return unsupported(format!("`{}` on `{}`", op.symbol(), ty)),
+            };
+            return Ok(builder.build_float_compare(predicate, l, r, "cmp")?.into());
+        }
+        if let (Type::String, BinaryOp::Equal | BinaryOp::NotEqual) = (&ty, op) {
+            let strcmp = self.module.get_function("strcmp").unwrap_or_else(|| {
+                let ptr_type = self.context.i8_type().ptr_type(AddressSpace::default());
+                let fn_type = self
+                    .context
+                    .i32_type()
+                    .fn_type(&[ptr_type.into(), ptr_type.into()], false);
+                self.module.add_function("strcmp", fn_type, None)
+            });
+            let order = builder
+                .build_call(strcmp, &[lhs.into(), rhs.into()], "strcmp")?
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_int_value();
+            let predicate = match op {
+                BinaryOp::Equal => IntPredicate::EQ,
+                _ => IntPredicate::NE,
+            };
+            let zero = self.context.i32_type().const_zero();
+            return Ok(builder
+                .build_int_compare(predicate, order, zero, "cmp")?
+                .into());
+        }
+        if !matches!(ty, Type::Int(_) | Type::Bool | Type::Char) {
+            return unsupported(format!("`{}` on `{}`", op.symbol(), ty));
+        }
+        let signed = is_signed(&ty);
+        let (l, r) = (lhs.into_int_value(), rhs.into_int_value());
+        let predicate = match (op, signed) {
+            (BinaryOp::Add, _) => return Ok(builder.build_int_add(l, r, "add")?.into()),
+            (BinaryOp::Subtract, _) => return Ok(builder.build_int_sub(l, r, "sub")?.into()),
+            (BinaryOp::Multiply, _) => return Ok(builder.build_int_mul(l, r, "mul")?.into()),
+            (BinaryOp::Divide, true) => {
+                return Ok(builder.build_int_signed_div(l, r, "div")?.into())
+            }
+            (BinaryOp::Divide, false) => {
+                return Ok(builder.build_int_unsigned_div(l, r, "div")?.into())
+            }
+            (BinaryOp::Modulo, true) => {
+                return Ok(builder.build_int_signed_rem(l, r, "rem")?.into())
+            }
+            (BinaryOp::Modulo, false) => {
+                return Ok(builder.build_int_unsigned_rem(l, r, "rem")?.into())
+            }
+            (BinaryOp::BitwiseAnd, _) => return Ok(builder.build_and(l, r, "and")?.into()),
+            (BinaryOp::BitwiseOr, _) => return Ok(builder.build_or(l, r, "or")?.into()),
+            (BinaryOp::BitwiseXor, _) => return Ok(builder.build_xor(l, r, "xor")?.into()),
+            (BinaryOp::ShiftLeft, _) => return Ok(builder.build_left_shift(l, r, "shl")?.into()),
+            (BinaryOp::ShiftRight, signed) => {
+                return Ok(builder.build_right_shift(l, r, signed, "shr")?.into())
+            }
+            (BinaryOp::Equal, _) => IntPredicate::EQ,
+            (BinaryOp::NotEqual, _) => IntPredicate::NE,
+            (BinaryOp::LessThan, true) => IntPredicate::SLT,
+            (BinaryOp::LessThan, false) => IntPredicate::ULT,
+            (BinaryOp::LessThanOrEqual, true) => IntPredicate::SLE,
+            (BinaryOp::LessThanOrEqual, false) => IntPredicate::ULE,
+            (BinaryOp::GreaterThan, true) => IntPredicate::SGT,
+            (BinaryOp::GreaterThan, false) => IntPredicate::UGT,
+            (BinaryOp::GreaterThanOrEqual, true) => IntPredicate::SGE,
+            (BinaryOp::GreaterThanOrEqual, false) => IntPredicate::UGE,
+            (op, _) => return unsupported(format!("`{}` on `{}`", op.symbol(), ty)),
+        };
+        Ok(builder.build_int_compare(predicate, l, r, "cmp")?.into())
+    }
+
+    // arguments are already in parameter order, with the receiver bound to `self`
+    fn gen_call(
+        &mut self,
+        callee: &Callee,
+        args: &[Node],
+    ) -> Result<Option<BasicValueEnum<'ctx>>, CodeGenError> {
+        let signature = match callee {
+            Callee::Fn(name) => self.signatures[name].clone(),
+            Callee::Value(value) => match value.ty() {
+                Type::Function(function) => *function,
+                ty => return unsupported(format!("calling `{}`", ty)),
             },
+        };
+        let mut values: Vec<BasicMetadataValueEnum> = Vec::new();
+        for (arg, (_, ty)) in args.iter().zip(&signature.params) {
+            values.push(self.gen_coerced(arg, ty)?.into());
         }
+        let call = match callee {
+            Callee::Fn(name) => {
+                let function = self.module.get_function(&llvm_name(name)).unwrap();
+                self.builder.build_call(function, &values, "call")?
+            }
+            Callee::Value(value) => {
+                let fn_type = self.fn_type(&signature)?;
+                let pointer = self.gen_value(value)?.into_pointer_value();
+                self.builder
+                    .build_indirect_call(fn_type, pointer, &values, "call")?
+            }
+        };
+        Ok(call.try_as_basic_value().left())
     }
 
-    fn gen_expr(&mut self, expr: &Expr) -> Result<BasicValueEnum, CodeGenError> {
-        match expr {
-            Expr::Atom(atom) => self.gen_atom(atom),
-            Expr::Unary(unary) => self.gen_unary(unary),
-            Expr::Binary(binary) => {
-                self.gen_binary(binary)?;
-                Ok(self.context.i32_type().const_int(0, false).into()) // replace with actual value
+    // a value only when both branches give one, through a phi in the merge block
+    fn gen_if(
+        &mut self,
+        cond: &Node,
+        then: &Node,
+        otherwise: Option<&Node>,
+        ty: &Type,
+    ) -> Result<Option<BasicValueEnum<'ctx>>, CodeGenError> {
+        let function = self.function.unwrap();
+        let cond = self.gen_value(cond)?.into_int_value();
+        let then_block = self.context.append_basic_block(function, "then");
+        let else_block = self.context.append_basic_block(function, "else");
+        let merge_block = self.context.append_basic_block(function, "merge");
+        self.builder
+            .build_conditional_branch(cond, then_block, else_block)?;
+
+        let mut incoming = Vec::new();
+        for (block, branch) in [(then_block, Some(then)), (else_block, otherwise)] {
+            self.builder.position_at_end(block);
+            let value = match branch {
+                Some(branch) => self.gen_node(branch)?,
+                None => None,
+            };
+            if self.terminated() {
+                continue;
             }
-            Expr::Ternary(ternary) => {
-                self.gen_ternary(ternary)?;
-                Ok(self.context.i32_type().const_int(0, false).into()) // replace with actual value
+            match value {
+                // the branch ended in a `return` or `exit`
+                None if branch.is_some() && !matches!(ty, Type::Void | Type::Unknown) => {
+                    self.builder.build_unreachable()?;
+                }
+                value => {
+                    incoming.push((value, self.builder.get_insert_block().unwrap()));
+                    self.builder.build_unconditional_branch(merge_block)?;
+                }
             }
-            Expr::Group(group) => {
-                self.gen_group(group)?;
-                Ok(self.context.i32_type().const_int(0, false).into()) // replace with actual value
+        }
+
+        self.builder.position_at_end(merge_block);
+        if *ty == Type::Void || incoming.is_empty() {
+            return Ok(None);
+        }
+        let phi = self.builder.build_phi(self.llvm_type(ty)?, "if")?;
+        for (value, block) in &incoming {
+            match value {
+                Some(value) => phi.add_incoming(&[(value as &dyn BasicValue, *block)]),
+                None => return unsupported("an `if` without a value in each branch"),
             }
-            _ => Err(CodeGenError::UnexpectedExpr(expr.clone())),
         }
+        Ok(Some(phi.as_basic_value()))
     }
 
-    pub fn compile(&mut self, expr: &Expr) -> Result<(), CodeGenError> {
-        self.gen_expr(expr)?;
+    fn gen_loop(&mut self, cond: &Node, body: &Node) -> Result<(), CodeGenError> {
+        let function = self.function.unwrap();
+        let cond_block = self.context.append_basic_block(function, "while");
+        let body_block = self.context.append_basic_block(function, "body");
+        let after_block = self.context.append_basic_block(function, "after");
+        self.builder.build_unconditional_branch(cond_block)?;
+
+        self.builder.position_at_end(cond_block);
+        let cond = self.gen_value(cond)?.into_int_value();
+        self.builder
+            .build_conditional_branch(cond, body_block, after_block)?;
+
+        self.builder.position_at_end(body_block);
+        self.gen_node(body)?;
+        if !self.terminated() {
+            self.builder.build_unconditional_branch(cond_block)?;
+        }
+        self.builder.position_at_end(after_block);
         Ok(())
     }
+
+    pub fn compile(&mut self, program: &ir::Program) -> Result<(), CodeGenError> {
+        self.gen_types(&program.types)?;
+        self.gen_globals(&program.globals)?;
+        for function in program.functions.iter().chain([&program.init]) {
+            self.declare_function(function)?;
+        }
+        for function in program.functions.iter().chain([&program.init]) {
+            self.gen_function(function)?;
+        }
+        self.gen_main(program)
+    }
 }
 
-#[test]
-pub fn test_codeGen() {
-    // let path = "src/tests/test.zen";
-    // let file = std::fs::read_to_string(path).unwrap();
-    let file = "a: 10".to_string();
-    let mut parser = parser::Parser::new(&file);
-    let ast = parser.parse().unwrap();
-    println!("{:#?}", ast);
+/// Compiles the program into a new module and returns the module's LLVM IR.
+pub fn emit_ir(program: &ir::Program, module_name: &str) -> Result<String, CodeGenError> {
+    let context = Context::create();
+    let module = context.create_module(module_name);
+    let builder = context.create_builder();
+    let mut codegen = CodeGen::new(&context, &builder, &module);
+    codegen.compile(program)?;
+    Ok(module.print_to_string().to_string())
+}
 
+/// The files `zen build` can write besides the executable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Artifact {
+    Ll,
+    Bc,
+    Object,
+}
+
+// `-O0` to `-O3`
+fn optimization_level(level: u8) -> OptimizationLevel {
+    match level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    }
+}
+
+fn host_machine(level: OptimizationLevel) -> Result<TargetMachine, CodeGenError> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(CodeGenError::Target)?;
+    let triple = TargetMachine::get_default_triple();
+    let target =
+        Target::from_triple(&triple).map_err(|error| CodeGenError::Target(error.to_string()))?;
+    // position independent, since the system linker makes PIE executables by default
+    target
+        .create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| {
+            CodeGenError::Target(format!(
+                "LLVM has no target machine for {}",
+                triple.as_str().to_string_lossy()
+            ))
+        })
+}
+
+/// Compiles the program for the host, optimises it at `opt_level` and writes
+/// each artifact to its path.
+pub fn write_artifacts(
+    program: &ir::Program,
+    module_name: &str,
+    opt_level: u8,
+    artifacts: &[(Artifact, &Path)],
+) -> Result<(), CodeGenError> {
     let context = Context::create();
-    let module = context.create_module("test");
+    let module = context.create_module(module_name);
     let builder = context.create_builder();
     let mut codegen = CodeGen::new(&context, &builder, &module);
-    codegen.compile(&ast);
-    println!("{:#?}", module.print_to_string().to_string());
+    codegen.compile(program)?;
+
+    let level = optimization_level(opt_level);
+    let machine = host_machine(level)?;
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    let passes = PassManager::create(());
+    let pass_builder = PassManagerBuilder::create();
+    pass_builder.set_optimization_level(level);
+    pass_builder.populate_module_pass_manager(&passes);
+    passes.run_on(&module);
+
+    let failed = |path: &Path, error: &dyn std::fmt::Display| {
+        CodeGenError::Target(format!("failed to write {}: {}", path.display(), error))
+    };
+    for (artifact, path) in artifacts {
+        match artifact {
+            Artifact::Ll => module
+                .print_to_file(path)
+                .map_err(|error| failed(path, &error.to_string()))?,
+            Artifact::Bc => {
+                if !module.write_bitcode_to_path(path) {
+                    return Err(failed(path, &"LLVM could not write the bitcode"));
+                }
+            }
+            Artifact::Object => machine
+                .write_to_file(&module, FileType::Object, path)
+                .map_err(|error| failed(path, &error.to_string()))?,
+        }
+    }
+    Ok(())
+}
+
+/// Compiles the program in memory and runs its `main` with the JIT, returning
+/// the exit status. An `exit` returns here instead of ending the process.
+pub fn run_jit(program: &ir::Program, module_name: &str) -> Result<i32, CodeGenError> {
+    let context = Context::create();
+    let module = context.create_module(module_name);
+    let builder = context.create_builder();
+    let mut codegen = CodeGen::new(&context, &builder, &module);
+    codegen.jit = true;
+    codegen.compile(program)?;
+    codegen.gen_jit_main()?;
+
+    let engine = module
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|error| CodeGenError::Target(error.to_string()))?;
+    // `zen.jit_main` wraps the C entry point `gen_main` makes, `i32 ()`
+    let main: JitFunction<unsafe extern "C" fn() -> i32> = unsafe { engine.get_function(JIT_MAIN) }
+        .map_err(|error| CodeGenError::Target(format!("{:?}", error)))?;
+    Ok(unsafe { main.call() })
+}
+
+#[test]
+pub fn test_codeGen() {
+    let program = ir::lower_source("a: 10\nb: a * 2 < 30 && true").unwrap();
+    let ir = emit_ir(&program, "test").unwrap();
+    assert!(ir.contains("@a = global i64 0"));
+    assert!(ir.contains("store i64 10"));
+    assert!(ir.contains("define void @zen.init()"));
+}
+
+#[test]
+fn test_codegen_functions() {
+    let program = ir::lower_source(
+        "\
+add: Fn { args: { a: Int, b: Int } return: Int fn: { a + b } }
+twice: Fn { args: { self: Int } body: { self.add(b: self) } }
+main: Fn {
+    return: Int
+    body: {
+        n: 3
+        if (n.twice() > 5) { return(1) }
+        0
+    }
+}",
+    )
+    .unwrap();
+    let ir = emit_ir(&program, "test").unwrap();
+    assert!(ir.contains("define i64 @add(i64 %a, i64 %b)"));
+    assert!(ir.contains("call i64 @add("));
+    assert!(ir.contains("define i64 @zen.main()"));
+    assert!(ir.contains("define i32 @main()"));
+}
+
+#[test]
+fn test_codegen_structs() {
+    let program = ir::lower_source(
+        "\
+Named: { name: String }
+Person: { ...Named, age: Int.I32 }
+grow: Fn { args: { self: Person } body: { self.age: self.age + 1 } }
+jane: Person(name: \"Jane\", age: 30)
+jane.grow()",
+    )
+    .unwrap();
+    let ir = emit_ir(&program, "test").unwrap();
+    assert!(ir.contains("%Person = type { ptr, i32 }"));
+    assert!(ir.contains("getelementptr inbounds %Person"));
+    assert!(ir.contains("call ptr @malloc"));
+}
+
+#[test]
+fn test_codegen_enums() {
+    let program = ir::lower_source(
+        "\
+Rgb: Enum { RED, GREEN }
+main: Fn { return: Int body: { Int(Rgb.parse(\"GREEN\")) } }",
+    )
+    .unwrap();
+    let ir = emit_ir(&program, "test").unwrap();
+    assert!(ir.contains("call i32 @strcmp"));
+    assert!(ir.contains("store i32 1"));
+    assert_eq!(run_jit(&program, "test").unwrap(), 1);
+
+    let program = ir::lower_source(
+        "\
+Position: Enum { Line: Int, Column: Int }
+main: Fn {
+    return: Int
+    body: {
+        position: Position.Column(4)
+        if (position != Position.Line(4) && position == Position.Column(4)) {
+            return(position.value + 1)
+        }
+        0
+    }
+}",
+    )
+    .unwrap();
+    assert_eq!(run_jit(&program, "test").unwrap(), 5);
+}
+
+#[test]
+fn test_write_artifacts() {
+    let program = ir::lower_source("main: Fn { return: Int body: { 2 * 3 } }").unwrap();
+    let dir = std::env::temp_dir();
+    let (ll, object) = (dir.join("zen-test-build.ll"), dir.join("zen-test-build.o"));
+    let artifacts = [
+        (Artifact::Ll, ll.as_path()),
+        (Artifact::Object, object.as_path()),
+    ];
+    write_artifacts(&program, "test", 2, &artifacts).unwrap();
+    let ir = std::fs::read_to_string(&ll).unwrap();
+    assert!(ir.contains("target triple"));
+    // folded by the optimiser
+    assert!(ir.contains("ret i64 6"));
+    assert!(std::fs::metadata(&object).unwrap().len() > 0);
+}
+
+#[test]
+fn test_run_jit() {
+    let program = ir::lower_source(
+        "\
+Point: { x: Int, y: Int }
+sum: Fn { args: { self: Point } body: { self.x + self.y } }
+main: Fn {
+    return: Int
+    body: {
+        point: Point(x: 2, y: 5)
+        point.sum()
+    }
+}",
+    )
+    .unwrap();
+    assert_eq!(run_jit(&program, "test").unwrap(), 7);
+
+    // `exit` returns its status instead of ending the test run
+    let program = ir::lower_source(
+        "\
+stop: Fn { args: { status: Int } body: { exit(status) } }
+main: Fn {
+    return: Int
+    body: {
+        stop(3)
+        0
+    }
+}",
+    )
+    .unwrap();
+    assert_eq!(run_jit(&program, "test").unwrap(), 3);
+    assert!(!emit_ir(&program, "test").unwrap().contains("_longjmp"));
 }
@@ -0,0 +1,212 @@
+// Parses every .zen file under std/ and examples/ and compares the AST with
+// the JSON snapshot in src/tests/snapshots/, so grammar changes show up as diffs.
+// Run with ZEN_UPDATE_SNAPSHOTS=1 to write the snapshots after an intended change.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::parser::Parser;
+
+const CORPUS: &[&str] = &["std", "examples"];
+const SNAPSHOTS: &str = "src/tests/snapshots";
+
+// files the parser can't handle yet, with the first error it reports
+const EXPECTED_FAILURES: &[(&str, &str)] = &[
+    ("examples/dijkstra.zen", "18:18: invalid character '['"),
+    (
+        "examples/dockerApi/buid.zen",
+        "12:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/dockerApi/src/api/api_container.zen",
+        "12:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/dockerApi/src/docker.zen",
+        "6:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/dockerApi/src/models/model_container_stats.zen",
+        "9:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    ("examples/forum/src/routes.zen", "29:42: a param block without a name is not supported yet"),
+    ("examples/forum/src/store.zen", "6:34: invalid character ';'"),
+    (
+        "examples/misc/src/callbacks.zen",
+        "6:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/misc/src/dateframe.zen",
+        "12:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/misc/src/example.zen",
+        "9:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/misc/src/example2.zen",
+        "23:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/misc/src/fibonacci.zen",
+        "6:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "examples/misc/src/main.zen",
+        "7:6: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    ("examples/misc/src/minst.zen", "138:23: invalid character '['"),
+    ("examples/misc/src/orm.zen", "92:19: a param block without a name is not supported yet"),
+    ("examples/misc/src/wisper.zen", "30:28: invalid character '['"),
+    (
+        "examples/weby/build.zen",
+        "10:29: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `.`",
+    ),
+    ("std/async.zen", "41:20: invalid character ';'"),
+    ("std/bitops.zen", "13:21: a param block without a name is not supported yet"),
+    (
+        "std/conditionals/loop.zen",
+        "15:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    ("std/data/array.zen", "39:19: invalid character '['"),
+    (
+        "std/data/dataframe.zen",
+        "13:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "std/http.zen",
+        "6:6: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "std/json.zen",
+        "22:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    ("std/science/neuralNetwork.zen", "40:18: invalid character '['"),
+    (
+        "std/testing/benchmark.zen",
+        "9:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    (
+        "std/testing/test.zen",
+        "6:2: expected one of identifier, number literal, string literal, char literal, bool literal, `...`, `{` or `(`, found `:`",
+    ),
+    ("std/types/integer.zen", "28:41: invalid character '`'"),
+];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn zen_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries =
+        std::fs::read_dir(dir).unwrap_or_else(|error| panic!("{}: {}", dir.display(), error));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            zen_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "zen") {
+            files.push(path);
+        }
+    }
+}
+
+// spans would make every snapshot change when a comment is edited
+fn strip_spans(value: &mut Value) {
+    let is_span = |value: &Value| {
+        value
+            .as_object()
+            .is_some_and(|object| object.contains_key("file_id") && object.contains_key("column"))
+    };
+    match value {
+        Value::Object(object) => {
+            object.remove("span");
+            object.values_mut().for_each(strip_spans);
+        }
+        Value::Array(array) => {
+            array.retain(|value| !is_span(value));
+            array.iter_mut().for_each(strip_spans);
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn test_conformance() {
+    let root = root();
+    let update = std::env::var_os("ZEN_UPDATE_SNAPSHOTS").is_some();
+    let mut files = Vec::new();
+    for dir in CORPUS {
+        zen_files(&root.join(dir), &mut files);
+    }
+    files.sort();
+
+    let mut failures = Vec::new();
+    for path in &files {
+        let name = path
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let source = std::fs::read_to_string(path).unwrap();
+        let expected_error = EXPECTED_FAILURES
+            .iter()
+            .find(|(file, _)| *file == name)
+            .map(|(_, error)| *error);
+
+        let ast = match (Parser::new(&source).parse(), expected_error) {
+            (Ok(ast), None) => ast,
+            (Ok(_), Some(_)) => {
+                failures.push(format!(
+                    "{}: parses now, remove it from EXPECTED_FAILURES",
+                    name
+                ));
+                continue;
+            }
+            (Err(errors), Some(expected)) => {
+                if errors[0].to_string() != expected {
+                    failures.push(format!(
+                        "{}: expected `{}`, found `{}`",
+                        name, expected, errors[0]
+                    ));
+                }
+                continue;
+            }
+            (Err(errors), None) => {
+                failures.push(format!("{}:{}", name, errors[0]));
+                continue;
+            }
+        };
+
+        let mut json = serde_json::to_value(&ast).unwrap();
+        strip_spans(&mut json);
+        let json = serde_json::to_string_pretty(&json).unwrap() + "\n";
+        let snapshot = root.join(SNAPSHOTS).join(&name).with_extension("json");
+        if update {
+            std::fs::create_dir_all(snapshot.parent().unwrap()).unwrap();
+            std::fs::write(&snapshot, json).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == json => {}
+            Ok(_) => failures.push(format!("{}: AST differs from {}", name, snapshot.display())),
+            Err(_) => failures.push(format!("{}: no snapshot at {}", name, snapshot.display())),
+        }
+    }
+
+    for (file, _) in EXPECTED_FAILURES {
+        if !files.iter().any(|path| path.ends_with(file)) {
+            failures.push(format!(
+                "{}: listed in EXPECTED_FAILURES but does not exist",
+                file
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} files failed, run with ZEN_UPDATE_SNAPSHOTS=1 if the AST changes are intended:\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,385 @@
+use std::io::{IsTerminal, Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn colour(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// A message about the source, pointing at one or more spans.
+/// Lexer, parser and codegen errors are all turned into these before
+/// they are shown to the user.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or(self.labels.first())
+            .map(|label| label.span)
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+/// Every file that has been loaded, indexed by `Span::file_id`.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> usize {
+        self.files.push(SourceFile {
+            name: name.into(),
+            source: source.into(),
+        });
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file_id: usize) -> Option<&SourceFile> {
+        self.files.get(file_id)
+    }
+
+    /// 1-based line and column of a byte offset.
+    pub fn location(&self, file_id: usize, offset: usize) -> (usize, usize) {
+        let source = match self.get(file_id) {
+            Some(file) => &file.source,
+            None => return (1, 1),
+        };
+        let offset = floor_char_boundary(source, offset);
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    fn line(&self, file_id: usize, line: usize) -> Option<&str> {
+        self.get(file_id)?.source.lines().nth(line.checked_sub(1)?)
+    }
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ErrorFormat {
+    /// rustc style messages with source snippets
+    Human,
+    /// one JSON object per diagnostic, per line
+    Json,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Writes diagnostics to stderr in the requested format.
+pub struct Emitter {
+    format: ErrorFormat,
+    colour: bool,
+}
+
+impl Emitter {
+    pub fn new(format: ErrorFormat) -> Self {
+        let colour = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        Emitter { format, colour }
+    }
+
+    pub fn with_colour(format: ErrorFormat, colour: bool) -> Self {
+        Emitter { format, colour }
+    }
+
+    pub fn emit(&self, diagnostics: &[Diagnostic], sources: &SourceMap) {
+        let mut stderr = std::io::stderr().lock();
+        for diagnostic in diagnostics {
+            let _ = write!(stderr, "{}", self.render(diagnostic, sources));
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+        match self.format {
+            ErrorFormat::Human => self.render_human(diagnostic, sources),
+            ErrorFormat::Json => render_json(diagnostic, sources) + "\n",
+        }
+    }
+
+    fn paint(&self, colour: &str, text: &str) -> String {
+        match self.colour {
+            true => format!("{}{}{}", colour, text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    fn render_human(&self, diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+        let severity = diagnostic.severity;
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(severity.colour(), severity.name()),
+            self.paint(BOLD, &format!(": {}", diagnostic.message)),
+        );
+
+        let primary = match diagnostic.primary_span() {
+            Some(span) if sources.get(span.file_id).is_some() => span,
+            _ => {
+                for note in &diagnostic.notes {
+                    out += &format!("{} note: {}\n", self.paint(BLUE, "="), note);
+                }
+                return out;
+            }
+        };
+
+        // labels in other files are not shown in the snippet
+        let mut labels: Vec<&Label> = diagnostic
+            .labels
+            .iter()
+            .filter(|label| label.span.file_id == primary.file_id)
+            .collect();
+        labels.sort_by_key(|label| (label.span.start, !label.primary));
+
+        let mut lines: Vec<usize> = labels
+            .iter()
+            .map(|label| sources.location(label.span.file_id, label.span.start).0)
+            .collect();
+        lines.dedup();
+        let gutter = lines.iter().max().unwrap_or(&1).to_string().len();
+        let pad = " ".repeat(gutter);
+
+        let file = sources.get(primary.file_id).unwrap();
+        let (line, column) = sources.location(primary.file_id, primary.start);
+        out += &format!(
+            "{}{} {}:{}:{}\n",
+            pad,
+            self.paint(BLUE, "-->"),
+            file.name,
+            line,
+            column
+        );
+        out += &format!("{} {}\n", pad, self.paint(BLUE, "|"));
+
+        let mut previous: Option<usize> = None;
+        for line in lines {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                out += &format!("{}\n", self.paint(BLUE, "..."));
+            }
+            previous = Some(line);
+
+            let text = sources.line(primary.file_id, line).unwrap_or("");
+            out += &format!(
+                "{} {}\n",
+                self.paint(BLUE, &format!("{:>width$} |", line, width = gutter)),
+                text
+            );
+            for label in labels
+                .iter()
+                .filter(|label| sources.location(label.span.file_id, label.span.start).0 == line)
+            {
+                let (_, column) = sources.location(label.span.file_id, label.span.start);
+                // underline to the end of the span, or the end of the line if it spans several
+                let width = text
+                    .chars()
+                    .skip(column - 1)
+                    .take(label.span.len().max(1))
+                    .count()
+                    .max(1);
+                let (marker, colour) = match label.primary {
+                    true => ("^", severity.colour()),
+                    false => ("-", BLUE),
+                };
+                let underline = format!("{} {}", marker.repeat(width), label.message);
+                out += &format!(
+                    "{} {}{}\n",
+                    self.paint(BLUE, &format!("{} |", pad)),
+                    " ".repeat(column - 1),
+                    self.paint(colour, underline.trim_end())
+                );
+            }
+        }
+
+        if !diagnostic.notes.is_empty() {
+            out += &format!("{} {}\n", pad, self.paint(BLUE, "|"));
+        }
+        for note in &diagnostic.notes {
+            out += &format!("{} {} note: {}\n", pad, self.paint(BLUE, "="), note);
+        }
+        out
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLabel<'a> {
+    file: Option<&'a str>,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    message: &'a str,
+    primary: bool,
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    severity: &'a str,
+    message: &'a str,
+    labels: Vec<JsonLabel<'a>>,
+    notes: &'a [String],
+}
+
+fn render_json(diagnostic: &Diagnostic, sources: &SourceMap) -> String {
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let span = label.span;
+            let (line, column) = sources.location(span.file_id, span.start);
+            let (end_line, end_column) = sources.location(span.file_id, span.end);
+            JsonLabel {
+                file: sources.get(span.file_id).map(|file| file.name.as_str()),
+                start: span.start,
+                end: span.end,
+                line,
+                column,
+                end_line,
+                end_column,
+                message: &label.message,
+                primary: label.primary,
+            }
+        })
+        .collect();
+    let json = JsonDiagnostic {
+        severity: diagnostic.severity.name(),
+        message: &diagnostic.message,
+        labels,
+        notes: &diagnostic.notes,
+    };
+    serde_json::to_string(&json).unwrap_or_default()
+}
+
+#[test]
+fn test_render_human() {
+    let mut sources = SourceMap::new();
+    let source = "Person: {\n    name: String,\n    age: Int,\n}\nperson: Person(name: 1)\n";
+    let file_id = sources.add("main.zen", source);
+    let diagnostic = Diagnostic::error("mismatched types")
+        .with_primary(Span::new(file_id, 65, 66, 5, 22), "expected `String`, found `Int`")
+        .with_secondary(Span::new(file_id, 14, 26, 2, 5), "field declared here")
+        .with_note("fields are matched by name");
+
+    let rendered = Emitter::with_colour(ErrorFormat::Human, false).render(&diagnostic, &sources);
+    let expected = "error: mismatched types
+ --> main.zen:5:22
+  |
+2 |     name: String,
+  |     ------------ field declared here
+...
+5 | person: Person(name: 1)
+  |                      ^ expected `String`, found `Int`
+  |
+  = note: fields are matched by name
+";
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn test_render_json() {
+    let mut sources = SourceMap::new();
+    let file_id = sources.add("main.zen", "a: }\n");
+    let diagnostic = Diagnostic::error("unexpected `}`").with_primary(Span::new(file_id, 3, 4, 1, 4), "");
+    let rendered = Emitter::with_colour(ErrorFormat::Json, false).render(&diagnostic, &sources);
+    let json: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["labels"][0]["file"], "main.zen");
+    assert_eq!(json["labels"][0]["column"], 4);
+    assert_eq!(json["labels"][0]["end_column"], 5);
+}
@@ -0,0 +1,532 @@
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use clap::ValueEnum;
+
+use crate::ast::Expr;
+use crate::codegen::{self, Artifact};
+use crate::diagnostics::{Diagnostic, Emitter, SourceMap};
+use crate::interp::{self, Interpreter};
+use crate::ir;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::resolve::{self, Resolution};
+use crate::sexpr;
+use crate::typeck::{self, Types};
+use crate::token::{SpannedToken, Token};
+use crate::wasm;
+use crate::wat_codegen::{self, watCodeGen};
+
+// exit status when the program could not be read or compiled
+pub const EXIT_COMPILE_ERROR: u8 = 1;
+// exit status when the program stops with a runtime error, the same as a rust panic
+pub const EXIT_RUNTIME_ERROR: u8 = 101;
+
+// the status a program exits with, one outside 0..=255 can't be passed on as is
+fn exit_status(status: i32) -> ExitCode {
+    ExitCode::from(u8::try_from(status).unwrap_or(EXIT_RUNTIME_ERROR))
+}
+
+pub enum Input {
+    File(PathBuf),
+    Source(String),
+}
+
+/// Steps of the pipeline, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Stage {
+    Lex,
+    Parse,
+    Lower,
+    Codegen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum AstFormat {
+    /// Rust debug output, with spans
+    Debug,
+    Json,
+    /// one S-expression per item
+    Sexpr,
+}
+
+/// What `zen build` compiles for.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Target {
+    /// the host, through LLVM
+    Native,
+    /// a WebAssembly module
+    #[value(alias = "wasm")]
+    Wasm32,
+}
+
+/// What `zen build` writes, next to each other with the output's name.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Emit {
+    /// a native executable, linked by the system linker
+    Exe,
+    /// an object file, `.o`
+    Obj,
+    /// LLVM IR, `.ll`
+    Ll,
+    /// LLVM bitcode, `.bc`
+    Bc,
+    /// a WebAssembly module, `.wasm`
+    Wasm,
+    /// WebAssembly text, `.wat`
+    Wat,
+}
+
+impl Emit {
+    fn target(&self) -> Target {
+        match self {
+            Emit::Wasm | Emit::Wat => Target::Wasm32,
+            _ => Target::Native,
+        }
+    }
+}
+
+pub struct BuildOptions {
+    pub target: Target,
+    pub output: Option<PathBuf>,
+    pub opt_level: u8,
+    pub emit: Vec<Emit>,
+}
+
+/// The intermediate output the debug subcommands print.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dump {
+    Tokens,
+    Ast(AstFormat),
+    Ir,
+    Wat,
+}
+
+impl Dump {
+    fn stage(&self) -> Stage {
+        match self {
+            Dump::Tokens => Stage::Lex,
+            Dump::Ast(_) => Stage::Parse,
+            Dump::Ir | Dump::Wat => Stage::Codegen,
+        }
+    }
+}
+
+pub fn format_token(spanned: &SpannedToken) -> String {
+    format!("{}:{} {:?}", spanned.span.line, spanned.span.column, spanned.token)
+}
+
+/// How `zen run` runs the program.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Runner {
+    Interpreter,
+    /// compiled in memory by LLVM and run natively
+    Jit,
+    /// compiled to WebAssembly and run by the embedded runtime
+    Wasm,
+}
+
+/// Takes source through the compiler pipeline for the CLI commands,
+/// reporting anything that goes wrong as diagnostics.
+pub struct Driver {
+    pub sources: SourceMap,
+    emitter: Emitter,
+}
+
+impl Driver {
+    pub fn new(emitter: Emitter) -> Self {
+        Driver {
+            sources: SourceMap::new(),
+            emitter,
+        }
+    }
+
+    pub fn emit(&self, diagnostics: &[Diagnostic]) {
+        self.emitter.emit(diagnostics, &self.sources);
+    }
+
+    /// Adds the input to the source map and returns its file id.
+    pub fn load(&mut self, input: &Input) -> Result<usize, Diagnostic> {
+        match input {
+            Input::File(path) => {
+                let source = std::fs::read_to_string(path).map_err(|error| {
+                    Diagnostic::error(format!("failed to read {}: {}", path.display(), error))
+                })?;
+                Ok(self.sources.add(path.display().to_string(), source))
+            }
+            Input::Source(source) => Ok(self.sources.add("<string>", source.as_str())),
+        }
+    }
+
+    // whitespace is left out, newlines are kept since they separate items
+    pub fn tokens(&self, file_id: usize) -> Vec<SpannedToken> {
+        let source = &self.sources.get(file_id).unwrap().source;
+        Lexer::with_file_id(source, file_id)
+            .filter(|spanned| !matches!(spanned.token, Token::WhiteSpace(_)))
+            .collect()
+    }
+
+    pub fn parse(&self, file_id: usize) -> Result<Expr, Vec<Diagnostic>> {
+        let source = &self.sources.get(file_id).unwrap().source;
+        Parser::with_file_id(source, file_id)
+            .parse()
+            .map_err(|errors| errors.iter().map(Diagnostic::from).collect())
+    }
+
+    /// `zen run`, the exit code is the program's own exit status once it gets to run.
+    pub fn run(&mut self, input: &Input, runner: Runner) -> ExitCode {
+        let file_id = match self.load(input) {
+            Ok(file_id) => file_id,
+            Err(diagnostic) => {
+                self.emit(&[diagnostic]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        let ast = match self.parse(file_id) {
+            Ok(ast) => ast,
+            Err(diagnostics) => {
+                self.emit(&diagnostics);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        match runner {
+            Runner::Jit => return self.execute_jit(&ast, file_id),
+            Runner::Wasm => return self.execute_wasm(&ast),
+            Runner::Interpreter => {}
+        }
+        if self.check(&ast).is_none() {
+            return ExitCode::from(EXIT_COMPILE_ERROR);
+        }
+        self.execute(&ast)
+    }
+
+    fn execute_jit(&self, ast: &Expr, file_id: usize) -> ExitCode {
+        let program = match self.lower(ast) {
+            Some(program) => program,
+            None => return ExitCode::from(EXIT_COMPILE_ERROR),
+        };
+        let name = &self.sources.get(file_id).unwrap().name;
+        match codegen::run_jit(&program, name) {
+            Ok(status) => exit_status(status),
+            Err(error) => {
+                self.emit(&[Diagnostic::from(&error)]);
+                ExitCode::from(EXIT_COMPILE_ERROR)
+            }
+        }
+    }
+
+    fn execute_wasm(&self, ast: &Expr) -> ExitCode {
+        let program = match self.lower(ast) {
+            Some(program) => program,
+            None => return ExitCode::from(EXIT_COMPILE_ERROR),
+        };
+        let module = match wat_codegen::wasm(&program) {
+            Ok(module) => module,
+            Err(error) => {
+                self.emit(&[Diagnostic::from(&error)]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        match wasm::run(&module, std::io::stdin(), std::io::stdout()) {
+            Ok((status, _)) => exit_status(status),
+            Err(error) => {
+                self.emit(&[Diagnostic::error(format!(
+                    "running the WebAssembly module failed: {}",
+                    error
+                ))]);
+                ExitCode::from(EXIT_RUNTIME_ERROR)
+            }
+        }
+    }
+
+    /// Reports what name resolution finds, `None` if any of it is an error
+    /// rather than a warning.
+    pub fn resolve(&self, ast: &Expr) -> Option<Resolution> {
+        let resolution = resolve::resolve(ast);
+        let diagnostics: Vec<Diagnostic> = resolution.errors.iter().map(Diagnostic::from).collect();
+        self.emit(&diagnostics);
+        match resolution.errors.iter().all(|error| error.is_warning()) {
+            true => Some(resolution),
+            false => None,
+        }
+    }
+
+    /// Resolves names and checks types, `None` if either finds an error.
+    pub fn check(&self, ast: &Expr) -> Option<(Resolution, Types)> {
+        let resolution = self.resolve(ast)?;
+        let types = typeck::check(ast, &resolution);
+        let diagnostics: Vec<Diagnostic> = types.errors.iter().map(Diagnostic::from).collect();
+        self.emit(&diagnostics);
+        match types.errors.is_empty() {
+            true => Some((resolution, types)),
+            false => None,
+        }
+    }
+
+    /// Checks the program and lowers it to the IR the backends compile.
+    pub fn lower(&self, ast: &Expr) -> Option<ir::Program> {
+        let (resolution, types) = self.check(ast)?;
+        match ir::lower(ast, &resolution, &types) {
+            Ok(program) => Some(program),
+            Err(error) => {
+                self.emit(&[Diagnostic::from(&error)]);
+                None
+            }
+        }
+    }
+
+    /// `zen tokens`, `zen ast`, `zen ir` and `zen wat`. With `stop_after` set to an
+    /// earlier stage, the pipeline stops there and prints that stage's output instead.
+    pub fn dump(&mut self, input: &Input, dump: Dump, stop_after: Option<Stage>) -> ExitCode {
+        let file_id = match self.load(input) {
+            Ok(file_id) => file_id,
+            Err(diagnostic) => {
+                self.emit(&[diagnostic]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        let stage = stop_after.map_or(dump.stage(), |stop_after| stop_after.min(dump.stage()));
+
+        if stage == Stage::Lex {
+            let tokens = self.tokens(file_id);
+            let errors: Vec<Diagnostic> = tokens
+                .iter()
+                .filter_map(|spanned| match &spanned.token {
+                    Token::Error(error) => Some(
+                        Diagnostic::error(error.to_string())
+                            .with_primary(spanned.span, "invalid token"),
+                    ),
+                    _ => None,
+                })
+                .collect();
+            for spanned in &tokens {
+                println!("{}", format_token(spanned));
+            }
+            self.emit(&errors);
+            return match errors.is_empty() {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::from(EXIT_COMPILE_ERROR),
+            };
+        }
+
+        let ast = match self.parse(file_id) {
+            Ok(ast) => ast,
+            Err(diagnostics) => {
+                self.emit(&diagnostics);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        if stage == Stage::Parse {
+            let format = match dump {
+                Dump::Ast(format) => format,
+                _ => AstFormat::Debug,
+            };
+            match format {
+                AstFormat::Debug => println!("{:#?}", ast),
+                AstFormat::Json => println!("{}", serde_json::to_string_pretty(&ast).unwrap()),
+                AstFormat::Sexpr => println!("{}", sexpr::program(&ast)),
+            }
+            return ExitCode::SUCCESS;
+        }
+
+        let program = match self.lower(&ast) {
+            Some(program) => program,
+            None => return ExitCode::from(EXIT_COMPILE_ERROR),
+        };
+        if stage == Stage::Lower {
+            println!("{}", program);
+            return ExitCode::SUCCESS;
+        }
+        match dump {
+            Dump::Wat => match watCodeGen::new().generate(&program) {
+                Ok(wat) => println!("{}", wat),
+                Err(error) => {
+                    self.emit(&[Diagnostic::from(&error)]);
+                    return ExitCode::from(EXIT_COMPILE_ERROR);
+                }
+            },
+            _ => {
+                let name = self.sources.get(file_id).unwrap().name.clone();
+                match codegen::emit_ir(&program, &name) {
+                    Ok(ir) => println!("{}", ir),
+                    Err(error) => {
+                        self.emit(&[Diagnostic::from(&error)]);
+                        return ExitCode::from(EXIT_COMPILE_ERROR);
+                    }
+                }
+            }
+        }
+        ExitCode::SUCCESS
+    }
+
+    /// `zen build`, compiles the program for the host or for WebAssembly.
+    pub fn build(&mut self, input: &Input, options: &BuildOptions) -> ExitCode {
+        let file_id = match self.load(input) {
+            Ok(file_id) => file_id,
+            Err(diagnostic) => {
+                self.emit(&[diagnostic]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        let ast = match self.parse(file_id) {
+            Ok(ast) => ast,
+            Err(diagnostics) => {
+                self.emit(&diagnostics);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        };
+        let program = match self.lower(&ast) {
+            Some(program) => program,
+            None => return ExitCode::from(EXIT_COMPILE_ERROR),
+        };
+
+        // `hello.zen` builds `hello` in the current directory
+        let output = options.output.clone().unwrap_or_else(|| match input {
+            Input::File(path) => PathBuf::from(path.file_stem().unwrap_or(path.as_os_str())),
+            Input::Source(_) => PathBuf::from("main"),
+        });
+        let emit = match (options.emit.is_empty(), options.target) {
+            (true, Target::Native) => vec![Emit::Exe],
+            (true, Target::Wasm32) => vec![Emit::Wasm],
+            (false, _) => options.emit.clone(),
+        };
+        if let Some(other) = emit.iter().find(|emit| emit.target() != options.target) {
+            let name = other.to_possible_value().unwrap().get_name().to_string();
+            self.emit(&[Diagnostic::error(format!(
+                "`--emit {}` can't be written for the {} target",
+                name,
+                options.target.to_possible_value().unwrap().get_name()
+            ))]);
+            return ExitCode::from(EXIT_COMPILE_ERROR);
+        }
+        if options.target == Target::Wasm32 {
+            return self.build_wasm(&program, &output, &emit);
+        }
+        let object = match emit.contains(&Emit::Obj) {
+            true => output.with_extension("o"),
+            // only needed until it is linked
+            false => std::env::temp_dir().join(format!("zen-{}.o", std::process::id())),
+        };
+        let ll = output.with_extension("ll");
+        let bc = output.with_extension("bc");
+        let mut artifacts = Vec::new();
+        if emit.contains(&Emit::Ll) {
+            artifacts.push((Artifact::Ll, ll.as_path()));
+        }
+        if emit.contains(&Emit::Bc) {
+            artifacts.push((Artifact::Bc, bc.as_path()));
+        }
+        if emit.contains(&Emit::Obj) || emit.contains(&Emit::Exe) {
+            artifacts.push((Artifact::Object, object.as_path()));
+        }
+
+        let name = self.sources.get(file_id).unwrap().name.clone();
+        let written = codegen::write_artifacts(&program, &name, options.opt_level, &artifacts);
+        if let Err(error) = written {
+            self.emit(&[Diagnostic::from(&error)]);
+            return ExitCode::from(EXIT_COMPILE_ERROR);
+        }
+        if emit.contains(&Emit::Exe) {
+            let linked = link(&object, &output);
+            if !emit.contains(&Emit::Obj) {
+                let _ = std::fs::remove_file(&object);
+            }
+            if let Err(diagnostic) = linked {
+                self.emit(&[diagnostic]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        }
+        ExitCode::SUCCESS
+    }
+
+    fn build_wasm(&self, program: &ir::Program, output: &Path, emit: &[Emit]) -> ExitCode {
+        let mut files = Vec::new();
+        let generated = match emit.contains(&Emit::Wat) {
+            true => watCodeGen::new()
+                .generate(program)
+                .map(|wat| files.push((output.with_extension("wat"), wat.into_bytes()))),
+            false => Ok(()),
+        };
+        let generated = generated.and_then(|_| match emit.contains(&Emit::Wasm) {
+            true => wat_codegen::wasm(program)
+                .map(|wasm| files.push((output.with_extension("wasm"), wasm))),
+            false => Ok(()),
+        });
+        if let Err(error) = generated {
+            self.emit(&[Diagnostic::from(&error)]);
+            return ExitCode::from(EXIT_COMPILE_ERROR);
+        }
+        for (path, contents) in files {
+            if let Err(error) = std::fs::write(&path, contents) {
+                self.emit(&[Diagnostic::error(format!(
+                    "failed to write {}: {}",
+                    path.display(),
+                    error
+                ))]);
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        }
+        ExitCode::SUCCESS
+    }
+
+    pub fn execute(&self, ast: &Expr) -> ExitCode {
+        let mut interpreter = Interpreter::new(BufReader::new(std::io::stdin()), std::io::stdout());
+        match interp::with_stack(|| interpreter.run(ast)) {
+            Ok(status) => exit_status(status),
+            Err(error) => {
+                self.emit(&[Diagnostic::from(&error)]);
+                ExitCode::from(EXIT_RUNTIME_ERROR)
+            }
+        }
+    }
+}
+
+// links with the C compiler, `$CC` or else `cc`, which brings in the C runtime
+fn link(object: &Path, output: &Path) -> Result<(), Diagnostic> {
+    let linker = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&linker)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .output()
+        .map_err(|error| {
+            Diagnostic::error(format!("failed to run the linker `{}`: {}", linker, error))
+        })?;
+    match result.status.success() {
+        true => Ok(()),
+        false => Err(
+            Diagnostic::error(format!("linking {} failed", output.display()))
+                .with_note(String::from_utf8_lossy(&result.stderr).trim().to_string()),
+        ),
+    }
+}
+
+#[test]
+fn test_driver_diagnostics() {
+    use crate::diagnostics::ErrorFormat;
+
+    let mut driver = Driver::new(Emitter::with_colour(ErrorFormat::Human, false));
+    let missing = driver.load(&Input::File(PathBuf::from("does/not/exist.zen")));
+    assert!(missing
+        .unwrap_err()
+        .message
+        .starts_with("failed to read does/not/exist.zen"));
+
+    let file_id = driver
+        .load(&Input::Source("a: Int(1 +)".to_string()))
+        .unwrap();
+    assert_eq!(driver.sources.get(file_id).unwrap().name, "<string>");
+    let diagnostics = driver.parse(file_id).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].primary_span().unwrap().column, 11);
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(exit_status(3), ExitCode::from(3));
+    assert_eq!(exit_status(255), ExitCode::from(255));
+    // these would wrap around to 0 and 255
+    assert_eq!(exit_status(256), ExitCode::from(EXIT_RUNTIME_ERROR));
+    assert_eq!(exit_status(-1), ExitCode::from(EXIT_RUNTIME_ERROR));
+}
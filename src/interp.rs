@@ -0,0 +1,1742 @@
+// A tree-walking interpreter for the AST. It is the reference semantics the
+// LLVM and WASM backends are tested against, and what `zen run` uses until
+// they can compile whole programs.
+//
+// Values are copied on assignment. There is no shadowing: assigning to a name
+// that is already visible updates it, as described in the readme.
+//
+// Every Zen call is a dozen nested rust calls, so programs are run on a thread
+// of their own with a large stack, see `with_stack`. That is also why values
+// share functions through `Arc` rather than `Rc`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use crate::ast::{
+    Atom, Binary, BinaryOp, Comparison, Expr, Group, GroupOp, Literal, NumberSuffix, Ternary,
+    Unary, UnaryOp,
+};
+use crate::builtins::Builtin;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+
+// deep enough for any sensible recursion, shallow enough to fit in STACK_SIZE
+const MAX_CALL_DEPTH: usize = 1000;
+// only the pages that are used are ever committed
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Runs `f` on a thread with enough stack for MAX_CALL_DEPTH calls.
+pub fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the interpreter thread");
+        thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Both types and their instances. A field holding a builtin type has to be
+/// given a value when the type is instantiated, any other field is a default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub type_name: Option<String>,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.fields
+            .iter_mut()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    fn set(&mut self, name: &str, value: Value) {
+        match self.get_mut(name) {
+            Some(field) => *field = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+}
+
+/// `Rgb: Enum { RED, GREEN }`, each variant with its value, a type for a
+/// variant with a payload and `Void` for a plain one.
+#[derive(Debug, PartialEq)]
+pub struct Enum {
+    pub name: Option<String>,
+    pub variants: Vec<(String, Value)>,
+}
+
+impl Enum {
+    fn type_name(&self) -> String {
+        self.name.clone().unwrap_or("Enum".to_string())
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.variants
+            .iter()
+            .position(|(variant, _)| variant == name)
+    }
+}
+
+/// `Rgb.RED` or `Token.Comment("...")`, `value` is the payload or the value of
+/// the variant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub ty: Arc<Enum>,
+    pub index: usize,
+    pub value: Box<Value>,
+}
+
+// a variant declared with a type, `Comment: String`, is given a value when made
+fn is_payload(value: &Value) -> bool {
+    matches!(value, Value::Builtin(builtin) if builtin.is_type()) || matches!(value, Value::Enum(_))
+}
+
+// the variant at `index`, one with a payload has to be given it
+fn variant(
+    ty: &Arc<Enum>,
+    index: usize,
+    payload: Option<Value>,
+    span: Span,
+) -> Result<Value, Control> {
+    let value = match (is_payload(&ty.variants[index].1), payload) {
+        (true, Some(payload)) => payload,
+        (true, None) => return error(RuntimeErrorKind::MissingArgument("value".to_string()), span),
+        (false, _) => ty.variants[index].1.clone(),
+    };
+    Ok(Value::Variant(Variant {
+        ty: ty.clone(),
+        index,
+        value: Box::new(value),
+    }))
+}
+
+/// `name: Fn { args: {...}, return: T, body: {...} }`
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Void,
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Record(Record),
+    Enum(Arc<Enum>),
+    Variant(Variant),
+    Function(Arc<Function>),
+    // the block passed to a call like `if (a) { ... }`
+    Block(Arc<Expr>),
+    Builtin(Builtin),
+}
+
+impl Value {
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Void => "Void".to_string(),
+            Value::Int(_) => "Int".to_string(),
+            Value::Float(_) => "Float".to_string(),
+            Value::Bool(_) => "Bool".to_string(),
+            Value::Char(_) => "Char".to_string(),
+            Value::String(_) => "String".to_string(),
+            Value::Record(record) => record.type_name.clone().unwrap_or("{}".to_string()),
+            Value::Enum(_) => "Type".to_string(),
+            Value::Variant(variant) => variant.ty.type_name(),
+            Value::Function(_) => "Fn".to_string(),
+            Value::Block(_) => "Body".to_string(),
+            Value::Builtin(builtin) if builtin.is_type() => "Type".to_string(),
+            Value::Builtin(_) => "Fn".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Void => write!(f, "void"),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Record(record) => {
+                if let Some(name) = &record.type_name {
+                    write!(f, "{} ", name)?;
+                }
+                if record.fields.is_empty() {
+                    return write!(f, "{{}}");
+                }
+                write!(f, "{{ ")?;
+                for (i, (name, value)) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    // quoted so `Person { name: "John" }` reads like the source
+                    match value {
+                        Value::String(value) => write!(f, "{}: {:?}", name, value)?,
+                        Value::Char(value) => write!(f, "{}: {:?}", name, value)?,
+                        value => write!(f, "{}: {}", name, value)?,
+                    }
+                }
+                write!(f, " }}")
+            }
+            Value::Enum(ty) => write!(f, "{}", ty.type_name()),
+            Value::Variant(variant) => write!(
+                f,
+                "{}.{}",
+                variant.ty.type_name(),
+                variant.ty.variants[variant.index].0
+            ),
+            Value::Function(function) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Block(_) => write!(f, "<body>"),
+            Value::Builtin(builtin) => write!(f, "{}", builtin.name()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    UndefinedName(String),
+    // field, type
+    UnknownField(String, String),
+    MissingField(String, String),
+    // type, variant
+    UnknownVariant(String, String),
+    // operator, left and right types
+    MismatchedOperands(&'static str, String, String),
+    InvalidOperand(&'static str, String),
+    NotCallable(String),
+    NotAType(String),
+    InvalidTarget,
+    UnknownArgument(String),
+    MissingArgument(String),
+    TooManyArguments(usize),
+    ExpectedBool(String),
+    DivisionByZero,
+    Overflow(&'static str),
+    StackOverflow,
+    Unsupported(String),
+}
+
+impl std::fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeErrorKind::UndefinedName(name) => write!(f, "`{}` is not defined", name),
+            RuntimeErrorKind::UnknownField(field, type_name) => {
+                write!(f, "`{}` has no field `{}`", type_name, field)
+            }
+            RuntimeErrorKind::MissingField(field, type_name) => {
+                write!(f, "missing field `{}` for `{}`", field, type_name)
+            }
+            RuntimeErrorKind::UnknownVariant(type_name, variant) => {
+                write!(f, "`{}` has no variant `{}`", type_name, variant)
+            }
+            RuntimeErrorKind::MismatchedOperands(op, left, right) => {
+                write!(f, "cannot apply `{}` to `{}` and `{}`", op, left, right)
+            }
+            RuntimeErrorKind::InvalidOperand(op, operand) => {
+                write!(f, "cannot apply `{}` to `{}`", op, operand)
+            }
+            RuntimeErrorKind::NotCallable(type_name) => {
+                write!(f, "`{}` cannot be called", type_name)
+            }
+            RuntimeErrorKind::NotAType(type_name) => {
+                write!(f, "expected a type before `{{`, found `{}`", type_name)
+            }
+            RuntimeErrorKind::InvalidTarget => write!(f, "cannot assign to this expression"),
+            RuntimeErrorKind::UnknownArgument(name) => write!(f, "unknown argument `{}`", name),
+            RuntimeErrorKind::MissingArgument(name) => write!(f, "missing argument `{}`", name),
+            RuntimeErrorKind::TooManyArguments(expected) => {
+                write!(f, "too many arguments, expected {}", expected)
+            }
+            RuntimeErrorKind::ExpectedBool(found) => {
+                write!(f, "expected a `Bool` condition, found `{}`", found)
+            }
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+            RuntimeErrorKind::Overflow(op) => write!(f, "integer overflow in `{}`", op),
+            RuntimeErrorKind::StackOverflow => {
+                write!(f, "too many nested calls, the limit is {}", MAX_CALL_DEPTH)
+            }
+            RuntimeErrorKind::Unsupported(what) => {
+                write!(f, "{} is not supported by the interpreter yet", what)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> Self {
+        RuntimeError { kind, span }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        Diagnostic::error(error.kind.to_string()).with_primary(error.span, "")
+    }
+}
+
+// positional and named arguments of a call
+type Arguments = (Vec<Value>, Vec<(String, Value)>);
+
+/// Why evaluation stopped before the end of an expression.
+#[derive(Debug, PartialEq)]
+pub enum Control {
+    Return(Value),
+    Exit(i32),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Control {
+    fn from(error: RuntimeError) -> Self {
+        Control::Error(error)
+    }
+}
+
+fn error<T>(kind: RuntimeErrorKind, span: Span) -> Result<T, Control> {
+    Err(Control::Error(RuntimeError::new(kind, span)))
+}
+
+// `io.std.writeLine` and `io.println`
+fn io_module() -> Value {
+    let std = Record {
+        type_name: None,
+        fields: vec![
+            ("write".to_string(), Value::Builtin(Builtin::Write)),
+            ("writeLine".to_string(), Value::Builtin(Builtin::WriteLine)),
+        ],
+    };
+    Value::Record(Record {
+        type_name: None,
+        fields: vec![
+            ("std".to_string(), Value::Record(std)),
+            ("print".to_string(), Value::Builtin(Builtin::Write)),
+            ("println".to_string(), Value::Builtin(Builtin::WriteLine)),
+        ],
+    })
+}
+
+// `std.io.stdout.write`, `std.io.stdout.writeLine` and `std.io.stdin.readLine`
+fn std_module() -> Value {
+    let stdout = Record {
+        type_name: None,
+        fields: vec![
+            ("write".to_string(), Value::Builtin(Builtin::Write)),
+            ("writeLine".to_string(), Value::Builtin(Builtin::WriteLine)),
+        ],
+    };
+    let stdin = Record {
+        type_name: None,
+        fields: vec![("readLine".to_string(), Value::Builtin(Builtin::ReadLine))],
+    };
+    let io = Record {
+        type_name: None,
+        fields: vec![
+            ("stdout".to_string(), Value::Record(stdout)),
+            ("stdin".to_string(), Value::Record(stdin)),
+        ],
+    };
+    Value::Record(Record {
+        type_name: None,
+        fields: vec![("io".to_string(), Value::Record(io))],
+    })
+}
+
+fn literal(literal: &Literal, span: Span) -> Result<Value, Control> {
+    Ok(match literal {
+        Literal::IntLiteral(value, _)
+        | Literal::OctalLiteral(value, _)
+        | Literal::HexLiteral(value, _)
+        | Literal::BinaryLiteral(value, _) => match i128::try_from(*value) {
+            Ok(value) => Value::Int(value),
+            Err(_) => return error(RuntimeErrorKind::Overflow("literal"), span),
+        },
+        Literal::FloatLiteral(value, _) => Value::Float(*value),
+        Literal::BoolLiteral(value) => Value::Bool(*value),
+        Literal::CharLiteral(value) => Value::Char(*value),
+        Literal::StringLiteral(value) => Value::String(value.clone()),
+    })
+}
+
+// `a.b.c` is right nested, `(. a (. b c))`, this flattens it to `[a, b, c]`
+fn accessor_chain<'e>(expr: &'e Expr, chain: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary(Binary {
+            op: BinaryOp::Accessor,
+            left,
+            right,
+            ..
+        }) => {
+            accessor_chain(left, chain);
+            accessor_chain(right, chain);
+        }
+        expr => chain.push(expr),
+    }
+}
+
+fn identifier(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Atom(Atom::Identifier(name), _, _) => Some(name),
+        _ => None,
+    }
+}
+
+// `if (a) { body: {...}, else: {...} }` picks a branch, a plain block is the body
+fn branches(block: &Expr) -> Option<(Option<&Expr>, Option<&Expr>)> {
+    let exprs = match block {
+        Expr::Group(group) if !group.exprs.is_empty() => &group.exprs,
+        _ => return None,
+    };
+    let (mut body, mut otherwise) = (None, None);
+    for expr in exprs {
+        match expr {
+            Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                left,
+                right,
+                ..
+            }) => match identifier(left) {
+                Some("body") => body = Some(&**right),
+                Some("else") => otherwise = Some(&**right),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some((body, otherwise))
+}
+
+/// Evaluates a program, reading lines from `input` and writing anything it
+/// prints to `out`.
+pub struct Interpreter<W: Write> {
+    pub out: W,
+    input: Box<dyn BufRead + Send>,
+    // the globals are scopes[0], a call gets a frame of its own starting at `frame`
+    scopes: Vec<HashMap<String, Value>>,
+    frame: usize,
+    depth: usize,
+}
+
+impl<W: Write> Interpreter<W> {
+    pub fn new(input: impl BufRead + Send + 'static, out: W) -> Self {
+        Interpreter {
+            out,
+            input: Box::new(input),
+            scopes: vec![HashMap::new()],
+            frame: 0,
+            depth: 0,
+        }
+    }
+
+    /// Runs the program and then its `main`, if it has one. Returns the exit
+    /// status: the value passed to `exit`, or what `main` returns if it is an `Int`.
+    pub fn run(&mut self, program: &Expr) -> Result<i32, RuntimeError> {
+        let result =
+            self.statements(program)
+                .and_then(|_| match self.scopes[0].get("main").cloned() {
+                    Some(main @ Value::Function(_)) => {
+                        self.call(main, None, &[], None, program.span())
+                    }
+                    _ => Ok(Value::Void),
+                });
+        let _ = self.out.flush();
+        match result {
+            Ok(Value::Int(status)) | Err(Control::Return(Value::Int(status))) => Ok(status as i32),
+            Ok(_) | Err(Control::Return(_)) => Ok(0),
+            Err(Control::Exit(status)) => Ok(status),
+            Err(Control::Error(error)) => Err(error),
+        }
+    }
+
+    /// Evaluates one repl entry, its bindings stay around for the next one.
+    pub fn eval_entry(&mut self, entry: &Expr) -> Result<Value, Control> {
+        let result = match self.statements(entry) {
+            Err(Control::Return(value)) => Ok(value),
+            result => result,
+        };
+        let _ = self.out.flush();
+        result
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> Result<Value, Control> {
+        if let Some(value) = self.find(name) {
+            return Ok(value.clone());
+        }
+        match name {
+            "io" => return Ok(io_module()),
+            "std" => return Ok(std_module()),
+            _ => {}
+        }
+        match Builtin::from_name(name) {
+            Some(builtin) => Ok(Value::Builtin(builtin)),
+            None => error(RuntimeErrorKind::UndefinedName(name.to_string()), span),
+        }
+    }
+
+    // the scopes of the current call, then the globals
+    fn visible(&self) -> impl Iterator<Item = usize> {
+        let globals = match self.frame {
+            0 => None,
+            _ => Some(0),
+        };
+        (self.frame..self.scopes.len()).rev().chain(globals)
+    }
+
+    fn find(&self, name: &str) -> Option<&Value> {
+        self.visible()
+            .find_map(|scope| self.scopes[scope].get(name))
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut Value> {
+        let scope = self
+            .visible()
+            .find(|scope| self.scopes[*scope].contains_key(name))?;
+        self.scopes[scope].get_mut(name)
+    }
+
+    // `name: value`, a new binding unless the name is already visible
+    fn declare(&mut self, name: &str, mut value: Value) {
+        let capitalised = name.starts_with(|c: char| c.is_ascii_uppercase());
+        match &mut value {
+            Value::Record(record) if record.type_name.is_none() && capitalised => {
+                record.type_name = Some(name.to_string());
+            }
+            Value::Enum(ty) if ty.name.is_none() && capitalised => {
+                value = Value::Enum(Arc::new(Enum {
+                    name: Some(name.to_string()),
+                    variants: ty.variants.clone(),
+                }));
+            }
+            Value::Function(function) if function.name.is_none() => {
+                value = Value::Function(Arc::new(Function {
+                    name: Some(name.to_string()),
+                    params: function.params.clone(),
+                    body: function.body.clone(),
+                }));
+            }
+            _ => {}
+        }
+        match self.find_mut(name) {
+            Some(slot) => *slot = value,
+            None => {
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.to_string(), value);
+            }
+        }
+    }
+
+    // runs the items of a block in the current scope
+    fn statements(&mut self, block: &Expr) -> Result<Value, Control> {
+        match block {
+            Expr::Group(group) => self.items(&group.exprs),
+            expr => self.eval(expr),
+        }
+    }
+
+    fn items(&mut self, exprs: &[Expr]) -> Result<Value, Control> {
+        let mut value = Value::Void;
+        for expr in exprs {
+            value = self.eval(expr)?;
+        }
+        Ok(value)
+    }
+
+    fn scoped(&mut self, block: &Expr) -> Result<Value, Control> {
+        self.scopes.push(HashMap::new());
+        let result = self.statements(block);
+        self.scopes.pop();
+        result
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, Control> {
+        match expr {
+            Expr::Atom(Atom::Literal(value), span, _) => literal(value, *span),
+            Expr::Atom(Atom::Identifier(name), span, _) => self.lookup(name, *span),
+            Expr::Atom(Atom::EndOfFile, _, _) => Ok(Value::Void),
+            Expr::Unary(unary) => self.eval_unary(unary),
+            Expr::Binary(binary) => self.eval_binary(binary),
+            Expr::Comparison(comparison) => self.eval_comparison(comparison),
+            Expr::Ternary(Ternary {
+                left,
+                middle,
+                right,
+                span,
+                ..
+            }) => self.eval_invoke(left, middle, Some(right), *span),
+            Expr::Group(group) => self.eval_group(group),
+            Expr::InterpolatedString(string) => {
+                let mut out = String::new();
+                for part in &string.parts {
+                    out += &self.eval(part)?.to_string();
+                }
+                Ok(Value::String(out))
+            }
+            Expr::Directive(directive) => error(
+                RuntimeErrorKind::Unsupported(format!("`@{}`", directive.name)),
+                directive.span,
+            ),
+            Expr::Error(span, _) => error(
+                RuntimeErrorKind::Unsupported("an expression that failed to parse".to_string()),
+                *span,
+            ),
+        }
+    }
+
+    fn eval_group(&mut self, group: &Group) -> Result<Value, Control> {
+        let spread = group.exprs.iter().any(|expr| {
+            matches!(
+                expr,
+                Expr::Unary(Unary {
+                    op: UnaryOp::SpreadExpr,
+                    ..
+                })
+            )
+        });
+        match group.op {
+            GroupOp::AnonymousType | GroupOp::AssignmentBlock => {
+                Ok(Value::Record(self.record(&group.exprs)?))
+            }
+            // `{ ...a, b: 1 }` comes out of the parser as a block
+            GroupOp::StatementBlock if spread => Ok(Value::Record(self.record(&group.exprs)?)),
+            GroupOp::StatementBlock => {
+                self.scopes.push(HashMap::new());
+                let result = self.items(&group.exprs);
+                self.scopes.pop();
+                result
+            }
+            GroupOp::ParamBlock => error(
+                RuntimeErrorKind::Unsupported("a param block without a name".to_string()),
+                group.span,
+            ),
+        }
+    }
+
+    // `{ name: "John", ...address, age }`
+    fn record(&mut self, exprs: &[Expr]) -> Result<Record, Control> {
+        let mut record = Record {
+            type_name: None,
+            fields: Vec::new(),
+        };
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    span,
+                    ..
+                }) => match identifier(left) {
+                    Some(name) => {
+                        let value = self.eval(right)?;
+                        record.set(name, value);
+                    }
+                    None => return error(RuntimeErrorKind::InvalidTarget, *span),
+                },
+                Expr::Unary(Unary {
+                    op: UnaryOp::SpreadExpr,
+                    expr,
+                    span,
+                    ..
+                }) => match self.eval(expr)? {
+                    Value::Record(spread) => {
+                        for (name, value) in spread.fields {
+                            record.set(&name, value);
+                        }
+                    }
+                    value => {
+                        return error(
+                            RuntimeErrorKind::InvalidOperand("...", value.type_name()),
+                            *span,
+                        )
+                    }
+                },
+                // shorthand for `name: name`
+                Expr::Atom(Atom::Identifier(name), span, _) => {
+                    let value = self.lookup(name, *span)?;
+                    record.set(name, value);
+                }
+                expr => {
+                    return error(
+                        RuntimeErrorKind::Unsupported("this expression in a type".to_string()),
+                        expr.span(),
+                    )
+                }
+            }
+        }
+        Ok(record)
+    }
+
+    // `Fn { args: { a: Int }, return: Int, body: { ... } }`
+    fn function(&mut self, exprs: &[Expr]) -> Result<Function, Control> {
+        let mut function = Function {
+            name: None,
+            params: Vec::new(),
+            body: None,
+        };
+        for expr in exprs {
+            let (name, value) = match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => (identifier(left).unwrap(), &**right),
+                expr => {
+                    return error(
+                        RuntimeErrorKind::Unsupported("this expression in a function".to_string()),
+                        expr.span(),
+                    )
+                }
+            };
+            match (name, value) {
+                ("args", Expr::Group(args)) => {
+                    for arg in &args.exprs {
+                        let param = match arg {
+                            Expr::Binary(Binary {
+                                op: BinaryOp::Assignment,
+                                left,
+                                ..
+                            }) => identifier(left),
+                            arg => identifier(arg),
+                        };
+                        match param {
+                            Some(param) => function.params.push(param.to_string()),
+                            None => return error(RuntimeErrorKind::InvalidTarget, arg.span()),
+                        }
+                    }
+                }
+                ("body" | "fn", body) => function.body = Some(body.clone()),
+                // types are left to the checker
+                ("return" | "returns", _) => {}
+                (name, value) => {
+                    return error(
+                        RuntimeErrorKind::UnknownField(name.to_string(), "Fn".to_string()),
+                        value.span(),
+                    )
+                }
+            }
+        }
+        Ok(function)
+    }
+
+    // `Rgb: Enum { RED, GREEN }`, `Currency: Enum { GBP: "GBP" }` or
+    // `Token: Enum { Comment: String }`
+    fn enumeration(&mut self, exprs: &[Expr]) -> Result<Enum, Control> {
+        let mut variants = Vec::new();
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    let value = self.eval(right)?;
+                    variants.push((identifier(left).unwrap().to_string(), value));
+                }
+                Expr::Atom(Atom::Identifier(name), _, _) => {
+                    variants.push((name.clone(), Value::Void))
+                }
+                expr => {
+                    return error(
+                        RuntimeErrorKind::Unsupported("this expression in an enum".to_string()),
+                        expr.span(),
+                    )
+                }
+            }
+        }
+        Ok(Enum {
+            name: None,
+            variants,
+        })
+    }
+
+    fn instantiate(
+        &mut self,
+        ty: &Record,
+        fields: Vec<(String, Value)>,
+        positional: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        let type_name = ty.type_name.clone().unwrap_or("{}".to_string());
+        let mut record = ty.clone();
+        if positional.len() > record.fields.len() {
+            return error(
+                RuntimeErrorKind::TooManyArguments(record.fields.len()),
+                span,
+            );
+        }
+        for (i, value) in positional.into_iter().enumerate() {
+            record.fields[i].1 = value;
+        }
+        for (name, value) in fields {
+            match record.get_mut(&name) {
+                Some(field) => *field = value,
+                None => return error(RuntimeErrorKind::UnknownField(name, type_name), span),
+            }
+        }
+        for (name, value) in &record.fields {
+            if matches!(value, Value::Builtin(builtin) if builtin.is_type()) {
+                return error(
+                    RuntimeErrorKind::MissingField(name.clone(), type_name),
+                    span,
+                );
+            }
+        }
+        Ok(Value::Record(record))
+    }
+
+    fn eval_typedef(&mut self, left: &Expr, block: &Expr, span: Span) -> Result<Value, Control> {
+        let exprs = match block {
+            Expr::Group(group) => &group.exprs[..],
+            block => std::slice::from_ref(block),
+        };
+        match self.eval(left)? {
+            Value::Builtin(Builtin::Type) => Ok(Value::Record(self.record(exprs)?)),
+            Value::Builtin(Builtin::Fn) => Ok(Value::Function(Arc::new(self.function(exprs)?))),
+            Value::Builtin(Builtin::Enum) => Ok(Value::Enum(Arc::new(self.enumeration(exprs)?))),
+            // `Person { name: "John", age }`
+            Value::Record(ty) => {
+                let fields = self.record(exprs)?.fields;
+                self.instantiate(&ty, fields, Vec::new(), span)
+            }
+            // `twice { ... }` passes the block as `body`
+            function @ Value::Function(_) => self.call(function, None, &[], Some(block), span),
+            value => error(RuntimeErrorKind::NotAType(value.type_name()), left.span()),
+        }
+    }
+
+    fn arguments(&mut self, args: &Expr) -> Result<Arguments, Control> {
+        let exprs = match args {
+            Expr::Group(group) => &group.exprs[..],
+            args => std::slice::from_ref(args),
+        };
+        let (mut positional, mut named) = (Vec::new(), Vec::new());
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    let value = self.eval(right)?;
+                    named.push((identifier(left).unwrap().to_string(), value));
+                }
+                expr => positional.push(self.eval(expr)?),
+            }
+        }
+        Ok((positional, named))
+    }
+
+    fn eval_invoke(
+        &mut self,
+        callee: &Expr,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        // `return` is also a variable in every function, see `call_function`
+        let callee = match identifier(callee) {
+            Some("return") => Value::Builtin(Builtin::Return),
+            _ => self.eval(callee)?,
+        };
+        self.call(callee, None, std::slice::from_ref(args), block, span)
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        receiver: Option<Value>,
+        args: &[Expr],
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        // these look at their arguments before evaluating them
+        match callee {
+            Value::Builtin(Builtin::If) => return self.call_if(args, block, span),
+            Value::Builtin(Builtin::While) => return self.call_while(args, block, span),
+            _ => {}
+        }
+
+        let (mut positional, mut named) = (Vec::new(), Vec::new());
+        for args in args {
+            let (more_positional, more_named) = self.arguments(args)?;
+            positional.extend(more_positional);
+            named.extend(more_named);
+        }
+        if let Some(block) = block {
+            match (&callee, block) {
+                // `Person(name: "John") { age: 30 }`
+                (Value::Record(_), Expr::Group(group)) => {
+                    named.extend(self.record(&group.exprs)?.fields)
+                }
+                _ => named.push(("body".to_string(), Value::Block(Arc::new(block.clone())))),
+            }
+        }
+
+        match callee {
+            Value::Function(function) => {
+                self.call_function(&function, receiver, positional, named, span)
+            }
+            Value::Block(block) => self.scoped(&block),
+            Value::Record(ty) => {
+                if let Some(receiver) = receiver {
+                    positional.insert(0, receiver);
+                }
+                self.instantiate(&ty, named, positional, span)
+            }
+            // `Rgb(0)`
+            Value::Enum(ty) => {
+                if let Some((name, _)) = named.first() {
+                    return error(RuntimeErrorKind::UnknownArgument(name.clone()), span);
+                }
+                match &positional[..] {
+                    [Value::Int(index)] => match usize::try_from(*index) {
+                        Ok(index) if index < ty.variants.len() => variant(&ty, index, None, span),
+                        _ => error(
+                            RuntimeErrorKind::UnknownVariant(ty.type_name(), index.to_string()),
+                            span,
+                        ),
+                    },
+                    [value] => error(
+                        RuntimeErrorKind::InvalidOperand("()", value.type_name()),
+                        span,
+                    ),
+                    [] => error(RuntimeErrorKind::MissingArgument("index".to_string()), span),
+                    _ => error(RuntimeErrorKind::TooManyArguments(1), span),
+                }
+            }
+            Value::Builtin(builtin) => {
+                if let Some(receiver) = receiver {
+                    positional.insert(0, receiver);
+                }
+                if let Some((name, _)) = named.first() {
+                    return error(RuntimeErrorKind::UnknownArgument(name.clone()), span);
+                }
+                self.call_builtin(builtin, positional, span)
+            }
+            callee => error(RuntimeErrorKind::NotCallable(callee.type_name()), span),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Function,
+        receiver: Option<Value>,
+        positional: Vec<Value>,
+        named: Vec<(String, Value)>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        let params = &function.params;
+        let mut bound: Vec<Option<Value>> = vec![None; params.len()];
+        if let Some(receiver) = receiver {
+            let index = params.iter().position(|param| param == "self").unwrap_or(0);
+            match bound.get_mut(index) {
+                Some(slot) => *slot = Some(receiver),
+                None => return error(RuntimeErrorKind::TooManyArguments(0), span),
+            }
+        }
+        for value in positional {
+            match bound.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => *slot = Some(value),
+                None => return error(RuntimeErrorKind::TooManyArguments(params.len()), span),
+            }
+        }
+        for (name, value) in named {
+            match params.iter().position(|param| *param == name) {
+                Some(index) => bound[index] = Some(value),
+                None => return error(RuntimeErrorKind::UnknownArgument(name), span),
+            }
+        }
+
+        let mut frame = HashMap::new();
+        let mut args = Record {
+            type_name: None,
+            fields: Vec::new(),
+        };
+        for (param, value) in params.iter().zip(bound) {
+            match value {
+                Some(value) => {
+                    args.set(param, value.clone());
+                    frame.insert(param.clone(), value);
+                }
+                None => return error(RuntimeErrorKind::MissingArgument(param.clone()), span),
+            }
+        }
+        // `args.name` works as well as `name`, and `return: value` sets the result
+        frame.insert("args".to_string(), Value::Record(args));
+        frame.insert("return".to_string(), Value::Void);
+
+        if self.depth == MAX_CALL_DEPTH {
+            return error(RuntimeErrorKind::StackOverflow, span);
+        }
+        let caller = self.frame;
+        self.depth += 1;
+        self.scopes.push(frame);
+        self.frame = self.scopes.len() - 1;
+        let result = match &function.body {
+            Some(body) => self.statements(body),
+            None => Ok(Value::Void),
+        };
+        let returned = self.scopes[self.frame].remove("return");
+        self.scopes.truncate(self.frame);
+        self.frame = caller;
+        self.depth -= 1;
+
+        match (result, returned) {
+            (Err(Control::Return(value)), _) => Ok(value),
+            (Ok(_), Some(value)) if value != Value::Void => Ok(value),
+            (result, _) => result,
+        }
+    }
+
+    fn condition(&mut self, args: &[Expr], span: Span) -> Result<bool, Control> {
+        let (positional, named) = match args {
+            [args] => self.arguments(args)?,
+            _ => (Vec::new(), Vec::new()),
+        };
+        if let Some((name, _)) = named.first() {
+            return error(RuntimeErrorKind::UnknownArgument(name.clone()), span);
+        }
+        match &positional[..] {
+            [Value::Bool(value)] => Ok(*value),
+            [value] => error(RuntimeErrorKind::ExpectedBool(value.type_name()), span),
+            [] => error(RuntimeErrorKind::MissingArgument("value".to_string()), span),
+            _ => error(RuntimeErrorKind::TooManyArguments(1), span),
+        }
+    }
+
+    fn call_if(
+        &mut self,
+        args: &[Expr],
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        let condition = self.condition(args, span)?;
+        let block = match block {
+            Some(block) => block,
+            None => return error(RuntimeErrorKind::MissingArgument("body".to_string()), span),
+        };
+        let (body, otherwise) = match branches(block) {
+            Some(branches) => branches,
+            None => (Some(block), None),
+        };
+        match (condition, body, otherwise) {
+            (true, Some(body), _) | (false, _, Some(body)) => self.scoped(body),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    fn call_while(
+        &mut self,
+        args: &[Expr],
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        let block = match block {
+            Some(block) => block,
+            None => return error(RuntimeErrorKind::MissingArgument("body".to_string()), span),
+        };
+        while self.condition(args, span)? {
+            self.scoped(block)?;
+        }
+        Ok(Value::Void)
+    }
+
+    fn call_builtin(
+        &mut self,
+        builtin: Builtin,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        if let Builtin::Write | Builtin::WriteLine = builtin {
+            let text = args
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+            let _ = match builtin {
+                Builtin::WriteLine => writeln!(self.out, "{}", text),
+                _ => write!(self.out, "{}", text),
+            };
+            return Ok(Value::Void);
+        }
+        // the line without its newline, empty at the end of the input
+        if builtin == Builtin::ReadLine {
+            if !args.is_empty() {
+                return error(RuntimeErrorKind::TooManyArguments(0), span);
+            }
+            let _ = self.out.flush();
+            let mut line = String::new();
+            let _ = self.input.read_line(&mut line);
+            if line.ends_with('\n') {
+                line.pop();
+            }
+            return Ok(Value::String(line));
+        }
+        if args.len() > 1 {
+            return error(RuntimeErrorKind::TooManyArguments(1), span);
+        }
+        match (builtin, args.into_iter().next()) {
+            (Builtin::Return, value) => Err(Control::Return(value.unwrap_or(Value::Void))),
+            (Builtin::Exit, None) => Err(Control::Exit(0)),
+            (Builtin::Exit, Some(Value::Int(status))) => Err(Control::Exit(status as i32)),
+            (Builtin::Int, None) => Ok(Value::Int(0)),
+            (Builtin::Int, Some(Value::Int(value))) => Ok(Value::Int(value)),
+            (Builtin::Int, Some(Value::Float(value))) => Ok(Value::Int(value as i128)),
+            (Builtin::Int, Some(Value::Char(value))) => Ok(Value::Int(value as i128)),
+            (Builtin::Int, Some(Value::Variant(variant))) => Ok(Value::Int(variant.index as i128)),
+            (Builtin::Float, None) => Ok(Value::Float(0.0)),
+            (Builtin::Float, Some(Value::Float(value))) => Ok(Value::Float(value)),
+            (Builtin::Float, Some(Value::Int(value))) => Ok(Value::Float(value as f64)),
+            (Builtin::Bool, None) => Ok(Value::Bool(false)),
+            (Builtin::Bool, Some(Value::Bool(value))) => Ok(Value::Bool(value)),
+            (Builtin::Char, Some(Value::Char(value))) => Ok(Value::Char(value)),
+            (Builtin::String, None) => Ok(Value::String(String::new())),
+            (Builtin::String, Some(value)) => Ok(Value::String(value.to_string())),
+            (Builtin::Type | Builtin::Fn | Builtin::Enum, _) => {
+                error(RuntimeErrorKind::NotCallable("Type".to_string()), span)
+            }
+            (builtin, Some(value)) => error(
+                RuntimeErrorKind::InvalidOperand(builtin.name(), value.type_name()),
+                span,
+            ),
+            (builtin, None) => error(
+                RuntimeErrorKind::MissingArgument(builtin.name().to_string()),
+                span,
+            ),
+        }
+    }
+
+    fn eval_unary(&mut self, unary: &Unary) -> Result<Value, Control> {
+        let value = match unary.op {
+            UnaryOp::SpreadExpr => {
+                return error(
+                    RuntimeErrorKind::Unsupported("`...` outside of a type".to_string()),
+                    unary.span,
+                )
+            }
+            _ => self.eval(&unary.expr)?,
+        };
+        match (&unary.op, value) {
+            (UnaryOp::Negate, Value::Int(value)) => match value.checked_neg() {
+                Some(value) => Ok(Value::Int(value)),
+                None => error(RuntimeErrorKind::Overflow("-"), unary.span),
+            },
+            (UnaryOp::Negate, Value::Float(value)) => Ok(Value::Float(-value)),
+            (UnaryOp::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+            (UnaryOp::BitwiseNot, Value::Int(value)) => Ok(Value::Int(!value)),
+            (op, value) => error(
+                RuntimeErrorKind::InvalidOperand(op.symbol(), value.type_name()),
+                unary.span,
+            ),
+        }
+    }
+
+    fn eval_binary(&mut self, binary: &Binary) -> Result<Value, Control> {
+        let span = binary.span;
+        match &binary.op {
+            BinaryOp::Assignment => {
+                let value = self.eval(&binary.right)?;
+                self.assign(&binary.left, value, true)?;
+                Ok(Value::Void)
+            }
+            BinaryOp::Reassignment => {
+                let value = self.eval(&binary.right)?;
+                self.assign(&binary.left, value, false)?;
+                Ok(Value::Void)
+            }
+            BinaryOp::Accessor => self.eval_accessor(binary),
+            BinaryOp::Invoke => self.eval_invoke(&binary.left, &binary.right, None, span),
+            BinaryOp::TypeDef => self.eval_typedef(&binary.left, &binary.right, span),
+            BinaryOp::FieldDef => error(
+                RuntimeErrorKind::Unsupported("a field definition".to_string()),
+                span,
+            ),
+            BinaryOp::And | BinaryOp::Or => {
+                let short_circuit = binary.op == BinaryOp::Or;
+                match self.eval(&binary.left)? {
+                    Value::Bool(left) if left == short_circuit => Ok(Value::Bool(left)),
+                    Value::Bool(_) => match self.eval(&binary.right)? {
+                        Value::Bool(right) => Ok(Value::Bool(right)),
+                        right => error(
+                            RuntimeErrorKind::MismatchedOperands(
+                                binary.op.symbol(),
+                                "Bool".to_string(),
+                                right.type_name(),
+                            ),
+                            span,
+                        ),
+                    },
+                    left => error(
+                        RuntimeErrorKind::InvalidOperand(binary.op.symbol(), left.type_name()),
+                        span,
+                    ),
+                }
+            }
+            op if op.is_comparison() => {
+                let left = self.eval(&binary.left)?;
+                let right = self.eval(&binary.right)?;
+                Ok(Value::Bool(compare(op, &left, &right, span)?))
+            }
+            op => {
+                let left = self.eval(&binary.left)?;
+                let right = self.eval(&binary.right)?;
+                arithmetic(op, left, right, span)
+            }
+        }
+    }
+
+    fn eval_comparison(&mut self, comparison: &Comparison) -> Result<Value, Control> {
+        let mut left = self.eval(&comparison.operands[0])?;
+        for (op, operand) in comparison.ops.iter().zip(&comparison.operands[1..]) {
+            let right = self.eval(operand)?;
+            if !compare(op, &left, &right, comparison.span)? {
+                return Ok(Value::Bool(false));
+            }
+            left = right;
+        }
+        Ok(Value::Bool(true))
+    }
+
+    fn field(&self, value: &Value, name: &str, span: Span) -> Result<Value, Control> {
+        match (value, name) {
+            (Value::Record(record), name) if record.get(name).is_some() => {
+                Ok(record.get(name).unwrap().clone())
+            }
+            (Value::Enum(ty), name) if ty.index(name).is_some() => {
+                variant(ty, ty.index(name).unwrap(), None, span)
+            }
+            (Value::Variant(variant), "value") if *variant.value != Value::Void => {
+                Ok((*variant.value).clone())
+            }
+            // the interpreter doesn't keep numbers at a width, `Int.I32` is `Int`
+            (Value::Builtin(builtin @ (Builtin::Int | Builtin::Float)), name)
+                if NumberSuffix::from_name(&name.to_lowercase()).is_some() =>
+            {
+                Ok(Value::Builtin(*builtin))
+            }
+            (value, "type") => Ok(Value::String(value.type_name())),
+            (value, name) => error(
+                RuntimeErrorKind::UnknownField(name.to_string(), value.type_name()),
+                span,
+            ),
+        }
+    }
+
+    // `value.name(args)` calls a function field of `value`, or else the function
+    // `name` with `value` as its `self`
+    fn method(
+        &mut self,
+        value: Value,
+        name: &str,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Value, Control> {
+        match (&value, name) {
+            (Value::Enum(ty), name) if name == "parse" || ty.index(name).is_some() => {
+                let ty = ty.clone();
+                return self.enum_method(&ty, name, args, span);
+            }
+            // `String.parse(color)` is the value of a variant that has a string,
+            // otherwise its name
+            (Value::Builtin(Builtin::String), "parse") => {
+                let (positional, _) = self.arguments(args)?;
+                return match positional.into_iter().next() {
+                    Some(Value::Variant(variant)) => Ok(Value::String(match *variant.value {
+                        Value::String(value) => value,
+                        _ => variant.ty.variants[variant.index].0.clone(),
+                    })),
+                    Some(value) => Ok(Value::String(value.to_string())),
+                    None => error(RuntimeErrorKind::MissingArgument("value".to_string()), span),
+                };
+            }
+            _ => {}
+        }
+        let field = match &value {
+            Value::Record(record) => record.get(name).cloned(),
+            _ => None,
+        };
+        match field {
+            Some(Value::Function(function)) if function.params.iter().any(|p| p == "self") => self
+                .call(
+                    Value::Function(function),
+                    Some(value),
+                    std::slice::from_ref(args),
+                    block,
+                    span,
+                ),
+            Some(field) => self.call(field, None, std::slice::from_ref(args), block, span),
+            None => {
+                let function = self.lookup(name, span)?;
+                self.call(
+                    function,
+                    Some(value),
+                    std::slice::from_ref(args),
+                    block,
+                    span,
+                )
+            }
+        }
+    }
+
+    // `Token.Comment("...")` and `Rgb.parse("RED")`
+    fn enum_method(
+        &mut self,
+        ty: &Arc<Enum>,
+        name: &str,
+        args: &Expr,
+        span: Span,
+    ) -> Result<Value, Control> {
+        let (positional, named) = self.arguments(args)?;
+        if let Some((name, _)) = named.first() {
+            return error(RuntimeErrorKind::UnknownArgument(name.clone()), span);
+        }
+        if positional.len() > 1 {
+            return error(RuntimeErrorKind::TooManyArguments(1), span);
+        }
+        match (ty.index(name), positional.into_iter().next()) {
+            (Some(index), payload) if is_payload(&ty.variants[index].1) => {
+                variant(ty, index, payload, span)
+            }
+            (Some(_), _) => error(RuntimeErrorKind::NotCallable(ty.type_name()), span),
+            (None, Some(Value::String(string))) => match ty.index(&string) {
+                Some(index) => variant(ty, index, None, span),
+                None => error(
+                    RuntimeErrorKind::UnknownVariant(ty.type_name(), string),
+                    span,
+                ),
+            },
+            (None, Some(value)) => error(
+                RuntimeErrorKind::InvalidOperand("parse", value.type_name()),
+                span,
+            ),
+            (None, None) => error(
+                RuntimeErrorKind::MissingArgument("string".to_string()),
+                span,
+            ),
+        }
+    }
+
+    fn eval_accessor(&mut self, binary: &Binary) -> Result<Value, Control> {
+        let mut chain = Vec::new();
+        accessor_chain(&binary.left, &mut chain);
+        accessor_chain(&binary.right, &mut chain);
+        // `a.b: 1` is parsed as `a.(b: 1)`
+        if let Some(Expr::Binary(Binary {
+            op: op @ (BinaryOp::Assignment | BinaryOp::Reassignment),
+            left,
+            right,
+            ..
+        })) = chain.last()
+        {
+            let mut path = Vec::new();
+            for expr in chain[..chain.len() - 1].iter().copied().chain([&**left]) {
+                match identifier(expr) {
+                    Some(name) => path.push(name),
+                    None => return error(RuntimeErrorKind::InvalidTarget, expr.span()),
+                }
+            }
+            let value = self.eval(right)?;
+            self.assign_path(&path, value, *op == BinaryOp::Assignment, binary.span)?;
+            return Ok(Value::Void);
+        }
+
+        let mut value = self.eval(chain[0])?;
+        for expr in &chain[1..] {
+            value = match expr {
+                Expr::Atom(Atom::Identifier(name), span, _) => self.field(&value, name, *span)?,
+                Expr::Binary(Binary {
+                    op: BinaryOp::Invoke,
+                    left,
+                    right,
+                    span,
+                    ..
+                }) if identifier(left).is_some() => {
+                    self.method(value, identifier(left).unwrap(), right, None, *span)?
+                }
+                Expr::Ternary(Ternary {
+                    left,
+                    middle,
+                    right,
+                    span,
+                    ..
+                }) if identifier(left).is_some() => {
+                    self.method(value, identifier(left).unwrap(), middle, Some(right), *span)?
+                }
+                expr => {
+                    return error(
+                        RuntimeErrorKind::Unsupported("this expression after `.`".to_string()),
+                        expr.span(),
+                    )
+                }
+            };
+        }
+        Ok(value)
+    }
+
+    // `name: value` declares, `name = value` needs `name` to exist
+    fn assign(&mut self, target: &Expr, value: Value, declare: bool) -> Result<(), Control> {
+        match target {
+            Expr::Atom(Atom::Identifier(name), span, _) => {
+                if declare {
+                    self.declare(name, value);
+                    return Ok(());
+                }
+                match self.find_mut(name) {
+                    Some(slot) => {
+                        *slot = value;
+                        Ok(())
+                    }
+                    None => error(RuntimeErrorKind::UndefinedName(name.clone()), *span),
+                }
+            }
+            Expr::Binary(Binary {
+                op: BinaryOp::Accessor,
+                span,
+                ..
+            }) => {
+                let mut chain = Vec::new();
+                accessor_chain(target, &mut chain);
+                let mut path = Vec::new();
+                for expr in chain {
+                    match identifier(expr) {
+                        Some(name) => path.push(name),
+                        None => return error(RuntimeErrorKind::InvalidTarget, expr.span()),
+                    }
+                }
+                self.assign_path(&path, value, declare, *span)
+            }
+            // `{ name, age }: person`
+            Expr::Group(group) => {
+                let record = match value {
+                    Value::Record(record) => record,
+                    value => {
+                        return error(
+                            RuntimeErrorKind::InvalidOperand("{}", value.type_name()),
+                            group.span,
+                        )
+                    }
+                };
+                for expr in &group.exprs {
+                    match expr {
+                        Expr::Atom(Atom::Identifier(name), span, _) => {
+                            let value = self.field(&Value::Record(record.clone()), name, *span)?;
+                            self.assign(expr, value, declare)?;
+                        }
+                        expr => return error(RuntimeErrorKind::InvalidTarget, expr.span()),
+                    }
+                }
+                Ok(())
+            }
+            target => error(RuntimeErrorKind::InvalidTarget, target.span()),
+        }
+    }
+
+    // `a.b.c: value`, only `:` can add a field
+    fn assign_path(
+        &mut self,
+        path: &[&str],
+        value: Value,
+        declare: bool,
+        span: Span,
+    ) -> Result<(), Control> {
+        let mut slot = match self.find_mut(path[0]) {
+            Some(slot) => slot,
+            None => return error(RuntimeErrorKind::UndefinedName(path[0].to_string()), span),
+        };
+        for (i, name) in path[1..].iter().enumerate() {
+            let last = i == path.len() - 2;
+            let type_name = slot.type_name();
+            let record = match slot {
+                Value::Record(record) => record,
+                _ => {
+                    return error(
+                        RuntimeErrorKind::UnknownField(name.to_string(), type_name),
+                        span,
+                    )
+                }
+            };
+            if last && declare && record.get(name).is_none() {
+                record.set(name, value);
+                return Ok(());
+            }
+            slot = match record.get_mut(name) {
+                Some(slot) => slot,
+                None => {
+                    return error(
+                        RuntimeErrorKind::UnknownField(name.to_string(), type_name),
+                        span,
+                    )
+                }
+            };
+        }
+        *slot = value;
+        Ok(())
+    }
+}
+
+fn compare(op: &BinaryOp, left: &Value, right: &Value, span: Span) -> Result<bool, Control> {
+    use std::cmp::Ordering;
+
+    let ordering = match (left, right) {
+        (Value::Int(left), Value::Int(right)) => left.partial_cmp(right),
+        (Value::Float(left), Value::Float(right)) => left.partial_cmp(right),
+        (Value::String(left), Value::String(right)) => left.partial_cmp(right),
+        (Value::Char(left), Value::Char(right)) => left.partial_cmp(right),
+        (Value::Bool(left), Value::Bool(right)) => left.partial_cmp(right),
+        (left, right) if matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) => {
+            if std::mem::discriminant(left) != std::mem::discriminant(right) {
+                return error(
+                    RuntimeErrorKind::MismatchedOperands(
+                        op.symbol(),
+                        left.type_name(),
+                        right.type_name(),
+                    ),
+                    span,
+                );
+            }
+            return Ok((left == right) == (*op == BinaryOp::Equal));
+        }
+        (left, right) => {
+            return error(
+                RuntimeErrorKind::MismatchedOperands(
+                    op.symbol(),
+                    left.type_name(),
+                    right.type_name(),
+                ),
+                span,
+            )
+        }
+    };
+    // only NaN has no ordering, and it is not equal to anything
+    Ok(match (op, ordering) {
+        (BinaryOp::NotEqual, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => false,
+        (BinaryOp::Equal, Some(ordering)) => ordering == Ordering::Equal,
+        (BinaryOp::LessThan, Some(ordering)) => ordering == Ordering::Less,
+        (BinaryOp::LessThanOrEqual, Some(ordering)) => ordering != Ordering::Greater,
+        (BinaryOp::GreaterThan, Some(ordering)) => ordering == Ordering::Greater,
+        (_, Some(ordering)) => ordering != Ordering::Less,
+    })
+}
+
+fn arithmetic(op: &BinaryOp, left: Value, right: Value, span: Span) -> Result<Value, Control> {
+    let symbol = op.symbol();
+    let int = |value: Option<i128>| match value {
+        Some(value) => Ok(Value::Int(value)),
+        None => error(RuntimeErrorKind::Overflow(symbol), span),
+    };
+    match (op, left, right) {
+        (BinaryOp::Divide | BinaryOp::Modulo, Value::Int(_), Value::Int(0)) => {
+            error(RuntimeErrorKind::DivisionByZero, span)
+        }
+        (BinaryOp::Add, Value::Int(left), Value::Int(right)) => int(left.checked_add(right)),
+        (BinaryOp::Subtract, Value::Int(left), Value::Int(right)) => int(left.checked_sub(right)),
+        (BinaryOp::Multiply, Value::Int(left), Value::Int(right)) => int(left.checked_mul(right)),
+        (BinaryOp::Divide, Value::Int(left), Value::Int(right)) => int(left.checked_div(right)),
+        (BinaryOp::Modulo, Value::Int(left), Value::Int(right)) => int(left.checked_rem(right)),
+        (BinaryOp::BitwiseAnd, Value::Int(left), Value::Int(right)) => Ok(Value::Int(left & right)),
+        (BinaryOp::BitwiseOr, Value::Int(left), Value::Int(right)) => Ok(Value::Int(left | right)),
+        (BinaryOp::BitwiseXor, Value::Int(left), Value::Int(right)) => Ok(Value::Int(left ^ right)),
+        (BinaryOp::ShiftLeft | BinaryOp::ShiftRight, Value::Int(left), Value::Int(right)) => {
+            let shift = match u32::try_from(right) {
+                Ok(shift) => shift,
+                Err(_) => return error(RuntimeErrorKind::Overflow(symbol), span),
+            };
+            int(match op {
+                BinaryOp::ShiftLeft => left.checked_shl(shift),
+                _ => left.checked_shr(shift),
+            })
+        }
+        (BinaryOp::Add, Value::Float(left), Value::Float(right)) => Ok(Value::Float(left + right)),
+        (BinaryOp::Subtract, Value::Float(left), Value::Float(right)) => {
+            Ok(Value::Float(left - right))
+        }
+        (BinaryOp::Multiply, Value::Float(left), Value::Float(right)) => {
+            Ok(Value::Float(left * right))
+        }
+        (BinaryOp::Divide, Value::Float(left), Value::Float(right)) => {
+            Ok(Value::Float(left / right))
+        }
+        (BinaryOp::Modulo, Value::Float(left), Value::Float(right)) => {
+            Ok(Value::Float(left % right))
+        }
+        (BinaryOp::Add, Value::String(left), Value::String(right)) => {
+            Ok(Value::String(left + &right))
+        }
+        (op, left, right) => error(
+            RuntimeErrorKind::MismatchedOperands(op.symbol(), left.type_name(), right.type_name()),
+            span,
+        ),
+    }
+}
+
+#[cfg(test)]
+pub fn run_source(source: &str) -> (Result<i32, RuntimeError>, String) {
+    let ast = crate::parser::Parser::new(source).parse().unwrap();
+    let mut interpreter = Interpreter::new(std::io::empty(), Vec::new());
+    let status = with_stack(|| interpreter.run(&ast));
+    (status, String::from_utf8(interpreter.out).unwrap())
+}
+
+#[test]
+fn test_interp() {
+    let (status, out) = run_source(
+        r#"
+Address: { city: String, country: "UK" }
+Person: Type { name: String, age: Int, ...Address }
+greet: Fn {
+    args: { self: Person, greeting: String }
+    return: String
+    body: {
+        return: "${greeting}, ${self.name} from ${self.city}"
+    }
+}
+older: Fn {
+    args: { self: Person, years: Int }
+    body: {
+        self.age = self.age + years
+        return(self)
+    }
+}
+john: Person(name: "John", age: 30, city: "Leeds")
+jane: Person { ...john, name: "Jane" }
+{ name, country }: john
+io.std.writeLine(john.greet(greeting: "Hi"))
+io.std.writeLine(name + " " + country)
+aged: john.older(2)
+io.std.writeLine(aged.age, john.type)
+jane.age: 1 + 2 * 3
+io.std.writeLine(jane)
+count: 0
+while (count < 3) {
+    count = count + 1
+}
+if (0 < count <= 3 && !false) { io.std.writeLine("count ${count}") }
+main: Fn { body: { return(count + 4) } }
+"#,
+    );
+    assert_eq!(
+        out,
+        "Hi, John from Leeds\nJohn UK\n32 Person\n\
+         Person { name: \"Jane\", age: 7, city: \"Leeds\", country: \"UK\" }\ncount 3\n"
+    );
+    assert_eq!(status, Ok(7));
+
+    // functions called with a trailing block, and no shadowing
+    let (status, out) = run_source(
+        r#"
+twice: Fn { args: { body } body: { body() body() } }
+n: 1
+bump: Fn { body: { n: n * 10 } }
+twice { bump() }
+io.std.writeLine(n, 7 / 2, 7.0 / 2.0, 0xff & ~0x0f, 1 << 4)
+if (n == 100) { body: { exit(3) }, else: { exit(4) } }
+"#,
+    );
+    assert_eq!(out, "100 3 3.5 240 16\n");
+    assert_eq!(status, Ok(3));
+}
+
+#[test]
+fn test_interp_enums() {
+    let (status, out) = run_source(
+        r#"
+Currency: Enum { GBP: "GBP", USD: "USD" }
+Rgb: Enum { RED, GREEN, BLUE }
+Token: Enum { Comment: String, Space: Int.Usize }
+currency: Currency.GBP
+color: Rgb(1)
+token: Token.Comment("a comment")
+io.std.writeLine(currency, String.parse(currency), color, Int(color), String.parse(color))
+io.std.writeLine(token, token.value, Rgb.parse("BLUE") == Rgb.BLUE, color == Rgb.RED)
+"#,
+    );
+    assert_eq!(
+        out,
+        "Currency.GBP GBP Rgb.GREEN 1 GREEN\nToken.Comment a comment true false\n"
+    );
+    assert_eq!(status, Ok(0));
+}
+
+#[test]
+fn test_interp_errors() {
+    let error = |source: &str| run_source(source).0.unwrap_err().to_string();
+    assert_eq!(error("a: b"), "1:4: `b` is not defined");
+    assert_eq!(error("a = 1"), "1:1: `a` is not defined");
+    assert_eq!(
+        error("a: 1 + \"x\""),
+        "1:4: cannot apply `+` to `Int` and `String`"
+    );
+    assert_eq!(error("a: 1 / 0"), "1:4: division by zero");
+    assert_eq!(
+        error("a: 1 < 2.0"),
+        "1:4: cannot apply `<` to `Int` and `Float`"
+    );
+    assert_eq!(
+        error("P: { a: Int }\np: P(b: 1)"),
+        "2:4: `P` has no field `b`"
+    );
+    assert_eq!(
+        error("P: { a: Int }\np: P()"),
+        "2:4: missing field `a` for `P`"
+    );
+    assert_eq!(
+        error("f: Fn { args: { a } body: { a } }\nf()"),
+        "2:1: missing argument `a`"
+    );
+    assert_eq!(
+        error("f: Fn { body: { f() } }\nf()"),
+        "1:17: too many nested calls, the limit is 1000"
+    );
+    assert_eq!(
+        error("if (1) { a: 1 }"),
+        "1:1: expected a `Bool` condition, found `Int`"
+    );
+    assert_eq!(
+        error("Rgb: Enum { RED }\nRgb(3)"),
+        "2:1: `Rgb` has no variant `3`"
+    );
+    assert_eq!(
+        error("Rgb: Enum { RED }\nRgb.parse(\"PINK\")"),
+        "2:5: `Rgb` has no variant `PINK`"
+    );
+}
@@ -0,0 +1,2126 @@
+// The lowered form of a checked program, which is what the backends compile.
+//
+// Names are resolved to declarations and every node carries its type. Calls
+// have their arguments in parameter order and constructors have every field,
+// defaults included. Functions are lifted to the top level, which is safe
+// since a function body can't see the locals around it. What the backends
+// can't do yet is reported here, with a span, rather than in the backend.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    Atom, Binary, BinaryOp, Comparison, Expr, GroupOp, Literal, NumberSuffix, Ternary, Unary,
+    UnaryOp,
+};
+use crate::diagnostics::Diagnostic;
+use crate::driver::EXIT_RUNTIME_ERROR;
+use crate::resolve::{DeclKind, Resolution};
+use crate::span::Span;
+use crate::typeck::{self, EnumType, FunctionType, RecordType, Type, Types, VariantKind};
+
+// what the statements at the top level of the file are compiled into
+pub const INIT: &str = "zen.init";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    // index into `Resolution::declarations`, temporaries are numbered after them
+    pub id: usize,
+    pub name: String,
+    pub global: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    // a function declared at the top level, or lifted there
+    Fn(String),
+    Value(Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Int(i128, Type),
+    Float(f64, Type),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Void,
+    Var(Symbol, Type),
+    // a function used as a value
+    FnRef(String, Type),
+    Let(Symbol, Box<Node>),
+    Set(Symbol, Box<Node>),
+    SetField {
+        record: Box<Node>,
+        field: String,
+        value: Box<Node>,
+    },
+    FieldGet {
+        record: Box<Node>,
+        field: String,
+        ty: Type,
+    },
+    // the fields in the order of the record type
+    Construct {
+        ty: Type,
+        fields: Vec<(String, Node)>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Node>,
+        ty: Type,
+    },
+    // `&&` and `||` only evaluate `right` when they have to
+    Binary {
+        op: BinaryOp,
+        left: Box<Node>,
+        right: Box<Node>,
+        ty: Type,
+    },
+    // `Int(x)`, `String(x)`
+    Convert(Box<Node>, Type),
+    // `Rgb.RED` or `Token.Comment(text)`, a variant declared with a value
+    // carries that value as its payload
+    Variant {
+        ty: Type,
+        tag: usize,
+        payload: Option<Box<Node>>,
+    },
+    // the index of the variant an enum value is, as an int of the type
+    Tag(Box<Node>, Type),
+    // what the variant of an enum value carries, read as the type
+    Payload(Box<Node>, Type),
+    // an interpolated string, the parts are turned into text and joined
+    Format(Vec<Node>),
+    Call {
+        callee: Callee,
+        args: Vec<Node>,
+        ty: Type,
+    },
+    // `io.std.writeLine(a, b)`, the arguments are separated by spaces
+    Print {
+        args: Vec<Node>,
+        newline: bool,
+    },
+    // a line of stdin without its newline, empty at the end of the input
+    ReadLine,
+    Block(Vec<Node>, Type),
+    If {
+        cond: Box<Node>,
+        then: Box<Node>,
+        otherwise: Option<Box<Node>>,
+        ty: Type,
+    },
+    Loop {
+        cond: Box<Node>,
+        body: Box<Node>,
+    },
+    Return(Box<Node>),
+    Exit(Box<Node>),
+}
+
+impl Node {
+    pub fn ty(&self) -> Type {
+        match self {
+            Node::Int(_, ty)
+            | Node::Float(_, ty)
+            | Node::Var(_, ty)
+            | Node::FnRef(_, ty)
+            | Node::FieldGet { ty, .. }
+            | Node::Construct { ty, .. }
+            | Node::Unary { ty, .. }
+            | Node::Binary { ty, .. }
+            | Node::Convert(_, ty)
+            | Node::Variant { ty, .. }
+            | Node::Tag(_, ty)
+            | Node::Payload(_, ty)
+            | Node::Call { ty, .. }
+            | Node::Block(_, ty)
+            | Node::If { ty, .. } => ty.clone(),
+            Node::Bool(_) => Type::Bool,
+            Node::Char(_) => Type::Char,
+            Node::String(_) | Node::Format(_) | Node::ReadLine => Type::String,
+            Node::Void
+            | Node::Let(..)
+            | Node::Set(..)
+            | Node::SetField { .. }
+            | Node::Print { .. }
+            | Node::Loop { .. }
+            | Node::Return(_)
+            | Node::Exit(_) => Type::Void,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeField {
+    pub name: String,
+    pub ty: Type,
+    pub default: Option<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDecl {
+    pub name: String,
+    pub fields: Vec<TypeField>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumVariant {
+    pub name: String,
+    pub payload: Option<Type>,
+    // `GBP: "GBP"`
+    pub value: Option<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnDecl {
+    pub name: String,
+    pub params: Vec<(Symbol, Type)>,
+    pub ret: Type,
+    // every other variable of the body, temporaries included
+    pub locals: Vec<(Symbol, Type)>,
+    // set when the body assigns `return: value`, the function then returns
+    // this local rather than the body's last value
+    pub result: Option<Symbol>,
+    pub body: Node,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub types: Vec<TypeDecl>,
+    pub enums: Vec<EnumDecl>,
+    pub functions: Vec<FnDecl>,
+    pub globals: Vec<(Symbol, Type)>,
+    // the statements of the file, run before `main`
+    pub init: FnDecl,
+    pub main: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LowerError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl LowerError {
+    fn unsupported(what: impl std::fmt::Display, span: Span) -> Self {
+        LowerError {
+            message: format!("{} can't be compiled yet", what),
+            span,
+        }
+    }
+}
+
+impl std::fmt::Display for LowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+impl From<&LowerError> for Diagnostic {
+    fn from(error: &LowerError) -> Self {
+        Diagnostic::error(error.message.clone())
+            .with_primary(error.span, "")
+            .with_note("`zen run` can still run it with the interpreter")
+    }
+}
+
+pub fn lower(
+    program: &Expr,
+    resolution: &Resolution,
+    types: &Types,
+) -> Result<Program, LowerError> {
+    let mut declared = HashMap::new();
+    for (id, declaration) in resolution.declarations.iter().enumerate() {
+        declared.insert((declaration.span, declaration.name.as_str()), id);
+    }
+    let lowerer = Lowerer {
+        resolution,
+        types,
+        declared,
+        type_decls: Vec::new(),
+        enum_decls: Vec::new(),
+        fn_decls: Vec::new(),
+        globals: Vec::new(),
+        functions: HashMap::new(),
+        function: Function::new(INIT),
+        temps: 0,
+        lifted: 0,
+    };
+    lowerer.file(program)
+}
+
+fn identifier(expr: &Expr) -> Option<(&str, Span)> {
+    match expr {
+        Expr::Atom(Atom::Identifier(name), span, _) => Some((name, *span)),
+        _ => None,
+    }
+}
+
+fn items(expr: &Expr) -> &[Expr] {
+    match expr {
+        Expr::Group(group) => &group.exprs,
+        expr => std::slice::from_ref(expr),
+    }
+}
+
+// `a.b.c` is right nested, `(. a (. b c))`, this flattens it to `[a, b, c]`
+fn accessor_chain<'e>(expr: &'e Expr, chain: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary(Binary {
+            op: BinaryOp::Accessor,
+            left,
+            right,
+            ..
+        }) => {
+            accessor_chain(left, chain);
+            accessor_chain(right, chain);
+        }
+        expr => chain.push(expr),
+    }
+}
+
+// `name: value` with a plain name
+fn assignment(expr: &Expr) -> Option<(&str, Span, &Expr)> {
+    match expr {
+        Expr::Binary(Binary {
+            op: BinaryOp::Assignment,
+            left,
+            right,
+            ..
+        }) => identifier(left).map(|(name, span)| (name, span, &**right)),
+        _ => None,
+    }
+}
+
+// `Fn { ... }`, the block of the definition
+fn fn_block(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Binary(Binary {
+            op: BinaryOp::TypeDef,
+            left,
+            right,
+            ..
+        }) if matches!(identifier(left), Some(("Fn" | "Function", _))) => Some(right),
+        _ => None,
+    }
+}
+
+// `if (a) { body: {...}, else: {...} }` picks a branch, a plain block is the body
+fn branches(block: &Expr) -> (Option<&Expr>, Option<&Expr>) {
+    let exprs = items(block);
+    let (mut body, mut otherwise) = (None, None);
+    for expr in exprs {
+        match assignment(expr) {
+            Some(("body", _, value)) => body = Some(value),
+            Some(("else", _, value)) => otherwise = Some(value),
+            _ => return (Some(block), None),
+        }
+    }
+    match exprs.is_empty() {
+        true => (Some(block), None),
+        false => (body, otherwise),
+    }
+}
+
+fn is_spread(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Unary(Unary {
+            op: UnaryOp::SpreadExpr,
+            ..
+        })
+    )
+}
+
+fn record_type(ty: &Type) -> Option<&RecordType> {
+    match ty {
+        Type::Record(record) => Some(record),
+        Type::Meta(ty) => match &**ty {
+            Type::Record(record) => Some(record),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// `Rgb` in `Rgb.RED` or `Rgb(0)`
+fn enum_meta(ty: &Type) -> Option<&EnumType> {
+    match ty {
+        Type::Meta(ty) => match &**ty {
+            Type::Enum(enumeration) => Some(enumeration),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn has_payloads(enumeration: &EnumType) -> bool {
+    enumeration
+        .variants
+        .iter()
+        .any(|variant| matches!(variant.kind, VariantKind::Payload(_)))
+}
+
+// a payload compared with a value of another type, which like in the
+// interpreter is never equal to it
+fn mismatched(op: &BinaryOp, left: &Type, right: &Type) -> Option<Node> {
+    let same = left == right
+        || matches!(
+            (left, right),
+            (Type::Unknown, _)
+                | (_, Type::Unknown)
+                | (Type::Int(_), Type::Int(_))
+                | (Type::Float(_), Type::Float(_))
+        );
+    match op {
+        BinaryOp::Equal | BinaryOp::NotEqual if !same => {
+            Some(Node::Bool(matches!(op, BinaryOp::NotEqual)))
+        }
+        _ => None,
+    }
+}
+
+// enum values are tagged with the index of their variant
+fn tag_type() -> Type {
+    Type::Int(Some(NumberSuffix::I32))
+}
+
+// what a compiled program does when there is no variant to give, the same as
+// the interpreter stopping with an error
+fn no_variant() -> Node {
+    Node::Exit(Box::new(Node::Int(
+        EXIT_RUNTIME_ERROR as i128,
+        Type::Int(None),
+    )))
+}
+
+// the value a conversion like `Int()` gives without an argument
+fn zero(ty: &Type) -> Option<Node> {
+    Some(match ty {
+        Type::Int(_) => Node::Int(0, ty.clone()),
+        Type::Float(_) => Node::Float(0.0, ty.clone()),
+        Type::Bool => Node::Bool(false),
+        Type::String => Node::String(String::new()),
+        _ => return None,
+    })
+}
+
+// `node`, after the temporaries it uses
+fn with_lets(mut lets: Vec<Node>, node: Node) -> Node {
+    if lets.is_empty() {
+        return node;
+    }
+    let ty = node.ty();
+    lets.push(node);
+    Node::Block(lets, ty)
+}
+
+type Arguments = (Vec<Node>, Vec<(String, Node)>);
+
+// the function being lowered
+struct Function {
+    name: String,
+    // the span `args` and `return` are declared at
+    block: Option<Span>,
+    params: Vec<(Symbol, Type)>,
+    locals: Vec<(Symbol, Type)>,
+    result: Option<Symbol>,
+}
+
+impl Function {
+    fn new(name: &str) -> Self {
+        Function {
+            name: name.to_string(),
+            block: None,
+            params: Vec::new(),
+            locals: Vec::new(),
+            result: None,
+        }
+    }
+}
+
+struct Lowerer<'a> {
+    resolution: &'a Resolution,
+    types: &'a Types,
+    // declaration span and name -> index into `Resolution::declarations`
+    declared: HashMap<(Span, &'a str), usize>,
+    type_decls: Vec<TypeDecl>,
+    enum_decls: Vec<EnumDecl>,
+    fn_decls: Vec<FnDecl>,
+    globals: Vec<(Symbol, Type)>,
+    // functions declared at the top level -> the name they are compiled under
+    functions: HashMap<usize, String>,
+    function: Function,
+    temps: usize,
+    // functions lifted out of other functions, for their names
+    lifted: usize,
+}
+
+impl<'a> Lowerer<'a> {
+    fn symbol(&self, id: usize) -> Symbol {
+        let declaration = &self.resolution.declarations[id];
+        Symbol {
+            id,
+            name: declaration.name.clone(),
+            global: declaration.scope == 0,
+        }
+    }
+
+    fn declaration_type(&self, id: usize) -> Type {
+        self.types
+            .declarations
+            .get(&id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // makes room for the variable in the function, or in the program for a global
+    fn local(&mut self, symbol: &Symbol, ty: Type) {
+        let locals = match symbol.global {
+            true => &mut self.globals,
+            false => &mut self.function.locals,
+        };
+        if !locals.iter().any(|(local, _)| local.id == symbol.id) {
+            locals.push((symbol.clone(), ty));
+        }
+    }
+
+    fn temp(&mut self, ty: Type) -> Symbol {
+        let symbol = Symbol {
+            id: self.resolution.declarations.len() + self.temps,
+            name: format!("%{}", self.temps),
+            global: false,
+        };
+        self.temps += 1;
+        self.local(&symbol, ty);
+        symbol
+    }
+
+    // evaluates `node` once into a temporary, unless it is a variable already
+    fn hoist(&mut self, node: Node, lets: &mut Vec<Node>) -> Node {
+        if let Node::Var(..) = node {
+            return node;
+        }
+        let ty = node.ty();
+        let symbol = self.temp(ty.clone());
+        lets.push(Node::Let(symbol.clone(), Box::new(node)));
+        Node::Var(symbol, ty)
+    }
+
+    fn file(mut self, program: &Expr) -> Result<Program, LowerError> {
+        // functions at the top level can be called before the line declaring
+        // them, and enums used before theirs
+        for expr in items(program) {
+            if let Some((name, span, value)) = assignment(expr) {
+                let id = match self.declared.get(&(span, name)) {
+                    Some(id) => *id,
+                    None => continue,
+                };
+                if fn_block(value).is_some() {
+                    self.functions.insert(id, name.to_string());
+                } else if enum_meta(&self.declaration_type(id)).is_some() {
+                    self.enum_decl(name, id, value)?;
+                }
+            }
+        }
+
+        let mut init = Vec::new();
+        for expr in items(program) {
+            let declaration = assignment(expr).and_then(|(name, span, value)| {
+                let id = *self.declared.get(&(span, name))?;
+                Some((name, id, value))
+            });
+            match declaration {
+                Some((name, _, value)) if fn_block(value).is_some() => {
+                    self.function(name, value)?;
+                }
+                Some((_, id, _)) if enum_meta(&self.declaration_type(id)).is_some() => {}
+                Some((name, id, value))
+                    if self.resolution.declarations[id].kind == DeclKind::Type
+                        && matches!(self.declaration_type(id), Type::Meta(_)) =>
+                {
+                    self.type_decl(name, id, value)?;
+                }
+                _ => init.push(self.expr(expr)?),
+            }
+        }
+
+        let function = std::mem::replace(&mut self.function, Function::new(INIT));
+        let init = FnDecl {
+            name: INIT.to_string(),
+            params: Vec::new(),
+            ret: Type::Void,
+            locals: function.locals,
+            result: None,
+            body: Node::Block(init, Type::Void),
+            span: program.span(),
+        };
+        let main = self
+            .functions
+            .values()
+            .find(|name| *name == "main")
+            .cloned();
+        Ok(Program {
+            types: self.type_decls,
+            enums: self.enum_decls,
+            functions: self.fn_decls,
+            globals: self.globals,
+            init,
+            main,
+        })
+    }
+
+    // `Person: { name: String, age: Int, ...Address, nickname: "" }`, the fields
+    // come from the checker and the defaults from the source
+    fn type_decl(&mut self, name: &str, id: usize, value: &Expr) -> Result<(), LowerError> {
+        let exprs = match value {
+            Expr::Binary(Binary {
+                op: BinaryOp::TypeDef,
+                right,
+                ..
+            }) => items(right),
+            value => items(value),
+        };
+        let mut defaults: HashMap<String, Node> = HashMap::new();
+        for expr in exprs {
+            match expr {
+                Expr::Unary(Unary {
+                    op: UnaryOp::SpreadExpr,
+                    expr,
+                    ..
+                }) => {
+                    let spread = identifier(expr).and_then(|(name, _)| {
+                        self.type_decls.iter().find(|decl| decl.name == name)
+                    });
+                    for field in spread.map_or(&[][..], |decl| &decl.fields) {
+                        match &field.default {
+                            Some(default) => defaults.insert(field.name.clone(), default.clone()),
+                            None => defaults.remove(&field.name),
+                        };
+                    }
+                }
+                expr => match assignment(expr) {
+                    Some((field, _, value)) if !matches!(self.types.of(value), Type::Meta(_)) => {
+                        let default = self.expr(value)?;
+                        defaults.insert(field.to_string(), default);
+                    }
+                    Some((field, ..)) => {
+                        defaults.remove(field);
+                    }
+                    None => {
+                        let node = self.expr(expr)?;
+                        if let Some((field, _)) = identifier(expr) {
+                            defaults.insert(field.to_string(), node);
+                        }
+                    }
+                },
+            }
+        }
+        let ty = self.declaration_type(id);
+        let fields = record_type(&ty)
+            .map_or(&[][..], |record| &record.fields)
+            .iter()
+            .map(|field| TypeField {
+                name: field.name.clone(),
+                ty: field.ty.clone(),
+                default: defaults.remove(&field.name),
+            })
+            .collect();
+        self.type_decls.push(TypeDecl {
+            name: name.to_string(),
+            fields,
+            span: value.span(),
+        });
+        Ok(())
+    }
+
+    // `Currency: Enum { GBP: "GBP" }`, the variants come from the checker and
+    // the values from the source
+    fn enum_decl(&mut self, name: &str, id: usize, value: &Expr) -> Result<(), LowerError> {
+        let exprs = match value {
+            Expr::Binary(Binary {
+                op: BinaryOp::TypeDef,
+                right,
+                ..
+            }) => items(right),
+            value => items(value),
+        };
+        let mut values = HashMap::new();
+        for expr in exprs {
+            if let Some((variant, _, value)) = assignment(expr) {
+                if !matches!(self.types.of(value), Type::Meta(_)) {
+                    values.insert(variant, self.expr(value)?);
+                }
+            }
+        }
+        let ty = self.declaration_type(id);
+        let variants = enum_meta(&ty)
+            .map_or(&[][..], |enumeration| &enumeration.variants)
+            .iter()
+            .map(|variant| EnumVariant {
+                name: variant.name.clone(),
+                payload: match &variant.kind {
+                    VariantKind::Payload(ty) => Some(ty.clone()),
+                    _ => None,
+                },
+                value: values.remove(variant.name.as_str()),
+            })
+            .collect();
+        self.enum_decls.push(EnumDecl {
+            name: name.to_string(),
+            variants,
+            span: value.span(),
+        });
+        Ok(())
+    }
+
+    // `name: Fn { args: { a: Int }, return: Int, body: { ... } }`, returns the
+    // name the function is compiled under
+    fn function(&mut self, name: &str, value: &Expr) -> Result<String, LowerError> {
+        let block = fn_block(value).unwrap();
+        let signature = match self.types.of(value) {
+            Type::Function(signature) => *signature,
+            _ => FunctionType {
+                params: Vec::new(),
+                ret: Type::Unknown,
+            },
+        };
+        let name = match self.function.name.as_str() {
+            INIT => name.to_string(),
+            outer => {
+                self.lifted += 1;
+                format!("{}.{}{}", outer, name, self.lifted)
+            }
+        };
+
+        let mut params = Vec::new();
+        let mut body = None;
+        for expr in items(block) {
+            match assignment(expr) {
+                Some(("args", _, args)) => {
+                    for (arg, (_, ty)) in items(args).iter().zip(&signature.params) {
+                        let param = match assignment(arg) {
+                            Some((param, span, _)) => Some((param, span)),
+                            None => identifier(arg),
+                        };
+                        if let Some(id) =
+                            param.and_then(|(param, span)| self.declared.get(&(span, param)))
+                        {
+                            params.push((self.symbol(*id), ty.clone()));
+                        }
+                    }
+                }
+                Some(("body" | "fn", _, value)) => body = Some(value),
+                _ => {}
+            }
+        }
+
+        let outer = std::mem::replace(&mut self.function, Function::new(&name));
+        self.function.block = Some(block.span());
+        self.function.params = params.clone();
+        let body = match body {
+            Some(body) => self.block(items(body)),
+            None => Ok(Node::Block(Vec::new(), Type::Void)),
+        };
+        let function = std::mem::replace(&mut self.function, outer);
+        self.fn_decls.push(FnDecl {
+            name: name.clone(),
+            params,
+            ret: signature.ret,
+            locals: function.locals,
+            result: function.result,
+            body: body?,
+            span: value.span(),
+        });
+        Ok(name)
+    }
+
+    fn block(&mut self, exprs: &[Expr]) -> Result<Node, LowerError> {
+        let nodes = exprs
+            .iter()
+            .map(|expr| self.expr(expr))
+            .collect::<Result<Vec<Node>, LowerError>>()?;
+        let ty = nodes.last().map_or(Type::Void, |node| node.ty());
+        Ok(Node::Block(nodes, ty))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Node, LowerError> {
+        let ty = self.types.of(expr);
+        let span = expr.span();
+        match expr {
+            Expr::Atom(Atom::Literal(literal), _, _) => self.literal(literal, ty, span),
+            Expr::Atom(Atom::Identifier(name), span, _) => self.variable(name, *span, ty),
+            Expr::Atom(Atom::EndOfFile, _, _) => Ok(Node::Void),
+            Expr::Unary(Unary {
+                op: UnaryOp::SpreadExpr,
+                ..
+            }) => Err(LowerError::unsupported("`...` outside of a record", span)),
+            Expr::Unary(unary) => Ok(Node::Unary {
+                op: unary.op.clone(),
+                operand: Box::new(self.expr(&unary.expr)?),
+                ty,
+            }),
+            Expr::Binary(binary) => self.binary(binary, ty),
+            Expr::Comparison(comparison) => self.comparison(comparison),
+            Expr::Ternary(Ternary {
+                left,
+                middle,
+                right,
+                span,
+                ..
+            }) => self.invoke(left, middle, Some(right), *span, ty),
+            Expr::Group(group) => match group.op {
+                GroupOp::AnonymousType | GroupOp::AssignmentBlock => {
+                    self.record(&group.exprs, ty, span)
+                }
+                GroupOp::StatementBlock if group.exprs.iter().any(is_spread) => {
+                    self.record(&group.exprs, ty, span)
+                }
+                GroupOp::StatementBlock => self.block(&group.exprs),
+                GroupOp::ParamBlock => Err(LowerError::unsupported("a param block", span)),
+            },
+            Expr::InterpolatedString(string) => {
+                let mut parts = Vec::new();
+                for part in &string.parts {
+                    let part = self.expr(part)?;
+                    parts.push(self.text(part)?);
+                }
+                Ok(Node::Format(parts))
+            }
+            Expr::Directive(directive) => Err(LowerError::unsupported(
+                format!("`@{}`", directive.name),
+                span,
+            )),
+            Expr::Error(_, _) => Err(LowerError::unsupported(
+                "an expression that failed to parse",
+                span,
+            )),
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal, ty: Type, span: Span) -> Result<Node, LowerError> {
+        Ok(match literal {
+            Literal::IntLiteral(value, _)
+            | Literal::OctalLiteral(value, _)
+            | Literal::HexLiteral(value, _)
+            | Literal::BinaryLiteral(value, _) => match (i128::try_from(*value), &ty) {
+                (Ok(value), Type::Float(_)) => Node::Float(value as f64, ty),
+                (Ok(value), _) => Node::Int(value, ty),
+                (Err(_), _) => {
+                    return Err(LowerError {
+                        message: "integer literal is too large".to_string(),
+                        span,
+                    })
+                }
+            },
+            Literal::FloatLiteral(value, _) => Node::Float(*value, ty),
+            Literal::BoolLiteral(value) => Node::Bool(*value),
+            Literal::CharLiteral(value) => Node::Char(*value),
+            Literal::StringLiteral(value) => Node::String(value.clone()),
+        })
+    }
+
+    fn variable(&mut self, name: &str, span: Span, ty: Type) -> Result<Node, LowerError> {
+        let id = match self.resolution.uses.get(&span) {
+            Some(id) => *id,
+            None => {
+                return Err(LowerError::unsupported(
+                    format!("`{}` as a value", name),
+                    span,
+                ))
+            }
+        };
+        if let Some(function) = self.functions.get(&id) {
+            return Ok(Node::FnRef(function.clone(), ty));
+        }
+        if let Type::Meta(_) = ty {
+            return Err(LowerError::unsupported("a type used as a value", span));
+        }
+        // `args` is a record of the parameters
+        let declaration = &self.resolution.declarations[id];
+        if declaration.name == "args" && Some(declaration.span) == self.function.block {
+            let fields = self
+                .function
+                .params
+                .iter()
+                .map(|(param, ty)| (param.name.clone(), Node::Var(param.clone(), ty.clone())))
+                .collect();
+            return Ok(Node::Construct { ty, fields });
+        }
+        Ok(Node::Var(self.symbol(id), ty))
+    }
+
+    fn binary(&mut self, binary: &Binary, ty: Type) -> Result<Node, LowerError> {
+        match binary.op {
+            BinaryOp::Assignment | BinaryOp::Reassignment => self.assign(
+                &binary.left,
+                &binary.right,
+                binary.op == BinaryOp::Assignment,
+            ),
+            BinaryOp::Accessor => self.accessor(binary),
+            BinaryOp::Invoke => self.invoke(&binary.left, &binary.right, None, binary.span, ty),
+            BinaryOp::TypeDef => self.typedef(binary, ty),
+            BinaryOp::FieldDef => Err(LowerError::unsupported("a field definition", binary.span)),
+            ref op if op.is_comparison() => {
+                let (left, right) = (self.expr(&binary.left)?, self.expr(&binary.right)?);
+                self.compare(op.clone(), left, right, binary.span)
+            }
+            _ => Ok(Node::Binary {
+                op: binary.op.clone(),
+                left: Box::new(self.expr(&binary.left)?),
+                right: Box::new(self.expr(&binary.right)?),
+                ty,
+            }),
+        }
+    }
+
+    // `a < b < c` is `a < b && b < c` with `b` evaluated once
+    fn comparison(&mut self, comparison: &Comparison) -> Result<Node, LowerError> {
+        let mut lets = Vec::new();
+        let last = comparison.operands.len() - 1;
+        let mut operands = Vec::new();
+        for (i, operand) in comparison.operands.iter().enumerate() {
+            let node = self.expr(operand)?;
+            operands.push(match i == 0 || i == last {
+                true => node,
+                false => self.hoist(node, &mut lets),
+            });
+        }
+        let mut compared = None;
+        for (op, pair) in comparison.ops.iter().zip(operands.windows(2)) {
+            let node = self.compare(
+                op.clone(),
+                pair[0].clone(),
+                pair[1].clone(),
+                comparison.span,
+            )?;
+            compared = Some(match compared {
+                None => node,
+                Some(left) => Node::Binary {
+                    op: BinaryOp::And,
+                    left: Box::new(left),
+                    right: Box::new(node),
+                    ty: Type::Bool,
+                },
+            });
+        }
+        lets.push(compared.unwrap());
+        Ok(Node::Block(lets, Type::Bool))
+    }
+
+    // enums are equal when they are the same variant, carrying equal payloads
+    fn compare(
+        &mut self,
+        op: BinaryOp,
+        left: Node,
+        right: Node,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        if let Node::Payload(value, Type::Unknown) = left {
+            return self.each_payload(*value, Type::Bool, |this, payload| {
+                match mismatched(&op, &payload.ty(), &right.ty()) {
+                    Some(compared) => Ok(compared),
+                    None => this.compare(op.clone(), payload, right.clone(), span),
+                }
+            });
+        }
+        if let Node::Payload(value, Type::Unknown) = right {
+            return self.each_payload(*value, Type::Bool, |this, payload| {
+                match mismatched(&op, &left.ty(), &payload.ty()) {
+                    Some(compared) => Ok(compared),
+                    None => this.compare(op.clone(), left.clone(), payload, span),
+                }
+            });
+        }
+        let (left, right) = match left.ty() {
+            Type::Enum(enumeration) if matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) => {
+                if has_payloads(&enumeration) {
+                    return self.compare_payloads(&enumeration, op, left, right, span);
+                }
+                (
+                    Node::Tag(Box::new(left), tag_type()),
+                    Node::Tag(Box::new(right), tag_type()),
+                )
+            }
+            _ => (left, right),
+        };
+        Ok(Node::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+            ty: Type::Bool,
+        })
+    }
+
+    // the tags first, the payloads are only read when the variants match
+    fn compare_payloads(
+        &mut self,
+        enumeration: &EnumType,
+        op: BinaryOp,
+        left: Node,
+        right: Node,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let mut lets = Vec::new();
+        let left = self.hoist(left, &mut lets);
+        let right = self.hoist(right, &mut lets);
+        let mut payloads = Vec::new();
+        for variant in &enumeration.variants {
+            payloads.push(match &variant.kind {
+                VariantKind::Payload(ty) => self.compare(
+                    BinaryOp::Equal,
+                    Node::Payload(Box::new(left.clone()), ty.clone()),
+                    Node::Payload(Box::new(right.clone()), ty.clone()),
+                    span,
+                )?,
+                // a variant declared with a value always carries the same one
+                _ => Node::Bool(true),
+            });
+        }
+        let same_variant = Node::Binary {
+            op: BinaryOp::Equal,
+            left: Box::new(Node::Tag(Box::new(left.clone()), tag_type())),
+            right: Box::new(Node::Tag(Box::new(right), tag_type())),
+            ty: Type::Bool,
+        };
+        let equal = Node::Binary {
+            op: BinaryOp::And,
+            left: Box::new(same_variant),
+            right: Box::new(self.by_variant(left, payloads, Type::Bool)),
+            ty: Type::Bool,
+        };
+        let compared = match op {
+            BinaryOp::NotEqual => Node::Unary {
+                op: UnaryOp::Not,
+                operand: Box::new(equal),
+                ty: Type::Bool,
+            },
+            _ => equal,
+        };
+        Ok(with_lets(lets, compared))
+    }
+
+    // `name: value` declares `name` the first time, later ones assign to it
+    fn assign(&mut self, target: &Expr, value: &Expr, declare: bool) -> Result<Node, LowerError> {
+        match target {
+            Expr::Atom(Atom::Identifier(name), span, _) => {
+                let value = match fn_block(value) {
+                    Some(_) => {
+                        let ty = self.types.of(value);
+                        Node::FnRef(self.function(name, value)?, ty)
+                    }
+                    None => self.expr(value)?,
+                };
+                if let (Some(id), true) = (self.declared.get(&(*span, name.as_str())), declare) {
+                    let symbol = self.symbol(*id);
+                    self.local(&symbol, self.declaration_type(*id));
+                    return Ok(Node::Let(symbol, Box::new(value)));
+                }
+                let id = match self.resolution.uses.get(span) {
+                    Some(id) => *id,
+                    None => {
+                        return Err(LowerError::unsupported(
+                            format!("assigning to `{}`", name),
+                            *span,
+                        ))
+                    }
+                };
+                let symbol = self.symbol(id);
+                // `return: value` sets what the function returns
+                if name == "return" && !symbol.global {
+                    self.local(&symbol, self.declaration_type(id));
+                    self.function.result = Some(symbol.clone());
+                }
+                Ok(Node::Set(symbol, Box::new(value)))
+            }
+            Expr::Binary(Binary {
+                op: BinaryOp::Accessor,
+                ..
+            }) => {
+                let mut chain = Vec::new();
+                accessor_chain(target, &mut chain);
+                let value = self.expr(value)?;
+                self.set_field(&chain, value)
+            }
+            // `{ name, age }: person`
+            Expr::Group(group) => {
+                let mut nodes = Vec::new();
+                let record = self.expr(value)?;
+                let record = self.hoist(record, &mut nodes);
+                let record_ty = record.ty();
+                for expr in &group.exprs {
+                    let (name, span) = match identifier(expr) {
+                        Some(field) => field,
+                        None => return Err(LowerError::unsupported("this target", expr.span())),
+                    };
+                    let field = Node::FieldGet {
+                        record: Box::new(record.clone()),
+                        field: name.to_string(),
+                        ty: typeck::member(&record_ty, name).unwrap_or_default(),
+                    };
+                    let symbol = match (
+                        self.declared.get(&(span, name)),
+                        self.resolution.uses.get(&span),
+                    ) {
+                        (Some(id), _) | (None, Some(id)) => *id,
+                        (None, None) => continue,
+                    };
+                    let symbol_id = symbol;
+                    let symbol = self.symbol(symbol_id);
+                    match self.declared.contains_key(&(span, name)) {
+                        true => {
+                            self.local(&symbol, self.declaration_type(symbol_id));
+                            nodes.push(Node::Let(symbol, Box::new(field)));
+                        }
+                        false => nodes.push(Node::Set(symbol, Box::new(field))),
+                    }
+                }
+                Ok(Node::Block(nodes, Type::Void))
+            }
+            target => Err(LowerError::unsupported("this target", target.span())),
+        }
+    }
+
+    // `a.b.c: value`
+    fn set_field(&mut self, chain: &[&Expr], value: Node) -> Result<Node, LowerError> {
+        let (last, path) = chain.split_last().unwrap();
+        let (field, span) = match identifier(last) {
+            Some(field) => field,
+            None => return Err(LowerError::unsupported("this target", last.span())),
+        };
+        let mut record = self.expr(path[0])?;
+        for expr in &path[1..] {
+            record = match identifier(expr) {
+                Some((name, span)) => self.field(record, name, span)?,
+                None => return Err(LowerError::unsupported("this target", expr.span())),
+            };
+        }
+        if typeck::member(&record.ty(), field).is_none() {
+            return Err(LowerError::unsupported("adding a field to a record", span));
+        }
+        Ok(Node::SetField {
+            record: Box::new(record),
+            field: field.to_string(),
+            value: Box::new(value),
+        })
+    }
+
+    fn field(&mut self, record: Node, name: &str, span: Span) -> Result<Node, LowerError> {
+        let ty = record.ty();
+        // `unknown` when the variants carry different types, what uses the
+        // value reads it at the type of each variant, see `each_payload`
+        if let (Type::Enum(_), "value") = (&ty, name) {
+            let value_ty = typeck::member(&ty, name).unwrap_or_default();
+            return Ok(Node::Payload(Box::new(record), value_ty));
+        }
+        match typeck::member(&ty, name) {
+            Some(field_ty) => Ok(Node::FieldGet {
+                record: Box::new(record),
+                field: name.to_string(),
+                ty: field_ty,
+            }),
+            // known once the program is checked
+            None if name == "type" => Ok(Node::String(ty.to_string())),
+            None => Err(LowerError::unsupported(
+                format!("the field `{}` of `{}`", name, ty),
+                span,
+            )),
+        }
+    }
+
+    fn accessor(&mut self, binary: &Binary) -> Result<Node, LowerError> {
+        let mut chain = Vec::new();
+        accessor_chain(&binary.left, &mut chain);
+        accessor_chain(&binary.right, &mut chain);
+        // `a.b: 1` is parsed as `a.(b: 1)`
+        if let Some(Expr::Binary(Binary {
+            op: BinaryOp::Assignment | BinaryOp::Reassignment,
+            left,
+            right,
+            ..
+        })) = chain.last()
+        {
+            let value = self.expr(right)?;
+            let mut path = chain[..chain.len() - 1].to_vec();
+            path.push(left);
+            return self.set_field(&path, value);
+        }
+        if let Some(io) = self.io(&chain)? {
+            return Ok(io);
+        }
+
+        // a type is only a receiver, `Int.I32(5)`
+        let mut receiver_ty = self.types.of(chain[0]);
+        let mut node = match receiver_ty {
+            Type::Meta(_) => None,
+            _ => Some(self.expr(chain[0])?),
+        };
+        for expr in &chain[1..] {
+            let next = match (expr, node.take()) {
+                (Expr::Atom(Atom::Identifier(name), span, _), Some(record)) => {
+                    self.field(record, name, *span)?
+                }
+                (Expr::Atom(Atom::Identifier(name), span, _), None)
+                    if enum_meta(&receiver_ty).is_some() =>
+                {
+                    self.variant(&receiver_ty, name, None, *span)?
+                }
+                (
+                    Expr::Binary(Binary {
+                        op: BinaryOp::Invoke,
+                        left,
+                        right,
+                        span,
+                        ..
+                    }),
+                    receiver,
+                ) => self.method(receiver, &receiver_ty, left, right, None, *span)?,
+                (
+                    Expr::Ternary(Ternary {
+                        left,
+                        middle,
+                        right,
+                        span,
+                        ..
+                    }),
+                    receiver,
+                ) => self.method(receiver, &receiver_ty, left, middle, Some(right), *span)?,
+                (expr, _) => {
+                    return Err(LowerError::unsupported(
+                        "this expression after `.`",
+                        expr.span(),
+                    ))
+                }
+            };
+            receiver_ty = next.ty();
+            node = Some(next);
+        }
+        node.ok_or_else(|| LowerError::unsupported("a type used as a value", binary.span))
+    }
+
+    // `io.std.writeLine(a)`, `io.println(a)`, `std.io.stdout.write(a)` and
+    // `std.io.stdin.readLine()`
+    fn io(&mut self, chain: &[&Expr]) -> Result<Option<Node>, LowerError> {
+        let root = match identifier(chain[0]) {
+            Some((root @ ("io" | "std"), span)) if !self.resolution.uses.contains_key(&span) => {
+                root
+            }
+            _ => return Ok(None),
+        };
+        let mut path = Vec::new();
+        for expr in &chain[1..chain.len() - 1] {
+            match identifier(expr) {
+                Some((name, _)) => path.push(name),
+                None => return Ok(None),
+            }
+        }
+        let (name, args) = match chain.last() {
+            Some(Expr::Binary(Binary {
+                op: BinaryOp::Invoke,
+                left,
+                right,
+                ..
+            })) => match identifier(left) {
+                Some((name, _)) => (name, right),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let newline = match (root, &path[..], name) {
+            ("io", ["std"], "write") | ("io", [], "print") | ("std", ["io", "stdout"], "write") => {
+                false
+            }
+            ("io", ["std"], "writeLine")
+            | ("io", [], "println")
+            | ("std", ["io", "stdout"], "writeLine") => true,
+            ("std", ["io", "stdin"], "readLine") if items(args).is_empty() => {
+                return Ok(Some(Node::ReadLine))
+            }
+            _ => {
+                return Err(LowerError::unsupported(
+                    format!("this part of `{}`", root),
+                    chain[0].span(),
+                ))
+            }
+        };
+        let (args, _) = self.arguments(args)?;
+        let args = args
+            .into_iter()
+            .map(|arg| self.text(arg))
+            .collect::<Result<Vec<Node>, LowerError>>()?;
+        Ok(Some(Node::Print { args, newline }))
+    }
+
+    fn arguments(&mut self, args: &Expr) -> Result<Arguments, LowerError> {
+        let (mut positional, mut named) = (Vec::new(), Vec::new());
+        for expr in items(args) {
+            match assignment(expr) {
+                Some((name, _, value)) => named.push((name.to_string(), self.expr(value)?)),
+                None => positional.push(self.expr(expr)?),
+            }
+        }
+        Ok((positional, named))
+    }
+
+    // named arguments are put in parameter order, which is also the order
+    // they are evaluated in
+    fn bind(
+        &mut self,
+        function: &FunctionType,
+        receiver: Option<Node>,
+        positional: Vec<Node>,
+        named: Vec<(String, Node)>,
+        span: Span,
+    ) -> Result<Vec<Node>, LowerError> {
+        let params = &function.params;
+        let mut bound: Vec<Option<Node>> = vec![None; params.len()];
+        if let Some(receiver) = receiver {
+            let index = params
+                .iter()
+                .position(|(param, _)| param == "self")
+                .unwrap_or(0);
+            if let Some(slot) = bound.get_mut(index) {
+                *slot = Some(receiver);
+            }
+        }
+        for node in positional {
+            if let Some(slot) = bound.iter_mut().find(|slot| slot.is_none()) {
+                *slot = Some(node);
+            }
+        }
+        for (name, node) in named {
+            if let Some(index) = params.iter().position(|(param, _)| *param == name) {
+                bound[index] = Some(node);
+            }
+        }
+        params
+            .iter()
+            .zip(bound)
+            .map(|((param, _), node)| {
+                node.ok_or_else(|| LowerError {
+                    message: format!("missing argument `{}`", param),
+                    span,
+                })
+            })
+            .collect()
+    }
+
+    fn call(
+        &mut self,
+        callee: Callee,
+        function: &FunctionType,
+        receiver: Option<Node>,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        if let Some(block) = block {
+            return Err(LowerError::unsupported(
+                "passing a block to a function",
+                block.span(),
+            ));
+        }
+        let (positional, named) = self.arguments(args)?;
+        let args = self.bind(function, receiver, positional, named, span)?;
+        Ok(Node::Call {
+            callee,
+            args,
+            ty: function.ret.clone(),
+        })
+    }
+
+    fn invoke(
+        &mut self,
+        callee: &Expr,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+        ty: Type,
+    ) -> Result<Node, LowerError> {
+        let builtin = identifier(callee)
+            .filter(|(name, span)| *name == "return" || !self.resolution.uses.contains_key(span));
+        if let Some((name, _)) = builtin {
+            return self.builtin(name, args, block, span);
+        }
+        let callee_ty = self.types.of(callee);
+        if let Some(record) = record_type(&callee_ty) {
+            let record = record.clone();
+            let (positional, mut named) = self.arguments(args)?;
+            if let Some(block) = block {
+                named.extend(self.fields(items(block), &mut Vec::new())?);
+            }
+            return self.construct(&record, positional, named, ty, span);
+        }
+        // `Rgb(0)`
+        if let Some(enumeration) = enum_meta(&callee_ty) {
+            let enumeration = enumeration.clone();
+            let (positional, _) = self.arguments(args)?;
+            let index = positional.into_iter().next().ok_or_else(|| LowerError {
+                message: "missing argument `index`".to_string(),
+                span,
+            })?;
+            return self.pick(&enumeration, index, span, |tag, _, index| Node::Binary {
+                op: BinaryOp::Equal,
+                left: Box::new(index),
+                right: Box::new(Node::Int(tag as i128, Type::Int(None))),
+                ty: Type::Bool,
+            });
+        }
+        match callee_ty {
+            Type::Function(function) => {
+                let target = identifier(callee)
+                    .and_then(|(_, span)| self.resolution.uses.get(&span))
+                    .and_then(|id| self.functions.get(id));
+                let target = match target {
+                    Some(name) => Callee::Fn(name.clone()),
+                    None => Callee::Value(Box::new(self.expr(callee)?)),
+                };
+                self.call(target, &function, None, args, block, span)
+            }
+            ty => Err(LowerError::unsupported(format!("calling `{}`", ty), span)),
+        }
+    }
+
+    fn builtin(
+        &mut self,
+        name: &str,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let (positional, _) = self.arguments(args)?;
+        let mut positional = positional.into_iter();
+        let mut argument = || positional.next();
+        Ok(match name {
+            "if" | "while" => {
+                let cond = Box::new(argument().unwrap_or(Node::Bool(false)));
+                let (body, otherwise) = match block {
+                    Some(block) => branches(block),
+                    None => (None, None),
+                };
+                let then = match body {
+                    Some(body) => self.block(items(body))?,
+                    None => Node::Block(Vec::new(), Type::Void),
+                };
+                if name == "while" {
+                    return Ok(Node::Loop {
+                        cond,
+                        body: Box::new(then),
+                    });
+                }
+                let otherwise = match otherwise {
+                    Some(otherwise) => Some(self.block(items(otherwise))?),
+                    None => None,
+                };
+                // a value only when both branches give the same type
+                let ty = match &otherwise {
+                    Some(otherwise) if otherwise.ty() == then.ty() => then.ty(),
+                    _ => Type::Void,
+                };
+                Node::If {
+                    cond,
+                    then: Box::new(then),
+                    otherwise: otherwise.map(Box::new),
+                    ty,
+                }
+            }
+            "return" => Node::Return(Box::new(argument().unwrap_or(Node::Void))),
+            "exit" => Node::Exit(Box::new(
+                argument().unwrap_or(Node::Int(0, Type::Int(None))),
+            )),
+            "Int" | "Float" | "Bool" | "Char" | "String" => {
+                let ty = match name {
+                    "Int" => Type::Int(None),
+                    "Float" => Type::Float(None),
+                    "Bool" => Type::Bool,
+                    "Char" => Type::Char,
+                    _ => Type::String,
+                };
+                self.convert(argument(), ty, span)?
+            }
+            name => return Err(LowerError::unsupported(format!("`{}`", name), span)),
+        })
+    }
+
+    fn convert(&mut self, value: Option<Node>, ty: Type, span: Span) -> Result<Node, LowerError> {
+        match value {
+            // `Int(color)` is the index of the variant
+            Some(value) if matches!((value.ty(), &ty), (Type::Enum(_), Type::Int(_))) => {
+                Ok(Node::Tag(Box::new(value), ty))
+            }
+            Some(value) if ty == Type::String && value.ty() != Type::String => self.string(value),
+            Some(Node::Payload(value, Type::Unknown)) => {
+                self.each_payload(*value, ty.clone(), |this, payload| {
+                    this.convert(Some(payload), ty.clone(), span)
+                })
+            }
+            Some(value) if value.ty() == ty => Ok(value),
+            Some(value) => Ok(Node::Convert(Box::new(value), ty)),
+            None => zero(&ty).ok_or_else(|| LowerError::unsupported(format!("`{}()`", ty), span)),
+        }
+    }
+
+    // `value.name(args)` calls a function field of `value`, or else the function
+    // `name` with `value` as its `self`
+    fn method(
+        &mut self,
+        receiver: Option<Node>,
+        receiver_ty: &Type,
+        callee: &Expr,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let (name, name_span) = match identifier(callee) {
+            Some(callee) => callee,
+            None => return Err(LowerError::unsupported("this call", span)),
+        };
+        let receiver = match (receiver, typeck::member(receiver_ty, name)) {
+            // `Int.I32(5)`
+            (None, Some(Type::Meta(ty))) => {
+                let (positional, _) = self.arguments(args)?;
+                return self.convert(positional.into_iter().next(), *ty, span);
+            }
+            (None, _) if enum_meta(receiver_ty).is_some() => {
+                return self.enum_method(receiver_ty, name, args, span)
+            }
+            (None, _) if *receiver_ty == Type::Meta(Box::new(Type::String)) && name == "parse" => {
+                let (positional, _) = self.arguments(args)?;
+                return self.parse_string(positional.into_iter().next(), span);
+            }
+            (None, _) => return Err(LowerError::unsupported("a function of a type", span)),
+            (Some(receiver), _) => receiver,
+        };
+        let mut lets = Vec::new();
+        let call = match typeck::member(receiver_ty, name) {
+            Some(Type::Function(function)) => {
+                let with_self = function.params.iter().any(|(param, _)| param == "self");
+                let receiver = match with_self {
+                    true => self.hoist(receiver, &mut lets),
+                    false => receiver,
+                };
+                let field = self.field(receiver.clone(), name, name_span)?;
+                let receiver = with_self.then_some(receiver);
+                self.call(
+                    Callee::Value(Box::new(field)),
+                    &function,
+                    receiver,
+                    args,
+                    block,
+                    span,
+                )?
+            }
+            Some(ty) => return Err(LowerError::unsupported(format!("calling `{}`", ty), span)),
+            None => {
+                let id = match self.resolution.uses.get(&name_span) {
+                    Some(id) => *id,
+                    None => return Err(LowerError::unsupported(format!("`{}`", name), name_span)),
+                };
+                let function = match self.declaration_type(id) {
+                    Type::Function(function) => function,
+                    ty => return Err(LowerError::unsupported(format!("calling `{}`", ty), span)),
+                };
+                let target = match self.functions.get(&id) {
+                    Some(name) => Callee::Fn(name.clone()),
+                    None => Callee::Value(Box::new(Node::Var(
+                        self.symbol(id),
+                        Type::Function(function.clone()),
+                    ))),
+                };
+                self.call(target, &function, Some(receiver), args, block, span)?
+            }
+        };
+        Ok(with_lets(lets, call))
+    }
+
+    // `Rgb.RED`, or `Token.Comment` with the payload it was called with
+    fn variant(
+        &mut self,
+        ty: &Type,
+        name: &str,
+        payload: Option<Node>,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let enumeration = match enum_meta(ty) {
+            Some(enumeration) => enumeration.clone(),
+            None => return Err(LowerError::unsupported(format!("`{}`", ty), span)),
+        };
+        let (tag, kind) = match enumeration.variant(name) {
+            Some((tag, variant)) => (tag, variant.kind.clone()),
+            None => {
+                return Err(LowerError {
+                    message: format!("`{}` has no variant `{}`", ty, name),
+                    span,
+                })
+            }
+        };
+        let payload = match (kind, payload) {
+            (VariantKind::Plain, _) => None,
+            (VariantKind::Value(_), _) => match self.variant_value(&enumeration, tag) {
+                Some(value) => Some(value),
+                None => return Err(LowerError::unsupported(format!("`{}.{}`", ty, name), span)),
+            },
+            (VariantKind::Payload(_), Some(payload)) => Some(payload),
+            (VariantKind::Payload(_), None) => {
+                return Err(LowerError {
+                    message: "missing argument `value`".to_string(),
+                    span,
+                })
+            }
+        };
+        Ok(Node::Variant {
+            ty: Type::Enum(enumeration),
+            tag,
+            payload: payload.map(Box::new),
+        })
+    }
+
+    // the value the variant was declared with, `"GBP"` in `GBP: "GBP"`
+    fn variant_value(&self, enumeration: &EnumType, tag: usize) -> Option<Node> {
+        let decl = self
+            .enum_decls
+            .iter()
+            .find(|decl| Some(&decl.name) == enumeration.name.as_ref())?;
+        decl.variants.get(tag)?.value.clone()
+    }
+
+    // `Rgb.parse("RED")` and `Token.Comment(text)`
+    fn enum_method(
+        &mut self,
+        ty: &Type,
+        name: &str,
+        args: &Expr,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let (positional, _) = self.arguments(args)?;
+        let argument = positional.into_iter().next();
+        let enumeration = enum_meta(ty).cloned().unwrap_or_default();
+        if name == "parse" && enumeration.variant(name).is_none() {
+            let string = argument.ok_or_else(|| LowerError {
+                message: "missing argument `string`".to_string(),
+                span,
+            })?;
+            return self.pick(&enumeration, string, span, |_, name, string| Node::Binary {
+                op: BinaryOp::Equal,
+                left: Box::new(string),
+                right: Box::new(Node::String(name.to_string())),
+                ty: Type::Bool,
+            });
+        }
+        self.variant(ty, name, argument, span)
+    }
+
+    // the first variant `condition` holds for, with `value` evaluated once, a
+    // program that asks for one that doesn't exist stops
+    fn pick(
+        &mut self,
+        enumeration: &EnumType,
+        value: Node,
+        span: Span,
+        condition: impl Fn(usize, &str, Node) -> Node,
+    ) -> Result<Node, LowerError> {
+        let ty = Type::Enum(enumeration.clone());
+        if has_payloads(enumeration) {
+            return Err(LowerError {
+                message: format!(
+                    "`{}` has variants with a payload, so it can't be made from an index or a name",
+                    ty
+                ),
+                span,
+            });
+        }
+        let mut lets = Vec::new();
+        let value = self.hoist(value, &mut lets);
+        let mut node = no_variant();
+        for (tag, variant) in enumeration.variants.iter().enumerate().rev() {
+            let payload = match variant.kind {
+                VariantKind::Value(_) => self.variant_value(enumeration, tag),
+                _ => None,
+            };
+            node = Node::If {
+                cond: Box::new(condition(tag, &variant.name, value.clone())),
+                then: Box::new(Node::Variant {
+                    ty: ty.clone(),
+                    tag,
+                    payload: payload.map(Box::new),
+                }),
+                otherwise: Some(Box::new(node)),
+                ty: ty.clone(),
+            };
+        }
+        Ok(with_lets(lets, node))
+    }
+
+    // `String.parse(currency)` is the value a variant was declared with when
+    // that is a string, and its name otherwise
+    fn parse_string(&mut self, value: Option<Node>, span: Span) -> Result<Node, LowerError> {
+        let value = match value {
+            Some(value) => value,
+            None => return self.convert(None, Type::String, span),
+        };
+        let enumeration = match value.ty() {
+            Type::Enum(enumeration) => enumeration,
+            _ => return self.convert(Some(value), Type::String, span),
+        };
+        let names = enumeration
+            .variants
+            .iter()
+            .enumerate()
+            .map(|(tag, variant)| match variant.kind {
+                VariantKind::Value(Type::String) => self
+                    .variant_value(&enumeration, tag)
+                    .unwrap_or_else(|| Node::String(variant.name.clone())),
+                _ => Node::String(variant.name.clone()),
+            })
+            .collect();
+        Ok(self.by_variant(value, names, Type::String))
+    }
+
+    // enums are printed and turned into strings as `Rgb.RED`, everything else
+    // is left to the backends
+    fn text(&mut self, node: Node) -> Result<Node, LowerError> {
+        if let Node::Payload(value, Type::Unknown) = node {
+            return self.each_payload(*value, Type::String, |this, payload| this.string(payload));
+        }
+        let enumeration = match node.ty() {
+            Type::Enum(enumeration) => enumeration,
+            _ => return Ok(node),
+        };
+        let ty = Type::Enum(enumeration.clone());
+        let names = enumeration
+            .variants
+            .iter()
+            .map(|variant| Node::String(format!("{}.{}", ty, variant.name)))
+            .collect();
+        Ok(self.by_variant(node, names, Type::String))
+    }
+
+    // `String(x)`, the backends turn values into text the way they print them
+    fn string(&mut self, node: Node) -> Result<Node, LowerError> {
+        let text = self.text(node)?;
+        match text.ty() {
+            Type::String => Ok(text),
+            _ => Ok(Node::Format(vec![text])),
+        }
+    }
+
+    // `then` with the payload of `value` read at the type its variant
+    // carries, a variant without one stops the program like the interpreter
+    fn each_payload(
+        &mut self,
+        value: Node,
+        ty: Type,
+        mut then: impl FnMut(&mut Self, Node) -> Result<Node, LowerError>,
+    ) -> Result<Node, LowerError> {
+        let variants = match value.ty() {
+            Type::Enum(enumeration) => enumeration.variants,
+            _ => Vec::new(),
+        };
+        let mut lets = Vec::new();
+        let value = self.hoist(value, &mut lets);
+        let mut nodes = Vec::new();
+        for variant in variants {
+            nodes.push(match variant.kind {
+                VariantKind::Value(payload) | VariantKind::Payload(payload) => {
+                    then(self, Node::Payload(Box::new(value.clone()), payload))?
+                }
+                VariantKind::Plain => no_variant(),
+            });
+        }
+        let node = self.by_variant(value, nodes, ty);
+        Ok(with_lets(lets, node))
+    }
+
+    // `nodes[tag]` for the variant `value` is
+    fn by_variant(&mut self, value: Node, mut nodes: Vec<Node>, ty: Type) -> Node {
+        let mut lets = Vec::new();
+        let value = self.hoist(value, &mut lets);
+        let mut node = nodes.pop().unwrap_or_else(no_variant);
+        for (tag, then) in nodes.into_iter().enumerate().rev() {
+            let cond = Node::Binary {
+                op: BinaryOp::Equal,
+                left: Box::new(Node::Tag(Box::new(value.clone()), tag_type())),
+                right: Box::new(Node::Int(tag as i128, tag_type())),
+                ty: Type::Bool,
+            };
+            node = Node::If {
+                cond: Box::new(cond),
+                then: Box::new(then),
+                otherwise: Some(Box::new(node)),
+                ty: ty.clone(),
+            };
+        }
+        with_lets(lets, node)
+    }
+
+    fn typedef(&mut self, binary: &Binary, ty: Type) -> Result<Node, LowerError> {
+        let (left, block, span) = (&*binary.left, &*binary.right, binary.span);
+        let builtin = identifier(left).filter(|(_, span)| !self.resolution.uses.contains_key(span));
+        match builtin {
+            Some(("Fn" | "Function", _)) => {
+                let name = self.function("fn", &Expr::Binary(binary.clone()))?;
+                return Ok(Node::FnRef(name, ty));
+            }
+            Some(("Type", _)) => {
+                return Err(LowerError::unsupported("a type used as a value", span))
+            }
+            Some(("Enum", _)) => {
+                return Err(LowerError::unsupported("an `Enum` inside a function", span))
+            }
+            _ => {}
+        }
+        let callee_ty = self.types.of(left);
+        match record_type(&callee_ty) {
+            // `Person { name: "John", age }`
+            Some(record) => {
+                let record = record.clone();
+                let mut lets = Vec::new();
+                let named = self.fields(items(block), &mut lets)?;
+                let construct = self.construct(&record, Vec::new(), named, ty.clone(), span)?;
+                Ok(with_lets(lets, construct))
+            }
+            None => Err(LowerError::unsupported(
+                "passing a block to a function",
+                block.span(),
+            )),
+        }
+    }
+
+    // `{ name: "John", ...address, age }`, a spread value is evaluated once
+    // into `lets`
+    fn fields(
+        &mut self,
+        exprs: &[Expr],
+        lets: &mut Vec<Node>,
+    ) -> Result<Vec<(String, Node)>, LowerError> {
+        let mut fields = Vec::new();
+        for expr in exprs {
+            match expr {
+                Expr::Unary(Unary {
+                    op: UnaryOp::SpreadExpr,
+                    expr,
+                    span,
+                    ..
+                }) => {
+                    let spread = self.expr(expr)?;
+                    let spread = self.hoist(spread, lets);
+                    let names: Vec<String> = match spread.ty() {
+                        Type::Record(record) => record
+                            .fields
+                            .iter()
+                            .map(|field| field.name.clone())
+                            .collect(),
+                        _ => return Err(LowerError::unsupported("this spread", *span)),
+                    };
+                    for name in names {
+                        let field = self.field(spread.clone(), &name, *span)?;
+                        fields.push((name, field));
+                    }
+                }
+                expr => match (assignment(expr), identifier(expr)) {
+                    (Some((name, _, value)), _) => {
+                        fields.push((name.to_string(), self.expr(value)?))
+                    }
+                    (_, Some((name, _))) => fields.push((name.to_string(), self.expr(expr)?)),
+                    _ => {
+                        return Err(LowerError::unsupported(
+                            "this expression in a record",
+                            expr.span(),
+                        ))
+                    }
+                },
+            }
+        }
+        Ok(fields)
+    }
+
+    // a record value, `{ a: 1, ...b }`
+    fn record(&mut self, exprs: &[Expr], ty: Type, span: Span) -> Result<Node, LowerError> {
+        let record = match &ty {
+            Type::Record(record) => record.clone(),
+            _ => return Err(LowerError::unsupported("a type used as a value", span)),
+        };
+        let mut lets = Vec::new();
+        let mut fields = self.fields(exprs, &mut lets)?;
+        // a later field replaces an earlier one
+        let fields = record
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let index = fields.iter().rposition(|(name, _)| *name == field.name)?;
+                Some(fields.swap_remove(index))
+            })
+            .collect();
+        Ok(with_lets(lets, Node::Construct { ty, fields }))
+    }
+
+    // `Person(name: "John", age: 30)`, fields left out get their defaults
+    fn construct(
+        &mut self,
+        record: &RecordType,
+        positional: Vec<Node>,
+        named: Vec<(String, Node)>,
+        ty: Type,
+        span: Span,
+    ) -> Result<Node, LowerError> {
+        let mut values: Vec<Option<Node>> = vec![None; record.fields.len()];
+        for (slot, node) in values.iter_mut().zip(positional) {
+            *slot = Some(node);
+        }
+        for (name, node) in named {
+            if let Some(index) = record.fields.iter().position(|field| field.name == name) {
+                values[index] = Some(node);
+            }
+        }
+        let decl = record
+            .name
+            .as_ref()
+            .and_then(|name| self.type_decls.iter().find(|decl| decl.name == *name));
+        let mut fields = Vec::new();
+        for (field, value) in record.fields.iter().zip(values) {
+            let default = decl
+                .and_then(|decl| decl.fields.iter().find(|f| f.name == field.name))
+                .and_then(|field| field.default.clone());
+            match value.or(default) {
+                Some(value) => fields.push((field.name.clone(), value)),
+                None => {
+                    return Err(LowerError {
+                        message: format!("missing field `{}`", field.name),
+                        span,
+                    })
+                }
+            }
+        }
+        Ok(Node::Construct { ty, fields })
+    }
+}
+
+fn list(head: &str, nodes: &[Node]) -> String {
+    let mut out = format!("({}", head);
+    for node in nodes {
+        out.push(' ');
+        out.push_str(&node.to_string());
+    }
+    out.push(')');
+    out
+}
+
+// S-expressions, like `sexpr` prints the AST, for `--stop-after lower` and tests
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Int(value, _) => write!(f, "{}", value),
+            Node::Float(value, _) => write!(f, "{:?}", value),
+            Node::Bool(value) => write!(f, "{}", value),
+            Node::Char(value) => write!(f, "{:?}", value),
+            Node::String(value) => write!(f, "{:?}", value),
+            Node::Void => write!(f, "void"),
+            Node::Var(symbol, _) => write!(f, "{}", symbol.name),
+            Node::FnRef(name, _) => write!(f, "(fn {})", name),
+            Node::Let(symbol, value) => write!(f, "(let {} {})", symbol.name, value),
+            Node::Set(symbol, value) => write!(f, "(set {} {})", symbol.name, value),
+            Node::SetField {
+                record,
+                field,
+                value,
+            } => write!(f, "(set-field {} {} {})", record, field, value),
+            Node::FieldGet { record, field, .. } => write!(f, "(. {} {})", record, field),
+            Node::Construct { ty, fields } => {
+                write!(f, "(construct {}", ty)?;
+                for (name, value) in fields {
+                    write!(f, " ({} {})", name, value)?;
+                }
+                write!(f, ")")
+            }
+            Node::Unary { op, operand, .. } => write!(f, "({} {})", op.symbol(), operand),
+            Node::Binary {
+                op, left, right, ..
+            } => write!(f, "({} {} {})", op.symbol(), left, right),
+            Node::Convert(value, ty) => write!(f, "(convert {} {})", value, ty),
+            Node::Variant { ty, tag, payload } => {
+                let name = match ty {
+                    Type::Enum(enumeration) => enumeration.variants[*tag].name.as_str(),
+                    _ => "?",
+                };
+                match payload {
+                    Some(payload) => write!(f, "(variant {}.{} {})", ty, name, payload),
+                    None => write!(f, "(variant {}.{})", ty, name),
+                }
+            }
+            Node::Tag(value, _) => write!(f, "(tag {})", value),
+            Node::Payload(value, _) => write!(f, "(payload {})", value),
+            Node::Format(parts) => write!(f, "{}", list("format", parts)),
+            Node::Call { callee, args, .. } => match callee {
+                Callee::Fn(name) => write!(f, "{}", list(&format!("call {}", name), args)),
+                Callee::Value(value) => write!(f, "{}", list(&format!("call {}", value), args)),
+            },
+            Node::Print { args, newline } => match newline {
+                true => write!(f, "{}", list("println", args)),
+                false => write!(f, "{}", list("print", args)),
+            },
+            Node::ReadLine => write!(f, "(readLine)"),
+            Node::Block(nodes, _) => write!(f, "{}", list("block", nodes)),
+            Node::If {
+                cond,
+                then,
+                otherwise,
+                ..
+            } => match otherwise {
+                Some(otherwise) => write!(f, "(if {} {} {})", cond, then, otherwise),
+                None => write!(f, "(if {} {})", cond, then),
+            },
+            Node::Loop { cond, body } => write!(f, "(loop {} {})", cond, body),
+            Node::Return(value) => write!(f, "(return {})", value),
+            Node::Exit(value) => write!(f, "(exit {})", value),
+        }
+    }
+}
+
+impl std::fmt::Display for FnDecl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(param, ty)| format!("({} {})", param.name, ty))
+            .collect();
+        write!(
+            f,
+            "(fn {} ({}) {} {})",
+            self.name,
+            params.join(" "),
+            self.ret,
+            self.body
+        )
+    }
+}
+
+/// One line per type, enum, global, function and then the file's statements.
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for decl in &self.types {
+            write!(f, "(type {}", decl.name)?;
+            for field in &decl.fields {
+                match &field.default {
+                    Some(default) => write!(f, " ({} {} {})", field.name, field.ty, default)?,
+                    None => write!(f, " ({} {})", field.name, field.ty)?,
+                }
+            }
+            writeln!(f, ")")?;
+        }
+        for decl in &self.enums {
+            write!(f, "(enum {}", decl.name)?;
+            for variant in &decl.variants {
+                match (&variant.value, &variant.payload) {
+                    (Some(value), _) => write!(f, " ({} {})", variant.name, value)?,
+                    (None, Some(ty)) => write!(f, " ({} {})", variant.name, ty)?,
+                    (None, None) => write!(f, " {}", variant.name)?,
+                }
+            }
+            writeln!(f, ")")?;
+        }
+        for (symbol, ty) in &self.globals {
+            writeln!(f, "(global {} {})", symbol.name, ty)?;
+        }
+        for function in &self.functions {
+            writeln!(f, "{}", function)?;
+        }
+        write!(f, "{}", self.init)
+    }
+}
+
+#[cfg(test)]
+pub fn lower_source(source: &str) -> Result<Program, LowerError> {
+    let ast = crate::parser::Parser::new(source).parse().unwrap();
+    let resolution = crate::resolve::resolve(&ast);
+    let types = typeck::check(&ast, &resolution);
+    assert_eq!(types.errors, []);
+    lower(&ast, &resolution, &types)
+}
+
+#[test]
+fn test_lower() {
+    let program = lower_source(
+        "\
+Address: { city: String, country: \"UK\" }
+Person: Type { name: String, age: Int, ...Address }
+older: Fn {
+    args: { self: Person, years: Int }
+    return: Int
+    body: { self.age + years }
+}
+main: Fn {
+    body: {
+        jane: Person(age: 7, name: \"Jane\", city: \"Leeds\")
+        age: jane.older(years: 2)
+        io.std.writeLine(\"${jane.name} ${age}\")
+        if (1 < age * 2 <= 20) { exit(age) }
+    }
+}",
+    )
+    .unwrap();
+    assert_eq!(
+        program.to_string(),
+        "\
+(type Address (city String) (country String \"UK\"))
+(type Person (name String) (age Int) (city String) (country String \"UK\"))
+(fn older ((self Person) (years Int)) Int (block (+ (. self age) years)))
+(fn main () unknown (block \
+(let jane (construct Person (name \"Jane\") (age 7) (city \"Leeds\") (country \"UK\"))) \
+(let age (call older jane 2)) \
+(println (format (. jane name) \" \" age)) \
+(if (block (let %0 (* age 2)) (&& (< 1 %0) (<= %0 20))) (block (exit age)))))
+(fn zen.init () Void (block))"
+    );
+    assert_eq!(program.main.as_deref(), Some("main"));
+    let main = &program.functions[1];
+    assert_eq!(main.locals.len(), 3);
+
+    let error = lower_source("f: Fn { body: { Color: Enum { Red, Green } } }").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "1:24: an `Enum` inside a function can't be compiled yet"
+    );
+}
+
+#[test]
+fn test_lower_enums() {
+    let program = lower_source(
+        "\
+Rgb: Enum { RED, GREEN }
+Currency: Enum { GBP: \"GBP\" }
+Token: Enum { Comment: String, Number: Int }
+color: Rgb.parse(\"GREEN\")
+io.std.writeLine(color, Int(color), String.parse(Currency.GBP))
+token: Token.Comment(\"hi\")",
+    )
+    .unwrap();
+    assert_eq!(
+        program.to_string(),
+        "\
+(enum Rgb RED GREEN)
+(enum Currency (GBP \"GBP\"))
+(enum Token (Comment String) (Number Int))
+(global color Rgb)
+(global token Token)
+(fn zen.init () Void (block \
+(let color (block (let %0 \"GREEN\") \
+(if (== %0 \"RED\") (variant Rgb.RED) (if (== %0 \"GREEN\") (variant Rgb.GREEN) (exit 101))))) \
+(println (if (== (tag color) 0) \"Rgb.RED\" \"Rgb.GREEN\") (tag color) \
+(block (let %1 (variant Currency.GBP \"GBP\")) \"GBP\")) \
+(let token (variant Token.Comment \"hi\"))))"
+    );
+}
@@ -1,18 +1,105 @@
 use serde::{Deserialize, Serialize};
 
-use crate::token::Token;
+use crate::span::Span;
+use crate::token::{SpannedToken, StringPart, Token};
 
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub enum LexError {
+    UnterminatedString,
+    UnterminatedChar,
+    EmptyCharLiteral,
+    InvalidCharLiteral(String),
+    InvalidEscape(char),
+    InvalidUnicodeEscape(String),
+    InvalidCharacter(char),
+    NonAsciiIdentifier(String),
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::UnterminatedString => write!(f, "unterminated string literal"),
+            LexError::UnterminatedChar => write!(f, "unterminated char literal"),
+            LexError::EmptyCharLiteral => write!(f, "empty char literal"),
+            LexError::InvalidCharLiteral(value) => {
+                write!(f, "char literal must contain one character, found '{}'", value)
+            }
+            LexError::InvalidEscape(ch) => write!(f, "unknown escape sequence `\\{}`", ch),
+            LexError::InvalidUnicodeEscape(value) => {
+                write!(f, "invalid unicode escape `\\u{}`", value)
+            }
+            LexError::InvalidCharacter(ch) => write!(f, "invalid character {:?}", ch),
+            LexError::NonAsciiIdentifier(ident) => {
+                write!(f, "identifiers must be ASCII, found `{}`", ident)
+            }
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Lexer<'a> {
     pub input: &'a str,
     pub pos: usize,
     pub finished: bool,
+    pub file_id: usize,
+    // 1-based line of `pos` and the byte offset that line starts at
+    line: usize,
+    line_start: usize,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Lexer<'a> {
-        Lexer { input, pos: 0, finished: false }
+        Lexer::with_file_id(input, 0)
+    }
+
+    pub fn with_file_id(input: &'a str, file_id: usize) -> Lexer<'a> {
+        Lexer {
+            input,
+            pos: 0,
+            finished: false,
+            file_id,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    /// A lexer for `input[pos..]` whose spans are relative to the whole of `input`.
+    /// Used to lex the expressions inside of string interpolations.
+    pub fn at(input: &'a str, file_id: usize, pos: usize) -> Lexer<'a> {
+        let before = &input[..pos];
+        Lexer {
+            input,
+            pos,
+            finished: false,
+            file_id,
+            line: before.matches('\n').count() + 1,
+            line_start: before.rfind('\n').map_or(0, |offset| offset + 1),
+        }
+    }
+
+    fn column(&self, pos: usize) -> usize {
+        self.input[self.line_start..pos].chars().count() + 1
+    }
+
+    // span of `start..end` inside the token currently being read
+    fn span_at(&self, start: usize, end: usize) -> Span {
+        let before = &self.input[self.line_start..start];
+        let line = self.line + before.matches('\n').count();
+        let line_start = before
+            .rfind('\n')
+            .map_or(self.line_start, |offset| self.line_start + offset + 1);
+        let column = self.input[line_start..start].chars().count() + 1;
+        Span::new(self.file_id, start, end, line, column)
+    }
+
+    // move the line counters over everything consumed since `start`
+    fn track_lines(&mut self, start: usize) {
+        for (offset, ch) in self.input[start..self.pos].char_indices() {
+            if ch == '\n' {
+                self.line += 1;
+                self.line_start = start + offset + 1;
+            }
+        }
     }
 
     fn next_char(&self) -> Option<char> {
@@ -38,34 +125,236 @@ impl<'a> Lexer<'a> {
         result
     }
     fn read_number(&mut self) -> Token {
-        // numVal: [0-9]+(\.[0-9]+)?
-        // num: numVal | numVal [eE] [+-]? numVal | hexVal | octVal | binVal
-        // hexVal: 0[xX] [0-9a-fA-F]+
-        // octVal: 0[oO] [0-7]+
-        // binVal: 0[bB] [01]+
-        // so we can consume until we hit a non number
-        // we can parse the number to int, float, hex, oct, bin in parser
-        let s = self.read_while(|ch| {
-            ch.is_ascii_digit()
-                || ch == '.'
-                || ch == 'e'
-                || ch == 'x'
-                || ch == 'o'
-                || ch == 'b'
-                || ch == '+'
-                || ch == '-'
-        });
-        Token::NumberLiteral(s)
+        // int:    [0-9] [0-9_]*
+        // float:  int (\. [0-9] [0-9_]*)? ([eE] [+-]? [0-9_]+)?
+        // hex:    0[xX] [0-9a-fA-F_]+
+        // oct:    0[oO] [0-7_]+
+        // bin:    0[bB] [01_]+
+        // any of them may end in a suffix like `u8` or `f32`.
+        // digits that are wrong for the radix and unknown suffixes are still
+        // consumed, the parser reports them with the whole literal in view
+        let start = self.pos;
+        let is_word = |ch: char| ch.is_ascii_alphanumeric() || ch == '_';
+        let radix = ["0x", "0X", "0o", "0O", "0b", "0B"]
+            .iter()
+            .any(|prefix| self.starts_with(prefix));
+        if radix {
+            self.pos += 2;
+            self.read_while(is_word);
+            return Token::NumberLiteral(self.input[start..self.pos].to_string());
+        }
+
+        self.read_while(|ch| ch.is_ascii_digit() || ch == '_');
+        // only take the `.` if a digit follows, so `1.abs()` and `1..2` still work
+        let rest = &self.input[self.pos..];
+        if rest.starts_with('.') && rest[1..].starts_with(|ch: char| ch.is_ascii_digit()) {
+            self.pos += 1;
+            self.read_while(|ch| ch.is_ascii_digit() || ch == '_');
+        }
+        let rest = &self.input[self.pos..];
+        if rest.starts_with(['e', 'E']) {
+            let exponent = rest[1..].trim_start_matches(['+', '-']);
+            // at most one sign, the rest is read as a suffix
+            let signs = rest.len() - 1 - exponent.len();
+            if signs <= 1 && exponent.starts_with(|ch: char| ch.is_ascii_digit()) {
+                self.pos += 1 + signs;
+                self.read_while(|ch| ch.is_ascii_digit() || ch == '_');
+            }
+        }
+        self.read_while(is_word);
+        Token::NumberLiteral(self.input[start..self.pos].to_string())
     }
 
+    // the opening " has already been consumed
     fn read_string(&mut self) -> Token {
-        let s = self.read_while(|ch| ch != '"');
-        self.pos += 1; // Skip the closing "
-        Token::StringLiteral(s)
+        let mut parts: Vec<StringPart> = Vec::new();
+        let mut literal = String::new();
+        let mut literal_start = self.pos;
+        // keep going after a bad escape so we still end up after the closing "
+        let mut error: Option<LexError> = None;
+        loop {
+            let ch = match self.next_char() {
+                Some(ch) => ch,
+                None => return Token::Error(LexError::UnterminatedString),
+            };
+            match ch {
+                '"' => {
+                    self.pos += 1; // Skip the closing "
+                    break;
+                }
+                // "\(expr)" and "${expr}" interpolations
+                '\\' | '$' if self.starts_with("\\(") || self.starts_with("${") => {
+                    let close = if ch == '$' { '}' } else { ')' };
+                    if !literal.is_empty() {
+                        parts.push(StringPart::Literal {
+                            value: std::mem::take(&mut literal),
+                            span: self.span_at(literal_start, self.pos),
+                        });
+                    }
+                    self.pos += 2;
+                    match self.read_interpolation(close) {
+                        Ok(part) => parts.push(part),
+                        Err(err) => return Token::Error(err),
+                    }
+                    literal_start = self.pos;
+                }
+                '\\' => {
+                    self.pos += 1;
+                    match self.read_escape() {
+                        Ok(ch) => literal.push(ch),
+                        Err(LexError::UnterminatedString) => {
+                            return Token::Error(LexError::UnterminatedString)
+                        }
+                        Err(err) => {
+                            error.get_or_insert(err);
+                        }
+                    }
+                }
+                _ => {
+                    literal.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+        if let Some(error) = error {
+            return Token::Error(error);
+        }
+        if parts.is_empty() {
+            return Token::StringLiteral(literal);
+        }
+        if !literal.is_empty() {
+            // up to the closing "
+            let span = self.span_at(literal_start, self.pos - 1);
+            parts.push(StringPart::Literal {
+                value: literal,
+                span,
+            });
+        }
+        Token::InterpolatedString(parts)
+    }
+
+    // r"..." or r#"..."#, no escapes and no interpolation
+    fn read_raw_string(&mut self) -> Token {
+        self.pos += 1; // Skip the r
+        let hashes = self.read_while(|ch| ch == '#').len();
+        self.pos += 1; // Skip the opening "
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        match self.input[self.pos..].find(&terminator) {
+            Some(len) => {
+                let s = self.input[self.pos..self.pos + len].to_string();
+                self.pos += len + terminator.len();
+                Token::StringLiteral(s)
+            }
+            None => {
+                self.pos = self.input.len();
+                Token::Error(LexError::UnterminatedString)
+            }
+        }
+    }
+
+    // the backslash has already been consumed
+    fn read_escape(&mut self) -> Result<char, LexError> {
+        let ch = self.next_char().ok_or(LexError::UnterminatedString)?;
+        self.pos += ch.len_utf8();
+        match ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' | '$' => Ok(ch),
+            'u' => {
+                if !self.starts_with("{") {
+                    return Err(LexError::InvalidUnicodeEscape(String::new()));
+                }
+                let digits = self.read_while(|ch| ch != '}' && ch != '"' && ch != '\n');
+                let escape = format!("{}}}", digits);
+                if !self.starts_with("}") {
+                    return Err(LexError::InvalidUnicodeEscape(digits));
+                }
+                self.pos += 1; // Skip the }
+                u32::from_str_radix(&digits[1..], 16)
+                    .ok()
+                    .filter(|_| digits.len() > 1 && digits.len() <= 7)
+                    .and_then(char::from_u32)
+                    .ok_or(LexError::InvalidUnicodeEscape(escape))
+            }
+            ch => Err(LexError::InvalidEscape(ch)),
+        }
+    }
+
+    // read up to the matching `close`, the opening delimiter has already been consumed
+    fn read_interpolation(&mut self, close: char) -> Result<StringPart, LexError> {
+        let open = if close == '}' { '{' } else { '(' };
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(ch) = self.next_char() {
+            match ch {
+                ch if ch == close && depth == 0 => {
+                    let source = self.input[start..self.pos].to_string();
+                    let span = self.span_at(start, self.pos);
+                    self.pos += 1; // Skip the closing delimiter
+                    return Ok(StringPart::Expr { source, span });
+                }
+                ch if ch == close => depth -= 1,
+                ch if ch == open => depth += 1,
+                // skip over strings nested in the expression
+                '"' => {
+                    self.pos += 1;
+                    while let Some(ch) = self.next_char() {
+                        if ch == '"' {
+                            break;
+                        }
+                        if ch == '\\' {
+                            self.pos += 1;
+                        }
+                        self.pos += self.next_char().map_or(0, |ch| ch.len_utf8());
+                    }
+                }
+                _ => {}
+            }
+            self.pos += self.next_char().map_or(0, |ch| ch.len_utf8());
+        }
+        Err(LexError::UnterminatedString)
     }
+
+    // the opening ' has already been consumed
     fn read_char(&mut self) -> Token {
-        let s = self.read_while(|ch| ch != '\'' && ch != '\n');
-        Token::CharLiteral(s.chars().next().unwrap())
+        let mut chars = String::new();
+        let mut error: Option<LexError> = None;
+        loop {
+            match self.next_char() {
+                None | Some('\n') => return Token::Error(LexError::UnterminatedChar),
+                Some('\'') => {
+                    self.pos += 1; // Skip the closing '
+                    break;
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.read_escape() {
+                        Ok(ch) => chars.push(ch),
+                        Err(LexError::UnterminatedString) => {
+                            return Token::Error(LexError::UnterminatedChar)
+                        }
+                        Err(err) => {
+                            error.get_or_insert(err);
+                        }
+                    }
+                }
+                Some(ch) => {
+                    chars.push(ch);
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+        if let Some(error) = error {
+            return Token::Error(error);
+        }
+        let mut iter = chars.chars();
+        match (iter.next(), iter.next()) {
+            (Some(ch), None) => Token::CharLiteral(ch),
+            (None, _) => Token::Error(LexError::EmptyCharLiteral),
+            (Some(_), Some(_)) => Token::Error(LexError::InvalidCharLiteral(chars)),
+        }
     }
 
     fn read_identifier(&mut self) -> Token {
@@ -78,10 +367,22 @@ impl<'a> Lexer<'a> {
             return Token::BoolLiteral(false);
         }
         
-        let s = self.read_while(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+        let s = self.read_while(|ch| ch.is_alphanumeric() || ch == '_');
+        if !s.is_ascii() {
+            return Token::Error(LexError::NonAsciiIdentifier(s));
+        }
         Token::Identifier(s)
     }
 
+    // the @ has already been consumed, `@{ ... }` gives a directive with no name
+    fn read_directive(&mut self) -> Token {
+        let name = self.read_while(|ch| ch.is_alphanumeric() || ch == '_');
+        if !name.is_ascii() {
+            return Token::Error(LexError::NonAsciiIdentifier(name));
+        }
+        Token::Directive(name)
+    }
+
     fn read_whitespace(&mut self) -> Token {
         // ifis newline
         let newline = self.read_while(|ch| ['\r', '\n', '\u{A0}'].contains(&ch));
@@ -103,10 +404,22 @@ impl<'a> Lexer<'a> {
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
-    
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        let start = self.pos;
+        let (line, column) = (self.line, self.column(start));
+        let token = self.next_token()?;
+        self.track_lines(start);
+        Some(SpannedToken {
+            token,
+            span: Span::new(self.file_id, start, self.pos, line, column),
+        })
+    }
+}
 
-    fn next(&mut self) -> Option<Token> {
+impl<'a> Lexer<'a> {
+    fn next_token(&mut self) -> Option<Token> {
         let white_space = self.read_whitespace();
         if white_space != Token::WhiteSpace(0) {
             return Some(white_space);
@@ -211,7 +524,19 @@ impl<'a> Iterator for Lexer<'a> {
                     self.pos += 1;
                     return Some(Token::Modulo);
                 }
+                '@' => {
+                    self.pos += 1;
+                    Some(self.read_directive())
+                }
                 // comparison
+                '=' => {
+                    if self.starts_with("==") {
+                        self.pos += 2;
+                        return Some(Token::Equality);
+                    }
+                    self.pos += 1;
+                    return Some(Token::Assign);
+                }
                 '!' => {
                     if self.starts_with("!=") {
                         self.pos += 2;
@@ -221,6 +546,10 @@ impl<'a> Iterator for Lexer<'a> {
                     return Some(Token::Not);
                 }
                 '>' => {
+                    if self.starts_with(">>") {
+                        self.pos += 2;
+                        return Some(Token::ShiftRight);
+                    }
                     if self.starts_with(">=") {
                         self.pos += 2;
                         return Some(Token::GreaterThanOrEqual);
@@ -229,6 +558,10 @@ impl<'a> Iterator for Lexer<'a> {
                     return Some(Token::GreaterThan);
                 }
                 '<' => {
+                    if self.starts_with("<<") {
+                        self.pos += 2;
+                        return Some(Token::ShiftLeft);
+                    }
                     if self.starts_with("<=") {
                         self.pos += 2;
                         return Some(Token::LessThanOrEqual);
@@ -237,8 +570,14 @@ impl<'a> Iterator for Lexer<'a> {
                     return Some(Token::LessThan);
                 }
                 ch if ch.is_digit(10) => Some(self.read_number()),
-                ch if ch.is_ascii_alphabetic() => Some(self.read_identifier()),
-                ch => panic!("Unexpected character: {}", ch),
+                'r' if self.input[self.pos + 1..].trim_start_matches('#').starts_with('"') => {
+                    Some(self.read_raw_string())
+                }
+                ch if ch.is_alphabetic() || ch == '_' => Some(self.read_identifier()),
+                ch => {
+                    self.pos += ch.len_utf8();
+                    Some(Token::Error(LexError::InvalidCharacter(ch)))
+                }
             }
         }
     }
@@ -303,6 +642,160 @@ myResult: MyFunction(String("Value")) {
         Token::EndOfFile,
     ];
     let lexer = Lexer::new(input);
-    let tokens = lexer.collect::<Vec<Token>>();
+    let tokens = lexer.map(|t| t.token).collect::<Vec<Token>>();
     assert_eq!(tokens, expected);
 }
+
+#[test]
+fn test_lexer_spans() {
+    let input = "a: 1\n  b.c";
+    let spans = Lexer::new(input).map(|t| t.span).collect::<Vec<Span>>();
+    assert_eq!(spans[0], Span::new(0, 0, 1, 1, 1)); // a
+    assert_eq!(spans[3], Span::new(0, 3, 4, 1, 4)); // 1
+    assert_eq!(spans[6], Span::new(0, 7, 8, 2, 3)); // b
+    assert_eq!(spans[8], Span::new(0, 9, 10, 2, 5)); // c
+}
+
+#[test]
+fn test_lexer_errors() {
+    let input = "a: \"open\nb: ''\nc: 'x\nd: $ naïve";
+    let errors = Lexer::new(input)
+        .filter_map(|t| match t.token {
+            Token::Error(error) => Some((error, t.span.line)),
+            _ => None,
+        })
+        .collect::<Vec<(LexError, usize)>>();
+    assert_eq!(errors, vec![(LexError::UnterminatedString, 1)]);
+
+    let input = "b: ''\nc: 'x\nd: $ naïve _ok";
+    let tokens = Lexer::new(input).map(|t| t.token).collect::<Vec<Token>>();
+    assert!(tokens.contains(&Token::Error(LexError::EmptyCharLiteral)));
+    assert!(tokens.contains(&Token::Error(LexError::UnterminatedChar)));
+    assert!(tokens.contains(&Token::Error(LexError::InvalidCharacter('$'))));
+    assert!(tokens.contains(&Token::Error(LexError::NonAsciiIdentifier("naïve".to_string()))));
+    assert!(tokens.contains(&Token::Identifier("_ok".to_string())));
+    assert_eq!(tokens.last(), Some(&Token::EndOfFile));
+}
+
+#[test]
+fn test_lexer_strings() {
+    let tokens = |input: &str| Lexer::new(input).map(|t| t.token).collect::<Vec<Token>>();
+    assert_eq!(
+        tokens(r#""hello \"zen\"\n\t\\ \u{1F600}""#)[0],
+        Token::StringLiteral("hello \"zen\"\n\t\\ \u{1F600}".to_string())
+    );
+    assert_eq!(tokens(r#"'\n'"#)[0], Token::CharLiteral('\n'));
+    assert_eq!(tokens(r#"'\''"#)[0], Token::CharLiteral('\''));
+    assert_eq!(tokens(r#"'ab'"#)[0], Token::Error(LexError::InvalidCharLiteral("ab".to_string())));
+    assert_eq!(tokens(r#""\q" a"#)[0], Token::Error(LexError::InvalidEscape('q')));
+    assert_eq!(tokens(r#""\q" a"#)[2], Token::Identifier("a".to_string()));
+    assert_eq!(
+        tokens(r#""\u{110000}""#)[0],
+        Token::Error(LexError::InvalidUnicodeEscape("{110000}".to_string()))
+    );
+    assert_eq!(
+        tokens(r##"r"C:\path" r#"say "hi" \(x)"#"##)[..3],
+        [
+            Token::StringLiteral("C:\\path".to_string()),
+            Token::WhiteSpace(1),
+            Token::StringLiteral("say \"hi\" \\(x)".to_string()),
+        ]
+    );
+
+    let input = "s: \"Hello \\(self.name)! ${String(\"}\")} years\"";
+    assert_eq!(
+        tokens(input)[3],
+        Token::InterpolatedString(vec![
+            StringPart::Literal {
+                value: "Hello ".to_string(),
+                span: Span::new(0, 4, 10, 1, 5),
+            },
+            StringPart::Expr {
+                source: "self.name".to_string(),
+                span: Span::new(0, 12, 21, 1, 13),
+            },
+            StringPart::Literal {
+                value: "! ".to_string(),
+                span: Span::new(0, 22, 24, 1, 23),
+            },
+            StringPart::Expr {
+                source: "String(\"}\")".to_string(),
+                span: Span::new(0, 26, 37, 1, 27),
+            },
+            StringPart::Literal {
+                value: " years".to_string(),
+                span: Span::new(0, 38, 44, 1, 39),
+            },
+        ])
+    );
+    assert_eq!(tokens("\"\\(a\"")[0], Token::Error(LexError::UnterminatedString));
+}
+
+#[test]
+fn test_lexer_numbers() {
+    let numbers = |input: &str| {
+        Lexer::new(input)
+            .map(|t| t.token)
+            .filter(|token| !matches!(token, Token::WhiteSpace(_) | Token::EndOfFile))
+            .collect::<Vec<Token>>()
+    };
+    let number = |text: &str| Token::NumberLiteral(text.to_string());
+    assert_eq!(
+        numbers("1_000 0xFF_ff 1.5e-3 2E+8f32 42i32 0b102"),
+        vec![
+            number("1_000"),
+            number("0xFF_ff"),
+            number("1.5e-3"),
+            number("2E+8f32"),
+            number("42i32"),
+            number("0b102"),
+        ]
+    );
+    assert_eq!(numbers("1-2"), vec![number("1"), Token::Minus, number("2")]);
+    assert_eq!(
+        numbers("1.abs"),
+        vec![number("1"), Token::Dot, Token::Identifier("abs".to_string())]
+    );
+}
+
+#[test]
+fn test_lexer_operators() {
+    let tokens = Lexer::new("a == b << 2 >> c <= d")
+        .map(|t| t.token)
+        .filter(|token| !matches!(token, Token::WhiteSpace(_) | Token::EndOfFile))
+        .collect::<Vec<Token>>();
+    let ident = |name: &str| Token::Identifier(name.to_string());
+    assert_eq!(
+        tokens,
+        vec![
+            ident("a"),
+            Token::Equality,
+            ident("b"),
+            Token::ShiftLeft,
+            Token::NumberLiteral("2".to_string()),
+            Token::ShiftRight,
+            ident("c"),
+            Token::LessThanOrEqual,
+            ident("d"),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_directives() {
+    let tokens = Lexer::new("a = @std == @{")
+        .map(|t| t.token)
+        .filter(|token| !matches!(token, Token::WhiteSpace(_) | Token::EndOfFile))
+        .collect::<Vec<Token>>();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Identifier("a".to_string()),
+            Token::Assign,
+            Token::Directive("std".to_string()),
+            Token::Equality,
+            Token::Directive(String::new()),
+            Token::CurlyBraceOpen,
+        ]
+    );
+}
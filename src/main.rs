@@ -88,13 +88,30 @@
 
 mod lexer;
 mod ast;
+mod diagnostics;
 mod token;
+mod span;
 mod parser;
 mod codegen;
+mod driver;
+mod builtins;
+mod interp;
+mod ir;
+mod repl;
+mod resolve;
+mod sexpr;
+mod typeck;
+mod wasm;
 mod wat_codegen;
+#[cfg(test)]
+mod conformance;
 
 use std::path::PathBuf;
-use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+use clap::{Args, Parser, Subcommand};
+
+use diagnostics::{Emitter, ErrorFormat};
+use driver::{AstFormat, BuildOptions, Driver, Dump, Emit, Input, Runner, Stage, Target};
 
 #[derive(Parser)]
 #[command(name = "Zen")]
@@ -102,52 +119,150 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// how errors are reported
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct InputArgs {
+    /// the source file
+    file: Option<PathBuf>,
+
+    /// use this code instead of a file
+    #[arg(short, long)]
+    eval: Option<String>,
+}
+
+impl InputArgs {
+    fn input(self) -> Input {
+        // clap makes sure exactly one of them is given
+        match (self.file, self.eval) {
+            (Some(file), _) => Input::File(file),
+            (None, Some(source)) => Input::Source(source),
+            (None, None) => unreachable!(),
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// runs a file or string
     Run {
-        #[arg(short, long)]
-        file: PathBuf,
+        #[command(flatten)]
+        input: InputArgs,
 
+        /// compile it with LLVM in memory and run that, rather than interpret it
+        #[arg(long, conflicts_with = "target")]
+        jit: bool,
+
+        /// compile it for this target and run that, `native` runs it with the JIT like
+        /// `--jit` and `wasm32` in an embedded runtime
+        #[arg(long, value_enum)]
+        target: Option<Target>,
+    },
+    /// compiles a file to a native executable or a WebAssembly module
+    Build {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[arg(long, value_enum, default_value_t = Target::Native)]
+        target: Target,
+
+        /// where to write the executable, the other outputs get its name
         #[arg(short, long)]
-        string: String,
+        output: Option<PathBuf>,
+
+        /// optimisation level, 0 to 3
+        #[arg(short = 'O', default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+        opt_level: u8,
+
+        /// what to write, an executable or a `.wasm` module by default
+        #[arg(long, value_enum, value_delimiter = ',')]
+        emit: Vec<Emit>,
     },
     // runs a repl session
     Repl,
-}
+    /// prints the tokens
+    Tokens {
+        #[command(flatten)]
+        input: InputArgs,
 
-fn parse_file(file_path: &PathBuf) {
-    let file = std::fs::read_to_string(file_path).expect("Failed to read file");
-    let mut parser = parser::Parser::new(&file);
-    let ast = parser.parse();
-    println!("{:#?}", ast);
-}
-// // string
-fn parse_string(string: &String) {
-    let mut parser = parser::Parser::new(&string);
-    let ast = parser.parse();
-    println!("{:#?}", ast);
-}
+        /// stop the pipeline after this stage and print its output
+        #[arg(long, value_enum)]
+        stop_after: Option<Stage>,
+    },
+    /// prints the AST
+    Ast {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[arg(long, value_enum, default_value_t = AstFormat::Debug)]
+        format: AstFormat,
 
-fn main() {
+        /// stop the pipeline after this stage and print its output
+        #[arg(long, value_enum)]
+        stop_after: Option<Stage>,
+    },
+    /// prints the LLVM IR
+    Ir {
+        #[command(flatten)]
+        input: InputArgs,
 
+        /// stop the pipeline after this stage and print its output
+        #[arg(long, value_enum)]
+        stop_after: Option<Stage>,
+    },
+    /// prints the WebAssembly text
+    Wat {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// stop the pipeline after this stage and print its output
+        #[arg(long, value_enum)]
+        stop_after: Option<Stage>,
+    },
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    match &cli.command {
-        Commands::Run {file, string} => {
-           if !file.is_file() {
-               parse_file(file);
-            } else if !string.is_empty() {
-                parse_string(string);
-            } else {
-                panic!("No file or string provided");
-            }
-        },
-        Commands::Repl => {
-            todo!()
+    let mut driver = Driver::new(Emitter::new(cli.error_format));
+    match cli.command {
+        Commands::Run { input, jit, target } => {
+            let runner = match (jit, target) {
+                (true, _) | (false, Some(Target::Native)) => Runner::Jit,
+                (false, Some(Target::Wasm32)) => Runner::Wasm,
+                (false, None) => Runner::Interpreter,
+            };
+            driver.run(&input.input(), runner)
         }
+        Commands::Build {
+            input,
+            target,
+            output,
+            opt_level,
+            emit,
+        } => {
+            let options = BuildOptions {
+                target,
+                output,
+                opt_level,
+                emit,
+            };
+            driver.build(&input.input(), &options)
+        }
+        Commands::Repl => repl::Repl::new(driver).run(),
+        Commands::Tokens { input, stop_after } => {
+            driver.dump(&input.input(), Dump::Tokens, stop_after)
+        }
+        Commands::Ast {
+            input,
+            format,
+            stop_after,
+        } => driver.dump(&input.input(), Dump::Ast(format), stop_after),
+        Commands::Ir { input, stop_after } => driver.dump(&input.input(), Dump::Ir, stop_after),
+        Commands::Wat { input, stop_after } => driver.dump(&input.input(), Dump::Wat, stop_after),
     }
-
-    
 }
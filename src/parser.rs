@@ -1,185 +1,545 @@
-use std::vec;
-
 use crate::ast::{
-    Atom, Binary, BinaryOp, Expr, Group, GroupOp, Literal, Ternary, TernaryOp, Unary, UnaryOp,
+    Atom, Binary, BinaryOp, Comparison, Directive, Expr, Group, GroupOp, InterpolatedString,
+    Literal, NodeId, NumberSuffix, Ternary, TernaryOp, Unary, UnaryOp,
 };
 
-use crate::lexer::Lexer;
-use crate::token::Token;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{LexError, Lexer};
+use crate::span::Span;
+use crate::token::{StringPart, Token};
 
 use serde::Serialize;
 
-#[derive(Debug, PartialEq, Serialize)]
-pub enum ParseError {
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub enum ParseErrorKind {
     UnexpectedToken(Token),
     UnexpectedEOF,
+    Lex(LexError),
+    InvalidNumber(String, NumberError),
+    Unsupported(String),
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub enum NumberError {
+    // `0x` with nothing after it
+    NoDigits,
+    // a digit that is not valid for the radix, like the `2` in `0b102`
+    InvalidDigit(char, u32),
+    InvalidSuffix(String),
+    // too large for the suffix, or for a 64 bit `Int` when there is none
+    OutOfRange(Option<NumberSuffix>),
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal",
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    // the tokens that would have been accepted, payloads are ignored
+    pub expected: Vec<Token>,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, expected: Vec<Token>, span: Span) -> Self {
+        ParseError {
+            kind,
+            expected,
+            span,
+        }
+    }
+
+    // built when the error is shown, so errors stay small while parsing
+    pub fn message(&self) -> String {
+        match &self.kind {
+            ParseErrorKind::UnexpectedToken(token) => match self.expected.len() {
+                0 => format!("unexpected {}", token.describe()),
+                _ => format!(
+                    "expected {}, found {}",
+                    Self::expected_list(&self.expected),
+                    token.describe()
+                ),
+            },
+            ParseErrorKind::UnexpectedEOF => match self.expected.len() {
+                0 => "unexpected end of file".to_string(),
+                _ => format!(
+                    "expected {}, found end of file",
+                    Self::expected_list(&self.expected)
+                ),
+            },
+            ParseErrorKind::Lex(error) => error.to_string(),
+            ParseErrorKind::InvalidNumber(number, error) => match error {
+                NumberError::NoDigits => format!("number literal `{}` has no digits", number),
+                NumberError::InvalidDigit(digit, radix) => format!(
+                    "invalid digit `{}` in {} literal `{}`",
+                    digit,
+                    radix_name(*radix),
+                    number
+                ),
+                NumberError::InvalidSuffix(suffix) => {
+                    format!("invalid suffix `{}` on number literal `{}`", suffix, number)
+                }
+                NumberError::OutOfRange(Some(suffix)) => format!(
+                    "number literal `{}` is out of range for `{}`",
+                    number,
+                    suffix.name()
+                ),
+                NumberError::OutOfRange(None) => {
+                    format!("number literal `{}` is out of range for `Int`", number)
+                }
+            },
+            ParseErrorKind::Unsupported(what) => format!("{} is not supported yet", what),
+        }
+    }
+
+    fn expected_list(expected: &[Token]) -> String {
+        let names: Vec<&str> = expected.iter().map(|token| token.kind_name()).collect();
+        match names.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("one of {} or {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let label = match &error.kind {
+            ParseErrorKind::UnexpectedToken(token) => format!("unexpected {}", token.kind_name()),
+            ParseErrorKind::UnexpectedEOF => "file ends here".to_string(),
+            ParseErrorKind::Lex(_) => "invalid token".to_string(),
+            ParseErrorKind::InvalidNumber(_, NumberError::OutOfRange(_)) => {
+                "literal out of range".to_string()
+            }
+            ParseErrorKind::InvalidNumber(..) => "not a valid number".to_string(),
+            ParseErrorKind::Unsupported(_) => String::new(),
+        };
+        Diagnostic::error(error.message()).with_primary(error.span, label)
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message()
+        )
+    }
+}
+
+// `a < b < c` chains, see `Parser::parse_comparison`
+const COMPARISON_PRECEDENCE: u8 = 3;
+
+// `a = b = c`, the only right associative operator
+const ASSIGN_PRECEDENCE: u8 = 0;
+
+// precedence of an infix operator and its AST op, higher binds tighter
+fn infix_operator(token: &Token) -> Option<(u8, BinaryOp)> {
+    Some(match token {
+        Token::Assign => (ASSIGN_PRECEDENCE, BinaryOp::Reassignment),
+        Token::Or => (1, BinaryOp::Or),
+        Token::And => (2, BinaryOp::And),
+        Token::Equality => (COMPARISON_PRECEDENCE, BinaryOp::Equal),
+        Token::NotEqual => (COMPARISON_PRECEDENCE, BinaryOp::NotEqual),
+        Token::LessThan => (COMPARISON_PRECEDENCE, BinaryOp::LessThan),
+        Token::LessThanOrEqual => (COMPARISON_PRECEDENCE, BinaryOp::LessThanOrEqual),
+        Token::GreaterThan => (COMPARISON_PRECEDENCE, BinaryOp::GreaterThan),
+        Token::GreaterThanOrEqual => (COMPARISON_PRECEDENCE, BinaryOp::GreaterThanOrEqual),
+        Token::BitwiseOr => (4, BinaryOp::BitwiseOr),
+        Token::BitwiseXor => (5, BinaryOp::BitwiseXor),
+        Token::BitwiseAnd => (6, BinaryOp::BitwiseAnd),
+        Token::ShiftLeft => (7, BinaryOp::ShiftLeft),
+        Token::ShiftRight => (7, BinaryOp::ShiftRight),
+        Token::Plus => (8, BinaryOp::Add),
+        Token::Minus => (8, BinaryOp::Subtract),
+        Token::Multiply => (9, BinaryOp::Multiply),
+        Token::Divide => (9, BinaryOp::Divide),
+        Token::Modulo => (9, BinaryOp::Modulo),
+        _ => return None,
+    })
+}
+
+// end of the run of digits and `_` starting at `from`
+fn digits_end(text: &str, from: usize) -> usize {
+    text[from..]
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '_')
+        .map_or(text.len(), |end| from + end)
+}
+
+/// Turns the text of a `Token::NumberLiteral` into a literal, checking the
+/// digits against the radix and the value against the suffix. A `negated`
+/// literal follows a `-`, so a signed type fits one more, `-128i8` is valid.
+pub fn parse_number(text: &str, negated: bool) -> Result<Literal, NumberError> {
+    let radix = match text.get(..2).map(|prefix| prefix.to_ascii_lowercase()) {
+        Some(prefix) if prefix == "0x" => 16,
+        Some(prefix) if prefix == "0o" => 8,
+        Some(prefix) if prefix == "0b" => 2,
+        _ => 10,
+    };
+
+    // split the digits from the suffix
+    let (digits, suffix, float) = if radix == 10 {
+        let mut end = digits_end(text, 0);
+        let mut float = false;
+        if text[end..].starts_with('.') {
+            float = true;
+            end = digits_end(text, end + 1);
+        }
+        let rest = &text[end..];
+        if rest.starts_with(['e', 'E']) {
+            let exponent = rest[1..].strip_prefix(['+', '-']).unwrap_or(&rest[1..]);
+            if exponent.starts_with(|ch: char| ch.is_ascii_digit()) {
+                float = true;
+                end = digits_end(text, text.len() - exponent.len());
+            }
+        }
+        (&text[..end], &text[end..], float)
+    } else {
+        // a hex digit can't start a suffix, other letters can
+        let body = &text[2..];
+        let is_digit = |ch: char| ch.is_ascii_digit() || (radix == 16 && ch.is_ascii_hexdigit());
+        let end = body
+            .find(|ch: char| !is_digit(ch) && ch != '_')
+            .unwrap_or(body.len());
+        (&body[..end], &body[end..], false)
+    };
+
+    let suffix = match suffix {
+        "" => None,
+        name => match NumberSuffix::from_name(name) {
+            Some(suffix) if suffix.is_float() && radix != 10 => {
+                return Err(NumberError::InvalidSuffix(name.to_string()))
+            }
+            Some(suffix) if float && !suffix.is_float() => {
+                return Err(NumberError::InvalidSuffix(name.to_string()))
+            }
+            Some(suffix) => Some(suffix),
+            None => return Err(NumberError::InvalidSuffix(name.to_string())),
+        },
+    };
+    // decimal digits were already checked while splitting off the suffix
+    let invalid = digits.chars().find(|ch| *ch != '_' && !ch.is_digit(radix));
+    if let (Some(digit), true) = (invalid, radix != 10) {
+        return Err(NumberError::InvalidDigit(digit, radix));
+    }
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return Err(NumberError::NoDigits);
+    }
+
+    if float || suffix.is_some_and(|suffix| suffix.is_float()) {
+        let value = digits
+            .parse::<f64>()
+            .map_err(|_| NumberError::OutOfRange(suffix))?;
+        let max = match suffix {
+            Some(NumberSuffix::F32) => f32::MAX as f64,
+            _ => f64::MAX,
+        };
+        if value > max {
+            return Err(NumberError::OutOfRange(Some(suffix.unwrap_or(NumberSuffix::F64))));
+        }
+        return Ok(Literal::FloatLiteral(value, suffix));
+    }
+
+    let value = u128::from_str_radix(&digits, radix).map_err(|_| NumberError::OutOfRange(suffix))?;
+    let max = suffix.map_or(Some(i64::MAX as u128), |suffix| suffix.max_int());
+    let signed = suffix.is_none_or(|suffix| suffix.is_signed());
+    let max = max.map(|max| max + (negated && signed) as u128);
+    if max.is_some_and(|max| value > max) {
+        return Err(NumberError::OutOfRange(suffix));
+    }
+    Ok(match radix {
+        2 => Literal::BinaryLiteral(value, suffix),
+        8 => Literal::OctalLiteral(value, suffix),
+        16 => Literal::HexLiteral(value, suffix),
+        _ => Literal::IntLiteral(value, suffix),
+    })
 }
 
 #[derive(Debug, PartialEq, Serialize)]
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Option<Token>,
+    current_span: Span,
+    // span of the last significant token that was consumed
+    prev_span: Span,
+    // closing tokens of the blocks we are currently inside of
+    delimiters: Vec<Token>,
+    errors: Vec<ParseError>,
+    // a newline or `,` was skipped right before the current token
+    separated: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
-        let mut lexer = Lexer::new(input);
-        let current_token = lexer.next();
-        Parser {
+        Parser::with_file_id(input, 0)
+    }
+
+    pub fn with_file_id(input: &'a str, file_id: usize) -> Self {
+        Parser::from_lexer(Lexer::with_file_id(input, file_id))
+    }
+
+    fn from_lexer(lexer: Lexer<'a>) -> Self {
+        let start = Span::new(lexer.file_id, lexer.pos, lexer.pos, 1, 1);
+        let mut parser = Parser {
             lexer,
-            current_token,
+            current_token: None,
+            current_span: start,
+            prev_span: start,
+            delimiters: Vec::new(),
+            errors: Vec::new(),
+            separated: false,
+        };
+        parser.bump();
+        parser
+    }
+
+    // advance one raw token, formatting included
+    fn bump(&mut self) {
+        match self.lexer.next() {
+            Some(spanned) => {
+                self.current_token = Some(spanned.token);
+                self.current_span = spanned.span;
+            }
+            None => self.current_token = None,
         }
     }
 
     fn next_token(&mut self) {
-        self.current_token = self.lexer.next();
+        self.prev_span = self.current_span;
+        self.separated = false;
+        self.bump();
         let _ = &self.skip_formating();
     }
-    // expect one or more tokens
-    fn expect_token(&mut self, expected: Vec<Token>) {
-        let mut found = false;
+
+    // span from `start` up to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.prev_span)
+    }
+    // error for the current token, given what would have been accepted instead
+    fn unexpected(&self, expected: Vec<Token>) -> ParseError {
         match &self.current_token {
-            None => panic!(
-                "Was expecting one of {:?} but got {:?}",
-                expected, self.current_token
+            None | Some(Token::EndOfFile) => {
+                ParseError::new(ParseErrorKind::UnexpectedEOF, expected, self.current_span)
+            }
+            // the lexer already knows what went wrong
+            Some(Token::Error(error)) => {
+                ParseError::new(ParseErrorKind::Lex(error.clone()), vec![], self.current_span)
+            }
+            Some(token) => ParseError::new(
+                ParseErrorKind::UnexpectedToken(token.clone()),
+                expected,
+                self.current_span,
             ),
-            Some(token) => {
-                for expected_token in &expected {
-                    if std::mem::discriminant(token) == std::mem::discriminant(expected_token) {
-                        found = true;
-                    }
-                }
-                if !found {
-                    panic!(
-                        "Was expecting one of {:?} but got {:?}",
-                        expected, self.current_token
-                    );
-                }
+        }
+    }
+
+    // expect one or more tokens
+    fn expect_token(&mut self, expected: Vec<Token>) -> Result<(), ParseError> {
+        match &self.current_token {
+            Some(token)
+                if expected.iter().any(|expected_token| {
+                    std::mem::discriminant(token) == std::mem::discriminant(expected_token)
+                }) =>
+            {
+                Ok(())
             }
+            _ => Err(self.unexpected(expected)),
         }
     }
-    fn skip_formating(&mut self) {
+    // skip to the start of the next item after an error:
+    // past a `,` or newline, or up to a `}` / `)` that closes an enclosing block
+    fn synchronize(&mut self) {
+        let mut depth = 0;
         while let Some(token) = &self.current_token {
             match token {
-                Token::Comment(_) | Token::Comma | Token::NewLine(_) | Token::WhiteSpace(_) => {
-                    self.next_token();
+                Token::EndOfFile => break,
+                Token::Comma | Token::NewLine(_) if depth == 0 => {
+                    self.bump();
+                    break;
                 }
-                _ => break,
+                Token::CurlyBraceOpen | Token::ParenOpen => depth += 1,
+                Token::CurlyBraceClose | Token::ParenClose if depth > 0 => depth -= 1,
+                Token::CurlyBraceClose | Token::ParenClose if self.delimiters.contains(token) => {
+                    break
+                }
+                _ => {}
             }
+            if !matches!(token, Token::Comment(_) | Token::WhiteSpace(_)) {
+                self.prev_span = self.current_span;
+            }
+            self.bump();
         }
+        let _ = &self.skip_formating();
     }
-    fn parse_number_literal(&mut self) -> Result<Expr, ParseError> {
-        // starts with 0b, 0o, 0x -> binary, octal, hex
-        // has a . -> float
-        // has a e -> exponent
-        // otherwise -> int
-        if let Some(Token::NumberLiteral(value)) = &self.current_token {
-            let number_expr = match value {
-                // binary
-                _ if value.starts_with("0b") => {
-                    let val = value.trim_start_matches("0b");
-                    let val = u32::from_str_radix(val, 2).unwrap();
-                    Expr::Atom(Atom::Literal(Literal::BinaryLiteral(val)))
-                }
-                // octal
-                _ if value.starts_with("0o") => {
-                    let val = value.trim_start_matches("0o");
-                    let val = u32::from_str_radix(val, 8).unwrap();
-                    Expr::Atom(Atom::Literal(Literal::OctalLiteral(val)))
+
+    // parse one item of a block, on failure record the error,
+    // skip the rest of the item and leave an `Expr::Error` in its place
+    fn parse_item<F>(&mut self, parse: F) -> Expr
+    where
+        F: FnOnce(&mut Self) -> Result<Expr, ParseError>,
+    {
+        let start = self.current_span;
+        match parse(self) {
+            Ok(expr) => expr,
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                Expr::Error(self.span_from(start), NodeId::next())
+            }
+        }
+    }
+
+    // parse items until `close`, the opening token has already been consumed
+    fn parse_delimited<F>(&mut self, close: Token, parse: F) -> Vec<Expr>
+    where
+        F: Fn(&mut Self) -> Result<Expr, ParseError>,
+    {
+        let mut exprs: Vec<Expr> = Vec::new();
+        self.delimiters.push(close.clone());
+        loop {
+            let _ = &self.skip_formating();
+            match &self.current_token {
+                Some(token) if *token == close => {
+                    self.next_token();
+                    break;
                 }
-                // hex
-                _ if value.starts_with("0x") => {
-                    let val = value.trim_start_matches("0x");
-                    let val = u8::from_str_radix(val, 16).unwrap();
-                    Expr::Atom(Atom::Literal(Literal::HexLiteral(val)))
+                // this closes an enclosing block, so ours was never closed
+                None | Some(Token::EndOfFile) => {
+                    self.errors.push(self.unexpected(vec![close.clone()]));
+                    break;
                 }
-                // float
-                _ if value.contains(".") => {
-                    // if contains e -> exponent
-                    // lhs = float
-                    // rhs = exponent
-                    if value.contains("e") {
-                        let mut split = value.split("e");
-                        let lhs = split.next().unwrap().parse::<f64>().unwrap();
-
-                        let rhs = split.next().unwrap();
-                        let rhs = i32::from_str_radix(rhs, 10).unwrap();
-
-                        // calculate the exponent
-                        let float = lhs * 10_f64.powi(rhs);
-                        Expr::Atom(Atom::Literal(Literal::FloatLiteral(float)))
-                    } else {
-                        let val = value.parse::<f64>().unwrap();
-                        Expr::Atom(Atom::Literal(Literal::FloatLiteral(val)))
-                    }
+                Some(token @ (Token::CurlyBraceClose | Token::ParenClose))
+                    if self.delimiters.contains(token) =>
+                {
+                    self.errors.push(self.unexpected(vec![close.clone()]));
+                    break;
                 }
-                // int
                 _ => {
-                    if value.contains("e") {
-                        let mut split = value.split("e");
-                        let lhs = i64::from_str_radix(split.next().unwrap(), 10).unwrap();
-
-                        let rhs = split.next().unwrap();
-                        let rhs = i64::from_str_radix(rhs, 10).unwrap();
-
-                        // calculate the exponent
-                        let int = lhs * 10_i64.pow(rhs as u32);
+                    let expr = self.parse_item(&parse);
+                    exprs.push(expr);
+                }
+            }
+        }
+        self.delimiters.pop();
+        exprs
+    }
 
-                        Expr::Atom(Atom::Literal(Literal::IntLiteral(int)))
-                    } else {
-                        let val = value.parse::<i64>().unwrap();
-                        Expr::Atom(Atom::Literal(Literal::IntLiteral(val)))
-                    }
+    fn skip_formating(&mut self) {
+        while let Some(token) = &self.current_token {
+            match token {
+                Token::Comma | Token::NewLine(_) => {
+                    self.separated = true;
+                    self.bump();
                 }
-            };
+                Token::Comment(_) | Token::WhiteSpace(_) => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+    fn parse_number_literal(&mut self, negated: bool) -> Result<Expr, ParseError> {
+        if let Some(Token::NumberLiteral(value)) = &self.current_token {
+            let span = self.current_span;
+            let literal = parse_number(value, negated).map_err(|error| {
+                let number = match negated {
+                    true => format!("-{}", value),
+                    false => value.clone(),
+                };
+                ParseError::new(ParseErrorKind::InvalidNumber(number, error), vec![], span)
+            })?;
             self.next_token();
 
-            Ok(number_expr)
+            Ok(Expr::Atom(Atom::Literal(literal), span, NodeId::next()))
         } else {
-            panic!("Unexpected token: {:?}", self.current_token);
+            Err(self.unexpected(vec![Token::NumberLiteral(String::new())]))
         }
     }
 
     fn parse_string_literal(&mut self) -> Result<Expr, ParseError> {
         if let Some(Token::StringLiteral(value)) = &self.current_token {
             let string_literal = Literal::StringLiteral(value.clone());
+            let span = self.current_span;
             self.next_token();
-            Ok(Expr::Atom(Atom::Literal(string_literal)))
+            Ok(Expr::Atom(Atom::Literal(string_literal), span, NodeId::next()))
         } else {
-            panic!("Unexpected token: {:?}", self.current_token);
+            Err(self.unexpected(vec![Token::StringLiteral(String::new())]))
+        }
+    }
+
+    fn parse_interpolated_string(&mut self) -> Result<Expr, ParseError> {
+        let parts = match &self.current_token {
+            Some(Token::InterpolatedString(parts)) => parts.clone(),
+            _ => return Err(self.unexpected(vec![Token::StringLiteral(String::new())])),
+        };
+        let span = self.current_span;
+        let mut exprs = Vec::new();
+        for part in parts {
+            match part {
+                StringPart::Literal { value, span } => {
+                    exprs.push(Expr::Atom(Atom::Literal(Literal::StringLiteral(value)), span, NodeId::next()))
+                }
+                StringPart::Expr { span, .. } => exprs.push(self.parse_interpolation(span)?),
+            }
+        }
+        self.next_token();
+        Ok(Expr::InterpolatedString(InterpolatedString {
+            parts: exprs,
+            span,
+            id: NodeId::next(),
+        }))
+    }
+
+    // parse the single expression of an interpolation with its own parser,
+    // reading the same input so the spans line up
+    fn parse_interpolation(&mut self, span: Span) -> Result<Expr, ParseError> {
+        let input = &self.lexer.input[..span.end];
+        let mut parser = Parser::from_lexer(Lexer::at(input, span.file_id, span.start));
+        let _ = &parser.skip_formating();
+        let result = match &parser.current_token {
+            None | Some(Token::EndOfFile) => Err(parser.unexpected(vec![
+                Token::Identifier(String::new()),
+                Token::StringLiteral(String::new()),
+                Token::NumberLiteral(String::new()),
+            ])),
+            _ => parser.parse_expression(),
+        };
+        self.errors.append(&mut parser.errors);
+        let expr = result?;
+        match &parser.current_token {
+            None | Some(Token::EndOfFile) => Ok(expr),
+            _ => Err(parser.unexpected(vec![])),
         }
     }
 
     fn parse_bool_literal(&mut self) -> Result<Expr, ParseError> {
         if let Some(Token::BoolLiteral(value)) = &self.current_token {
             let bool_literal = Literal::BoolLiteral(value.clone());
+            let span = self.current_span;
             self.next_token();
-            Ok(Expr::Atom(Atom::Literal(bool_literal)))
+            Ok(Expr::Atom(Atom::Literal(bool_literal), span, NodeId::next()))
         } else {
-            panic!("Unexpected token: {:?}", self.current_token);
+            Err(self.unexpected(vec![Token::BoolLiteral(false)]))
         }
     }
 
     fn parse_char_literal(&mut self) -> Result<Expr, ParseError> {
         if let Some(Token::CharLiteral(value)) = &self.current_token {
             let char_literal = Literal::CharLiteral(value.clone());
+            let span = self.current_span;
             self.next_token();
-            Ok(Expr::Atom(Atom::Literal(char_literal)))
+            Ok(Expr::Atom(Atom::Literal(char_literal), span, NodeId::next()))
         } else {
-            panic!("Unexpected token: {:?}", self.current_token);
-        }
-    }
-
-    fn parse_literal(&mut self) -> Result<Expr, ParseError> {
-        // number literal
-        // string literal
-        // char literal
-        match &self.current_token {
-            Some(Token::NumberLiteral(_)) => self.parse_number_literal(),
-            Some(Token::StringLiteral(_)) => self.parse_string_literal(),
-            Some(Token::CharLiteral(_)) => self.parse_char_literal(),
-            None => panic!("Unexpected EOF"),
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            Err(self.unexpected(vec![Token::CharLiteral(' ')]))
         }
     }
 
@@ -188,75 +548,110 @@ impl<'a> Parser<'a> {
 
         let identifier = match &self.current_token {
             Some(Token::Identifier(identifier)) => identifier.clone(),
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            _ => return Err(self.unexpected(vec![Token::Identifier(String::new())])),
         };
+        let ident = Expr::Atom(Atom::Identifier(identifier), self.current_span, NodeId::next());
 
         self.next_token();
+        self.parse_postfix(ident)
+    }
+
+    // whatever follows an identifier or directive
+    fn parse_postfix(&mut self, ident: Expr) -> Result<Expr, ParseError> {
         match &self.current_token {
             // ident : ident
-            Some(Token::Colon) => {
-                self.parse_assignment(Box::new(Expr::Atom(Atom::Identifier(identifier.clone()))))
-            }
+            Some(Token::Colon) => self.parse_assignment(Box::new(ident)),
             // ident . ident
-            Some(Token::Dot) => {
-                self.parse_accessor(Box::new(Expr::Atom(Atom::Identifier(identifier.clone()))))
-            }
+            Some(Token::Dot) => self.parse_accessor(Box::new(ident)),
             // ident { ... }
-            Some(Token::CurlyBraceOpen) => self.parse_block(Some(Box::new(Expr::Atom(
-                Atom::Identifier(identifier.clone()),
-            )))),
+            Some(Token::CurlyBraceOpen) => self.parse_block(Some(Box::new(ident))),
             // ident ( ... )
-            Some(Token::ParenOpen) => self.parse_paren_block(Some(Box::new(Expr::Atom(
-                Atom::Identifier(identifier.clone()),
-            )))),
-            _ => Ok(Expr::Atom(Atom::Identifier(identifier.clone()))),
+            Some(Token::ParenOpen) => self.parse_paren_block(Some(Box::new(ident))),
+            _ => Ok(ident),
+        }
+    }
+
+    fn parse_directive(&mut self) -> Result<Expr, ParseError> {
+        let name = match &self.current_token {
+            Some(Token::Directive(name)) => name.clone(),
+            _ => return Err(self.unexpected(vec![Token::Directive(String::new())])),
+        };
+        let start = self.current_span;
+        self.next_token();
+        // only an argument on the same line belongs to the directive
+        let args = match &self.current_token {
+            _ if self.separated => None,
+            Some(Token::StringLiteral(_)) => Some(Box::new(self.parse_string_literal()?)),
+            Some(Token::CurlyBraceOpen) => Some(Box::new(self.parse_curly_block(None)?)),
+            _ => None,
+        };
+        let directive = Expr::Directive(Directive {
+            name,
+            args,
+            span: self.span_from(start),
+            id: NodeId::next(),
+        });
+        // `@std()`, `@this.value`
+        match &self.current_token {
+            Some(Token::Dot | Token::ParenOpen) if !self.separated => self.parse_postfix(directive),
+            _ => Ok(directive),
         }
     }
 
     fn parse_assignment(&mut self, key: Box<Expr>) -> Result<Expr, ParseError> {
-        self.expect_token(vec![Token::Colon]);
+        self.expect_token(vec![Token::Colon])?;
         self.next_token();
         // key : value
         // key : Type ...
         // key : Func ...
-        let value = Box::new(self.parse_expression().unwrap());
+        let value = Box::new(self.parse_expression()?);
+        let span = self.span_from(key.span());
         Ok(Expr::Binary(Binary {
             op: BinaryOp::Assignment,
             left: key,
             right: value,
+            span,
+            id: NodeId::next(),
         }))
     }
 
     fn parse_accessor(&mut self, object: Box<Expr>) -> Result<Expr, ParseError> {
-        self.expect_token(vec![Token::Dot]);
+        self.expect_token(vec![Token::Dot])?;
         self.next_token();
-        let property = Box::new(self.parse_expression().unwrap());
+        // `a.b + c` is `(a.b) + c`, so only take the operand
+        let property = Box::new(self.parse_primary()?);
+        let span = self.span_from(object.span());
         Ok(Expr::Binary(Binary {
             op: BinaryOp::Accessor,
             left: object,
             right: property,
+            span,
+            id: NodeId::next(),
         }))
     }
 
     fn parse_spread_expression(&mut self) -> Result<Expr, ParseError> {
-        self.expect_token(vec![Token::Ellipse]);
+        self.expect_token(vec![Token::Ellipse])?;
+        let start = self.current_span;
         // skip this token(...)
         self.next_token();
         // this expr expects a Identifier
-        self.expect_token(vec![Token::Identifier("".to_string())]);
-        let expr = Box::new(self.parse_expression().unwrap());
+        self.expect_token(vec![Token::Identifier(String::new())])?;
+        let expr = Box::new(self.parse_primary()?);
         // get the next expr
         Ok(Expr::Unary(Unary {
             op: UnaryOp::SpreadExpr,
             expr,
-         }
-        ))
+            span: self.span_from(start),
+            id: NodeId::next(),
+        }))
     }
 
     fn parse_curly_block(&mut self, ident: Option<Box<Expr>>) -> Result<Expr, ParseError> {
         // either a assignment block, a statement block
-        self.expect_token(vec![Token::CurlyBraceOpen]);
+        self.expect_token(vec![Token::CurlyBraceOpen])?;
         let mut is_statement_block = false;
+        let start = self.current_span;
         self.next_token();
         // this expects either
         // Spread       ...
@@ -272,28 +667,8 @@ impl<'a> Parser<'a> {
         // if the result contains a statement signature return statement block
         // return assignment block
 
-        let mut exprs: Vec<Expr> = Vec::new();
-        loop {
-            let _ = &self.skip_formating();
-            match &self.current_token {
-                Some(Token::CurlyBraceClose) => {
-                    self.next_token();
-                    break;
-                }
-                Some(Token::Ellipse) => {
-                    exprs.push(self.parse_spread_expression().unwrap());
-                }
-                Some(Token::Identifier(_)) => {
-                    exprs.push(self.parse_identifier().unwrap());
-                }
-                None | Some(Token::EndOfFile) => {
-                    panic!("Unexpected EOF, expected: {:?}", Token::CurlyBraceClose);
-                }
-                _ => {
-                    panic!("Unexpected token: {:?}", self.current_token);
-                }
-            }
-        }
+        let exprs = self.parse_delimited(Token::CurlyBraceClose, Self::parse_expression);
+        let block_span = self.span_from(start);
         for expr in &exprs {
             // if any of the exprs is not an assignment then we need to treat it as a statement block
             match expr {
@@ -308,38 +683,38 @@ impl<'a> Parser<'a> {
             }
         }
 
+        // a body for a type, `Fn { ... }` or `Enum { A, B }`, is a statement block,
+        // otherwise it lists the fields of an anonymous type
+        let op = if is_statement_block {
+            GroupOp::StatementBlock
+        } else {
+            GroupOp::AnonymousType
+        };
+        let block = Expr::Group(Group {
+            exprs,
+            op,
+            span: block_span,
+            id: NodeId::next(),
+        });
         match &ident {
-            Some(ident) => {
-                if is_statement_block {
-                    // maybe this can be the default constructor
-                    unimplemented!("Statement block as type def, {:?}", self.current_token);
-                } else {
-                    // this is a type def
-                    Ok(Expr::Binary(Binary {
-                        op: BinaryOp::TypeDef,
-                        left: ident.to_owned(),
-                        right: Box::new(Expr::Group(Group {
-                            exprs,
-                            op: GroupOp::AnonymousType,
-                        })),
-                    }))
-                }
-            }
-            None => {
-                if is_statement_block {
-                    // this is a statement block
-                    Ok(Expr::Group(Group {
-                        exprs,
-                        op: GroupOp::StatementBlock,
-                    }))
-                } else {
-                    // this is a anonymous type
-                    Ok(Expr::Group(Group {
-                        exprs,
-                        op: GroupOp::AnonymousType,
-                    }))
-                }
-            }
+            // this is a type def
+            Some(ident) => Ok(Expr::Binary(Binary {
+                op: BinaryOp::TypeDef,
+                left: ident.to_owned(),
+                right: Box::new(block),
+                span: self.span_from(ident.span()),
+                id: NodeId::next(),
+            })),
+            None => Ok(block),
+        }
+    }
+
+    // a block, or destructuring a record into its fields, `{a, b}: value`
+    fn parse_destructuring(&mut self) -> Result<Expr, ParseError> {
+        let block = self.parse_block(None)?;
+        match &self.current_token {
+            Some(Token::Colon) if !self.separated => self.parse_assignment(Box::new(block)),
+            _ => Ok(block),
         }
     }
 
@@ -350,7 +725,8 @@ impl<'a> Parser<'a> {
         // if not it might be a function call or statement block
 
         // a params block
-        self.expect_token(vec![Token::ParenOpen]);
+        self.expect_token(vec![Token::ParenOpen])?;
+        let start = self.current_span;
         self.next_token();
 
         // this expects either
@@ -359,47 +735,17 @@ impl<'a> Parser<'a> {
         // Ident        ident
 
         // if we encounter a paren close we can finish parsing
-        let mut param_exprs: Vec<Expr> = Vec::new();
+        let param_exprs = self.parse_delimited(Token::ParenClose, Self::parse_expression);
         let mut block_expr: Option<Expr> = None;
-        loop {
-            let _ = &self.skip_formating();
-            match &self.current_token {
-                Some(Token::ParenClose) => {
-                    self.next_token();
-                    break;
-                }
-                Some(Token::Ellipse) => {
-                    param_exprs.push(self.parse_spread_expression().unwrap());
-                }
-                Some(Token::Identifier(_)) => {
-                    param_exprs.push(self.parse_identifier().unwrap());
-                }
-                Some(Token::CharLiteral(_) | Token::StringLiteral(_) | Token::NumberLiteral(_)) => {
-                    param_exprs.push(self.parse_literal().unwrap());
-                }
 
-                None | Some(Token::EndOfFile) => {
-                    // panic!("Unexpected EOF, expected: {:?}", &self.current_token);
-                    // this is a valid case ) can be the end of a file
-                    break;
-                }
-
-                // should add expected errors
-                _ => {
-                    unreachable!(
-                        "lexerPos: {:?}, token: {:?}",
-                        self.lexer.pos, self.current_token
-                    );
-                }
-            }
-        }
+        let params_span = self.span_from(start);
 
         // check to see if param has a assignment block or statement block
         loop {
             let _ = &self.skip_formating();
             match &self.current_token {
                 Some(Token::CurlyBraceOpen) => {
-                    block_expr = Some(self.parse_block(None).unwrap());
+                    block_expr = Some(self.parse_block(None)?);
                     break;
                 }
                 None | Some(Token::EndOfFile) => {
@@ -414,17 +760,17 @@ impl<'a> Parser<'a> {
 
         // check to see if next token is a curly brace open
         if &self.current_token == &Some(Token::CurlyBraceOpen) {
-            block_expr = Some(self.parse_block(None).unwrap());
+            block_expr = Some(self.parse_block(None)?);
         }
 
         match &ident {
             Some(ident) => match block_expr {
                 Some(expr) => {
-                    if expr
-                        == Expr::Group(Group {
-                            exprs: vec![],
+                    if matches!(&expr, Expr::Group(Group {
+                            exprs,
                             op: GroupOp::StatementBlock,
-                        })
+                            ..
+                        }) if exprs.is_empty())
                     {
                         Ok(Expr::Ternary(Ternary {
                             op: TernaryOp::FuncDefInvoke,
@@ -432,8 +778,12 @@ impl<'a> Parser<'a> {
                             middle: Box::new(Expr::Group(Group {
                                 exprs: param_exprs,
                                 op: GroupOp::ParamBlock,
+                                span: params_span,
+                                id: NodeId::next(),
                             })),
                             right: Box::new(expr),
+                            span: self.span_from(ident.span()),
+                            id: NodeId::next(),
                         }))
                     } else {
                         Ok(Expr::Ternary(Ternary {
@@ -442,8 +792,12 @@ impl<'a> Parser<'a> {
                             middle: Box::new(Expr::Group(Group {
                                 exprs: param_exprs,
                                 op: GroupOp::ParamBlock,
+                                span: params_span,
+                                id: NodeId::next(),
                             })),
                             right: Box::new(expr),
+                            span: self.span_from(ident.span()),
+                            id: NodeId::next(),
                         }))
                     }
                 }
@@ -453,60 +807,215 @@ impl<'a> Parser<'a> {
                     right: Box::new(Expr::Group(Group {
                         exprs: param_exprs,
                         op: GroupOp::ParamBlock,
+                        span: params_span,
+                        id: NodeId::next(),
                     })),
+                    span: self.span_from(ident.span()),
+                    id: NodeId::next(),
                 })),
             },
             None => {
                 // anon block { }
-                unimplemented!("Param block without a name")
+                let span = self.span_from(params_span);
+                self.errors.push(ParseError::new(
+                    ParseErrorKind::Unsupported("a param block without a name".to_string()),
+                    vec![],
+                    span,
+                ));
+                Ok(Expr::Error(span, NodeId::next()))
             }
         }
     }
 
     fn parse_block(&mut self, ident: Option<Box<Expr>>) -> Result<Expr, ParseError> {
         // either a assignment block, a statement block or a param block
-        self.expect_token(vec![Token::CurlyBraceOpen, Token::ParenOpen]);
-        let expr = match &self.current_token {
+        match &self.current_token {
             Some(Token::CurlyBraceOpen) => self.parse_curly_block(ident),
             Some(Token::ParenOpen) => self.parse_paren_block(ident),
-            _ => unreachable!(),
-        };
-        expr
+            _ => Err(self.unexpected(vec![Token::CurlyBraceOpen, Token::ParenOpen])),
+        }
     }
 
-    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+    // a single operand: an atom, a block, or an identifier along with whatever
+    // is attached to it (`a: b`, `a.b`, `a(b)`, `a {b}`)
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
         let _ = &self.skip_formating();
         match &self.current_token {
             // Atoms
             Some(Token::Identifier(_)) => self.parse_identifier(),
-            Some(Token::NumberLiteral(_)) => self.parse_number_literal(),
+            Some(Token::NumberLiteral(_)) => self.parse_number_literal(false),
             Some(Token::StringLiteral(_)) => self.parse_string_literal(),
+            Some(Token::InterpolatedString(_)) => self.parse_interpolated_string(),
             Some(Token::BoolLiteral(_)) => self.parse_bool_literal(),
-            // Binary should be handled by the caller
+            Some(Token::CharLiteral(_)) => self.parse_char_literal(),
+            Some(Token::Directive(_)) => self.parse_directive(),
+            // Binary is handled by parse_binary
 
             // Unary
             Some(Token::Ellipse) => self.parse_spread_expression(),
 
             // Grouping
-            Some(Token::CurlyBraceOpen) | Some(Token::ParenOpen) => self.parse_block(None),
-            Some(Token::EndOfFile) => Ok(Expr::Atom(Atom::EndOfFile)),
-            _ => panic!("Unexpected token: {:?}", self.current_token),
+            Some(Token::CurlyBraceOpen) => self.parse_destructuring(),
+            Some(Token::ParenOpen) => self.parse_block(None),
+            Some(Token::EndOfFile) => Ok(Expr::Atom(Atom::EndOfFile, self.current_span, NodeId::next())),
+            _ => Err(self.unexpected(vec![
+                Token::Identifier(String::new()),
+                Token::NumberLiteral(String::new()),
+                Token::StringLiteral(String::new()),
+                Token::CharLiteral(' '),
+                Token::BoolLiteral(false),
+                Token::Ellipse,
+                Token::CurlyBraceOpen,
+                Token::ParenOpen,
+            ])),
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        let _ = &self.skip_formating();
+        self.parse_binary(0)
+    }
+
+    // the operator at the current token, if it continues the expression.
+    // an operator at the start of a line or after a `,` starts a new item instead
+    fn infix_operator(&self) -> Option<(u8, BinaryOp)> {
+        match self.separated {
+            true => None,
+            false => self.current_token.as_ref().and_then(infix_operator),
+        }
+    }
+
+    // precedence climbing, every operator binds tighter than `min_precedence`
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let (precedence, op) = match self.infix_operator() {
+                Some((precedence, op)) if precedence >= min_precedence => (precedence, op),
+                _ => break,
+            };
+            self.next_token();
+            if op.is_comparison() {
+                left = self.parse_comparison(left, op)?;
+                continue;
+            }
+            self.expect_operand()?;
+            let right = match op {
+                BinaryOp::Reassignment => self.parse_binary(precedence)?,
+                _ => self.parse_binary(precedence + 1)?,
+            };
+            left = Expr::Binary(Binary {
+                op,
+                span: self.span_from(left.span()),
+                id: NodeId::next(),
+                left: Box::new(left),
+                right: Box::new(right),
+            });
         }
+        Ok(left)
     }
 
-    pub fn parse(&mut self) -> Result<Expr, ParseError> {
+    // `a < b`, or a chain like `a < b <= c`, the first operator has been consumed
+    fn parse_comparison(&mut self, first: Expr, op: BinaryOp) -> Result<Expr, ParseError> {
+        let mut operands = vec![first];
+        let mut ops = vec![op];
+        loop {
+            self.expect_operand()?;
+            operands.push(self.parse_binary(COMPARISON_PRECEDENCE + 1)?);
+            match self.infix_operator() {
+                Some((_, op)) if op.is_comparison() => {
+                    ops.push(op);
+                    self.next_token();
+                }
+                _ => break,
+            }
+        }
+        let span = self.span_from(operands[0].span());
+        if ops.len() == 1 {
+            let right = operands.pop().unwrap();
+            let left = operands.pop().unwrap();
+            return Ok(Expr::Binary(Binary {
+                op: ops.remove(0),
+                left: Box::new(left),
+                right: Box::new(right),
+                span,
+                id: NodeId::next(),
+            }));
+        }
+        Ok(Expr::Comparison(Comparison {
+            operands,
+            ops,
+            span,
+            id: NodeId::next(),
+        }))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let op = match &self.current_token {
+            Some(Token::Minus) => UnaryOp::Negate,
+            Some(Token::Not) => UnaryOp::Not,
+            Some(Token::BitwiseNot) => UnaryOp::BitwiseNot,
+            _ => return self.parse_primary(),
+        };
+        let start = self.current_span;
+        self.next_token();
+        self.expect_operand()?;
+        let expr = match (&op, &self.current_token) {
+            // the literal is range checked with its sign
+            (UnaryOp::Negate, Some(Token::NumberLiteral(_))) => self.parse_number_literal(true)?,
+            _ => self.parse_unary()?,
+        };
+        Ok(Expr::Unary(Unary {
+            op,
+            expr: Box::new(expr),
+            span: self.span_from(start),
+            id: NodeId::next(),
+        }))
+    }
+
+    // unlike an item, the operand of an operator can't be left out at the end of the file
+    fn expect_operand(&self) -> Result<(), ParseError> {
+        match &self.current_token {
+            None | Some(Token::EndOfFile) => Err(self.unexpected(vec![
+                Token::Identifier(String::new()),
+                Token::NumberLiteral(String::new()),
+                Token::StringLiteral(String::new()),
+                Token::ParenOpen,
+            ])),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Expr, Vec<ParseError>> {
+        let (expr, errors) = self.parse_recovering();
+        match errors.is_empty() {
+            true => Ok(expr),
+            false => Err(errors),
+        }
+    }
+
+    /// Parses the whole input, recovering from syntax errors.
+    /// Returns the AST with `Expr::Error` in place of anything that failed
+    /// to parse, along with every error that was found.
+    pub fn parse_recovering(&mut self) -> (Expr, Vec<ParseError>) {
         let mut expressions = Vec::new();
 
-        while let Some(token) = &self.current_token {
-            if token == &Token::EndOfFile {
-                break;
+        loop {
+            let _ = &self.skip_formating();
+            match &self.current_token {
+                None | Some(Token::EndOfFile) => break,
+                _ => {
+                    let expr = self.parse_item(Self::parse_expression);
+                    expressions.push(expr);
+                }
             }
-            expressions.push(self.parse_expression().unwrap());
         }
-        Ok(Expr::Group(Group {
+        let expr = Expr::Group(Group {
             exprs: expressions,
             op: GroupOp::StatementBlock,
-        }))
+            span: Span::new(self.lexer.file_id, 0, self.lexer.input.len(), 1, 1),
+            id: NodeId::next(),
+        });
+        (expr, std::mem::take(&mut self.errors))
     }
 }
 
@@ -518,39 +1027,52 @@ fn test_parser() {
     }";
     println!("Parsing string: {:?}", name_str);
     let lexer = Lexer::new(name_str);
-    let tokens = lexer.collect::<Vec<Token>>();
+    let tokens = lexer.map(|t| t.token).collect::<Vec<Token>>();
     println!("Parsed tokens: {:?}", tokens);
     let mut parser = Parser::new(name_str);
     let name_ast = parser.parse().unwrap();
     println!("Pared AST: {:?}", name_ast);
 
+    let ident = |name: &str, span: Span| Box::new(Expr::Atom(Atom::Identifier(name.to_string()), span, NodeId::next()));
     let name_ast_expected = Expr::Group(Group {
         op: GroupOp::StatementBlock,
         exprs: vec![Expr::Binary(Binary {
             op: BinaryOp::Assignment,
-            left: Box::new(Expr::Atom(Atom::Identifier("Name".to_string()))),
+            left: ident("Name", Span::new(0, 0, 4, 1, 1)),
             right: Box::new(Expr::Binary(Binary {
                 op: BinaryOp::TypeDef,
-                left: Box::new(Expr::Atom(Atom::Identifier("Type".to_string()))),
+                left: ident("Type", Span::new(0, 6, 10, 1, 7)),
                 right: Box::new(Expr::Group(Group {
                     op: GroupOp::AnonymousType,
                     exprs: vec![
                         Expr::Binary(Binary {
                             op: BinaryOp::Assignment,
-                            left: Box::new(Expr::Atom(Atom::Identifier("fistName".to_string()))),
-                            right: Box::new(Expr::Atom(Atom::Identifier("String".to_string()))),
+                            left: ident("fistName", Span::new(0, 21, 29, 2, 9)),
+                            right: ident("String", Span::new(0, 31, 37, 2, 19)),
+                            span: Span::new(0, 21, 37, 2, 9),
+                            id: NodeId::next(),
                         }),
                         Expr::Binary(Binary {
                             op: BinaryOp::Assignment,
-                            left: Box::new(Expr::Atom(Atom::Identifier("lastName".to_string()))),
-                            right: Box::new(Expr::Atom(Atom::Identifier("String".to_string()))),
+                            left: ident("lastName", Span::new(0, 47, 55, 3, 9)),
+                            right: ident("String", Span::new(0, 57, 63, 3, 19)),
+                            span: Span::new(0, 47, 63, 3, 9),
+                            id: NodeId::next(),
                         }),
                     ],
+                    span: Span::new(0, 11, 70, 1, 12),
+                    id: NodeId::next(),
                 })),
+                span: Span::new(0, 6, 70, 1, 7),
+                id: NodeId::next(),
             })),
+            span: Span::new(0, 0, 70, 1, 1),
+            id: NodeId::next(),
         })],
+        span: Span::new(0, 0, 70, 1, 1),
+        id: NodeId::next(),
     });
-    debug_assert!(name_ast == name_ast_expected);
+    assert_eq!(name_ast, name_ast_expected);
 }
 
 #[test]
@@ -579,7 +1101,7 @@ fn test_parser_1() {
         )
     ";
     let lexer = Lexer::new(name_str);
-    let tokens = lexer.collect::<Vec<Token>>();
+    let tokens = lexer.map(|t| t.token).collect::<Vec<Token>>();
     println!("Parsing string: {:?}", name_str);
     println!("Parsing tokens: {:?}", tokens);
     let mut parser = Parser::new(name_str);
@@ -589,3 +1111,275 @@ fn test_parser_1() {
         serde_json::to_string_pretty(&parser.parse()).unwrap()
     );
 }
+
+#[test]
+fn test_parse_error() {
+    let mut parser = Parser::new("person: Person(\n    name: \"John\",\n    age: }\n)");
+    let errors = parser.parse().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    let error = &errors[0];
+    assert_eq!(error.kind, ParseErrorKind::UnexpectedToken(Token::CurlyBraceClose));
+    assert_eq!(error.span, Span::new(0, 43, 44, 3, 10));
+    assert!(error.expected.contains(&Token::Identifier(String::new())));
+    assert!(error.message().starts_with("expected one of identifier, number literal"));
+
+    let mut parser = Parser::new("Name: {\n    first: String,");
+    let errors = parser.parse().unwrap_err();
+    assert_eq!(errors[0].kind, ParseErrorKind::UnexpectedEOF);
+    assert_eq!(errors[0].message(), "expected `}`, found end of file");
+}
+
+#[test]
+fn test_parse_lex_error() {
+    let mut parser = Parser::new("a: Int(1)\nb: String(\"unclosed)\n");
+    let errors = parser.parse().unwrap_err();
+    // the string swallows the rest of the file, so `String(` is never closed either
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].kind, ParseErrorKind::Lex(LexError::UnterminatedString));
+    assert_eq!(errors[0].span.line, 2);
+    assert_eq!(errors[0].message(), "unterminated string literal");
+}
+
+#[test]
+fn test_parse_interpolated_string() {
+    let input = "greeting: String.format(\"Hello \\(self.name)!\")";
+    let ast = Parser::new(input).parse().unwrap();
+    let string = Expr::InterpolatedString(InterpolatedString {
+        parts: vec![
+            Expr::Atom(Atom::Literal(Literal::StringLiteral("Hello ".to_string())), Span::new(0, 25, 31, 1, 26), NodeId::next()),
+            Expr::Binary(Binary {
+                op: BinaryOp::Accessor,
+                left: Box::new(Expr::Atom(Atom::Identifier("self".to_string()), Span::new(0, 33, 37, 1, 34), NodeId::next())),
+                right: Box::new(Expr::Atom(Atom::Identifier("name".to_string()), Span::new(0, 38, 42, 1, 39), NodeId::next())),
+                span: Span::new(0, 33, 42, 1, 34),
+                id: NodeId::next(),
+            }),
+            Expr::Atom(Atom::Literal(Literal::StringLiteral("!".to_string())), Span::new(0, 43, 44, 1, 44), NodeId::next()),
+        ],
+        span: Span::new(0, 24, 45, 1, 25),
+        id: NodeId::next(),
+    });
+    let found = match ast {
+        Expr::Group(Group { exprs, .. }) => match &exprs[0] {
+            Expr::Binary(Binary { right, .. }) => match &**right {
+                Expr::Binary(Binary { right, .. }) => match &**right {
+                    Expr::Binary(Binary { right, .. }) => match &**right {
+                        Expr::Group(Group { exprs, .. }) => exprs[0].clone(),
+                        expr => panic!("expected params, got {:?}", expr),
+                    },
+                    expr => panic!("expected a call, got {:?}", expr),
+                },
+                expr => panic!("expected an accessor, got {:?}", expr),
+            },
+            expr => panic!("expected an assignment, got {:?}", expr),
+        },
+        expr => panic!("expected a statement block, got {:?}", expr),
+    };
+    assert_eq!(found, string);
+
+    let errors = Parser::new("s: \"\\() and ${a b}\"").parse().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].span, Span::new(0, 6, 6, 1, 7));
+}
+
+#[test]
+fn test_parse_number() {
+    assert_eq!(parse_number("1_000", false), Ok(Literal::IntLiteral(1000, None)));
+    assert_eq!(parse_number("0xdead_BEEF", false), Ok(Literal::HexLiteral(0xdead_beef, None)));
+    assert_eq!(parse_number("0o17u8", false), Ok(Literal::OctalLiteral(15, Some(NumberSuffix::U8))));
+    assert_eq!(parse_number("2.5e-3", false), Ok(Literal::FloatLiteral(2.5e-3, None)));
+    assert_eq!(parse_number("1e3", false), Ok(Literal::FloatLiteral(1000.0, None)));
+    assert_eq!(parse_number("1f32", false), Ok(Literal::FloatLiteral(1.0, Some(NumberSuffix::F32))));
+    assert_eq!(
+        parse_number("0xffff_ffff_ffff_ffff_ffffu128", false),
+        Ok(Literal::HexLiteral(0xffff_ffff_ffff_ffff_ffff, Some(NumberSuffix::U128)))
+    );
+
+    assert_eq!(parse_number("0x", false), Err(NumberError::NoDigits));
+    assert_eq!(parse_number("0b102", false), Err(NumberError::InvalidDigit('2', 2)));
+    assert_eq!(parse_number("1.0i32", false), Err(NumberError::InvalidSuffix("i32".to_string())));
+    assert_eq!(parse_number("1i7", false), Err(NumberError::InvalidSuffix("i7".to_string())));
+    assert_eq!(parse_number("256u8", false), Err(NumberError::OutOfRange(Some(NumberSuffix::U8))));
+    assert_eq!(parse_number("128i8", false), Err(NumberError::OutOfRange(Some(NumberSuffix::I8))));
+    assert_eq!(parse_number("127i8", false), Ok(Literal::IntLiteral(127, Some(NumberSuffix::I8))));
+    assert_eq!(parse_number("128i8", true), Ok(Literal::IntLiteral(128, Some(NumberSuffix::I8))));
+    assert_eq!(parse_number("129i8", true), Err(NumberError::OutOfRange(Some(NumberSuffix::I8))));
+    assert_eq!(parse_number("256u8", true), Err(NumberError::OutOfRange(Some(NumberSuffix::U8))));
+    assert_eq!(parse_number("9223372036854775807", false), Ok(Literal::IntLiteral(i64::MAX as u128, None)));
+    assert_eq!(parse_number("9223372036854775808", false), Err(NumberError::OutOfRange(None)));
+    assert_eq!(parse_number("9223372036854775808", true), Ok(Literal::IntLiteral(1 << 63, None)));
+    assert_eq!(parse_number("9223372036854775809", true), Err(NumberError::OutOfRange(None)));
+    assert_eq!(parse_number("1e999", false), Err(NumberError::OutOfRange(Some(NumberSuffix::F64))));
+
+    let errors = Parser::new("byte: 300u8").parse().unwrap_err();
+    assert_eq!(errors[0].message(), "number literal `300u8` is out of range for `u8`");
+    assert_eq!(errors[0].span, Span::new(0, 6, 11, 1, 7));
+    assert!(Parser::new("min: -128i8\nlong: -9223372036854775808").parse().is_ok());
+    let errors = Parser::new("byte: -129i8").parse().unwrap_err();
+    assert_eq!(errors[0].message(), "number literal `-129i8` is out of range for `i8`");
+}
+
+#[test]
+fn test_parse_operators() {
+    // writes the tree back out with explicit parentheses
+    fn shape(expr: &Expr) -> String {
+        match expr {
+            Expr::Atom(Atom::Identifier(name), _, _) => name.clone(),
+            Expr::Atom(Atom::Literal(Literal::IntLiteral(value, _)), _, _) => value.to_string(),
+            Expr::Unary(unary) => format!("({:?} {})", unary.op, shape(&unary.expr)),
+            Expr::Binary(binary) => format!(
+                "({:?} {} {})",
+                binary.op,
+                shape(&binary.left),
+                shape(&binary.right)
+            ),
+            Expr::Comparison(comparison) => format!(
+                "(Comparison {:?} {})",
+                comparison.ops,
+                comparison.operands.iter().map(shape).collect::<Vec<_>>().join(" ")
+            ),
+            Expr::Group(group) => group.exprs.iter().map(shape).collect::<Vec<_>>().join("; "),
+            expr => panic!("unexpected {:?}", expr),
+        }
+    }
+    let parse = |input: &str| shape(&Parser::new(input).parse().unwrap());
+
+    assert_eq!(parse("a + b * c - d"), "(Subtract (Add a (Multiply b c)) d)");
+    assert_eq!(
+        parse("a || b && c | d ^ e & f << 1"),
+        "(Or a (And b (BitwiseOr c (BitwiseXor d (BitwiseAnd e (ShiftLeft f 1))))))"
+    );
+    assert_eq!(parse("-a.b * ~c"), "(Multiply (Negate (Accessor a b)) (BitwiseNot c))");
+    assert_eq!(parse("x: !a == b"), "(Assignment x (Equal (Not a) b))");
+    assert_eq!(
+        parse("x < y + 1 <= z && ok"),
+        "(And (Comparison [LessThan, LessThanOrEqual] x (Add y 1) z) ok)"
+    );
+    // an operator at the start of a line begins a new item
+    assert_eq!(parse("a: b\n-c"), "(Assignment a b); (Negate c)");
+    assert_eq!(parse("f(a, -b)"), "(Invoke f a; (Negate b))");
+
+    let errors = Parser::new("a: b +").parse().unwrap_err();
+    assert_eq!(errors[0].kind, ParseErrorKind::UnexpectedEOF);
+}
+
+#[test]
+fn test_parse_directives() {
+    let input = "std: @std
+io: @import \"io.zen\"
+@this.count = @this.count + 1
+";
+    let exprs = match Parser::new(input).parse().unwrap() {
+        Expr::Group(group) => group.exprs,
+        expr => panic!("expected a statement block, got {:?}", expr),
+    };
+    assert_eq!(exprs.len(), 3);
+    let directive = |name: &str, args: Option<Expr>, span: Span| {
+        Box::new(Expr::Directive(Directive {
+            name: name.to_string(),
+            args: args.map(Box::new),
+            span,
+            id: NodeId::next(),
+        }))
+    };
+    assert_eq!(
+        exprs[0],
+        Expr::Binary(Binary {
+            op: BinaryOp::Assignment,
+            left: Box::new(Expr::Atom(Atom::Identifier("std".to_string()), Span::new(0, 0, 3, 1, 1), NodeId::next())),
+            right: directive("std", None, Span::new(0, 5, 9, 1, 6)),
+            span: Span::new(0, 0, 9, 1, 1),
+            id: NodeId::next(),
+        })
+    );
+    let import = Expr::Atom(
+        Atom::Literal(Literal::StringLiteral("io.zen".to_string())),
+        Span::new(0, 22, 30, 2, 13),
+        NodeId::next(),
+    );
+    match &exprs[1] {
+        Expr::Binary(Binary { right, .. }) => {
+            assert_eq!(*right, directive("import", Some(import), Span::new(0, 14, 30, 2, 5)))
+        }
+        expr => panic!("expected an assignment, got {:?}", expr),
+    }
+    match &exprs[2] {
+        Expr::Binary(Binary { op: BinaryOp::Reassignment, left, right, .. }) => {
+            assert!(matches!(&**left, Expr::Binary(Binary { op: BinaryOp::Accessor, .. })));
+            assert!(matches!(&**right, Expr::Binary(Binary { op: BinaryOp::Add, .. })));
+        }
+        expr => panic!("expected a reassignment, got {:?}", expr),
+    }
+}
+
+#[test]
+fn test_parse_error_recovery() {
+    let input = "a: String(\"a\")
+b: )
+c: Int(1, +)
+Name: {
+    first: String,
+    last: +,
+    age: Int,
+}
+d: Int(2)
+";
+    let mut parser = Parser::new(input);
+    let (ast, errors) = parser.parse_recovering();
+    let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+    assert_eq!(lines, vec![2, 3, 6]);
+
+    let exprs = match ast {
+        Expr::Group(group) => group.exprs,
+        _ => panic!("expected a statement block"),
+    };
+    assert_eq!(exprs.len(), 5);
+    assert!(matches!(exprs[1], Expr::Error(_, _)));
+    match &exprs[3] {
+        Expr::Binary(Binary { right, .. }) => match &**right {
+            Expr::Group(Group { exprs, .. }) => {
+                assert_eq!(exprs.len(), 3);
+                assert!(matches!(exprs[1], Expr::Error(_, _)));
+            }
+            expr => panic!("expected a type body, got {:?}", expr),
+        },
+        expr => panic!("expected an assignment, got {:?}", expr),
+    }
+}
+
+#[test]
+fn test_parse_type_bodies() {
+    let input = "Color: Enum { Red, Green }
+Point: { x: Int, y: Int }
+{ name, country }: john
+";
+    let exprs = match Parser::new(input).parse().unwrap() {
+        Expr::Group(group) => group.exprs,
+        expr => panic!("expected a statement block, got {:?}", expr),
+    };
+    assert_eq!(exprs.len(), 3);
+    let body = |expr: &Expr| match expr {
+        Expr::Binary(Binary { op: BinaryOp::Assignment, right, .. }) => match &**right {
+            Expr::Binary(Binary { op: BinaryOp::TypeDef, right, .. }) => match &**right {
+                Expr::Group(group) => (group.op.clone(), group.exprs.len()),
+                expr => panic!("expected a type body, got {:?}", expr),
+            },
+            expr => panic!("expected a type def, got {:?}", expr),
+        },
+        expr => panic!("expected an assignment, got {:?}", expr),
+    };
+    assert_eq!(body(&exprs[0]), (GroupOp::StatementBlock, 2));
+    match &exprs[1] {
+        Expr::Binary(Binary { right, .. }) => {
+            assert!(matches!(&**right, Expr::Group(Group { op: GroupOp::AnonymousType, .. })))
+        }
+        expr => panic!("expected an assignment, got {:?}", expr),
+    }
+    match &exprs[2] {
+        Expr::Binary(Binary { op: BinaryOp::Assignment, left, right, .. }) => {
+            assert!(matches!(&**left, Expr::Group(Group { exprs, .. }) if exprs.len() == 2));
+            assert!(matches!(&**right, Expr::Atom(Atom::Identifier(name), _, _) if name == "john"));
+        }
+        expr => panic!("expected a destructuring assignment, got {:?}", expr),
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::ast::{Expr, Group, GroupOp, NodeId};
+use crate::codegen;
+use crate::diagnostics::Diagnostic;
+use crate::driver::{format_token, Driver};
+use crate::interp::{self, Control, Interpreter, Value};
+use crate::lexer::{LexError, Lexer};
+use crate::resolve;
+use crate::span::Span;
+use crate::token::Token;
+use crate::typeck::{self, Type};
+
+const PROMPT: &str = "zen> ";
+const CONTINUE_PROMPT: &str = "...> ";
+
+const HELP: &str = "\
+:ast [code]     show the AST
+:tokens [code]  show the tokens
+:type [code]    show the type
+:ir [code]      show the LLVM IR
+:help           show this message
+:quit           leave the session
+
+without code the commands use the previous entry, `:ir` uses the whole session";
+
+/// Whether `input` can be parsed as it is, or is still inside a `{}` / `()`
+/// block or string and needs more lines.
+pub fn is_complete(input: &str) -> bool {
+    let mut depth: i32 = 0;
+    for spanned in Lexer::new(input) {
+        match spanned.token {
+            Token::CurlyBraceOpen | Token::ParenOpen => depth += 1,
+            Token::CurlyBraceClose | Token::ParenClose => depth -= 1,
+            Token::Error(LexError::UnterminatedString) => return false,
+            _ => {}
+        }
+    }
+    // a stray closer is an error the parser should report now
+    depth <= 0
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".zen_history"))
+}
+
+/// An interactive session for `zen repl`. Every entry that parses is kept,
+/// so later entries and `:ir` can see what was declared before.
+pub struct Repl {
+    driver: Driver,
+    // what the program prints is buffered and copied to the output after each entry
+    interpreter: Interpreter<Vec<u8>>,
+    bindings: Vec<Expr>,
+    // source of the last entry, for meta-commands without code
+    previous: Option<String>,
+    entries: usize,
+}
+
+impl Repl {
+    pub fn new(driver: Driver) -> Self {
+        Repl {
+            driver,
+            interpreter: Interpreter::new(BufReader::new(std::io::stdin()), Vec::new()),
+            bindings: Vec::new(),
+            previous: None,
+            entries: 0,
+        }
+    }
+
+    pub fn run(&mut self) -> ExitCode {
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(error) => {
+                let diagnostic = Diagnostic::error(format!("failed to start the repl: {}", error));
+                self.driver.emit(&[diagnostic]);
+                return ExitCode::FAILURE;
+            }
+        };
+        let history = history_path();
+        if let Some(history) = &history {
+            // there is no history the first time
+            let _ = editor.load_history(history);
+        }
+        println!("Zen {}, :help for help", env!("CARGO_PKG_VERSION"));
+
+        let mut input = String::new();
+        loop {
+            let prompt = if input.is_empty() {
+                PROMPT
+            } else {
+                CONTINUE_PROMPT
+            };
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    input.push_str(&line);
+                    input.push('\n');
+                    if !is_complete(&input) {
+                        continue;
+                    }
+                    let entry = std::mem::take(&mut input);
+                    if entry.trim().is_empty() {
+                        continue;
+                    }
+                    let _ = editor.add_history_entry(entry.trim_end());
+                    if !self.handle(&entry, &mut std::io::stdout()) {
+                        break;
+                    }
+                }
+                // ctrl-c drops the current entry, ctrl-d ends the session
+                Err(ReadlineError::Interrupted) => input.clear(),
+                Err(ReadlineError::Eof) => break,
+                Err(error) => {
+                    self.driver.emit(&[Diagnostic::error(error.to_string())]);
+                    break;
+                }
+            }
+        }
+        if let Some(history) = &history {
+            let _ = editor.save_history(history);
+        }
+        ExitCode::SUCCESS
+    }
+
+    /// Handles one complete entry, returns false when the session should end.
+    pub fn handle(&mut self, entry: &str, out: &mut dyn Write) -> bool {
+        match entry.trim().strip_prefix(':') {
+            Some(command) => {
+                let (name, code) = command
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((command, ""));
+                self.command(name, code.trim(), out)
+            }
+            None => self.entry(entry, out),
+        }
+    }
+
+    fn command(&mut self, name: &str, code: &str, out: &mut dyn Write) -> bool {
+        match name {
+            "q" | "quit" | "exit" => return false,
+            "help" => {
+                let _ = writeln!(out, "{}", HELP);
+            }
+            "tokens" => {
+                if let Some(file_id) = self.load(code) {
+                    for spanned in self.driver.tokens(file_id) {
+                        let _ = writeln!(out, "{}", format_token(&spanned));
+                    }
+                }
+            }
+            "ast" => {
+                if let Some(ast) = self.load(code).and_then(|file_id| self.parse(file_id)) {
+                    let _ = writeln!(out, "{:#?}", ast);
+                }
+            }
+            "type" => {
+                if let Some(ast) = self.load(code).and_then(|file_id| self.parse(file_id)) {
+                    let _ = writeln!(out, "{}", self.type_of(&ast));
+                }
+            }
+            "ir" => {
+                let ast = match code {
+                    "" => Some(self.session()),
+                    code => self.load(code).and_then(|file_id| self.parse(file_id)),
+                };
+                let program = ast.and_then(|ast| self.driver.lower(&ast));
+                match program.map(|program| codegen::emit_ir(&program, "repl")) {
+                    Some(Ok(ir)) => {
+                        let _ = writeln!(out, "{}", ir);
+                    }
+                    Some(Err(error)) => self.driver.emit(&[Diagnostic::from(&error)]),
+                    None => {}
+                }
+            }
+            name => {
+                let diagnostic = Diagnostic::error(format!("unknown command `:{}`", name))
+                    .with_note("`:help` lists the commands");
+                self.driver.emit(&[diagnostic]);
+            }
+        }
+        true
+    }
+
+    // evaluates the entry and shows its value, `exit()` ends the session
+    fn entry(&mut self, entry: &str, out: &mut dyn Write) -> bool {
+        self.previous = Some(entry.to_string());
+        let ast = match self.load(entry).and_then(|file_id| self.parse(file_id)) {
+            Some(ast) => ast,
+            None => return true,
+        };
+        let interpreter = &mut self.interpreter;
+        let result = interp::with_stack(|| interpreter.eval_entry(&ast));
+        let _ = out.write_all(&std::mem::take(&mut self.interpreter.out));
+        // an entry that failed is not part of the session
+        if let (Ok(_), Expr::Group(group)) = (&result, ast) {
+            self.bindings.extend(group.exprs);
+        }
+        match result {
+            Ok(Value::Void) => true,
+            Ok(value) => {
+                let _ = writeln!(out, "{}", value);
+                true
+            }
+            Err(Control::Return(_)) => true,
+            Err(Control::Exit(_)) => false,
+            Err(Control::Error(error)) => {
+                self.driver.emit(&[Diagnostic::from(&error)]);
+                true
+            }
+        }
+    }
+
+    // every entry gets its own file so diagnostics point at the right one,
+    // no code means the previous entry
+    fn load(&mut self, code: &str) -> Option<usize> {
+        let code = match (code, &self.previous) {
+            ("", Some(previous)) => previous.clone(),
+            ("", None) => {
+                self.driver
+                    .emit(&[Diagnostic::error("there is no previous entry")]);
+                return None;
+            }
+            (code, _) => code.to_string(),
+        };
+        self.entries += 1;
+        Some(
+            self.driver
+                .sources
+                .add(format!("<repl:{}>", self.entries), code),
+        )
+    }
+
+    fn parse(&self, file_id: usize) -> Option<Expr> {
+        match self.driver.parse(file_id) {
+            Ok(ast) => Some(ast),
+            Err(diagnostics) => {
+                self.driver.emit(&diagnostics);
+                None
+            }
+        }
+    }
+
+    fn session(&self) -> Expr {
+        Expr::Group(Group {
+            op: GroupOp::StatementBlock,
+            exprs: self.bindings.clone(),
+            span: Span::default(),
+            id: NodeId::next(),
+        })
+    }
+
+    // the entry is checked after the session so it sees what was declared
+    // before, only its own errors are reported
+    fn type_of(&self, entry: &Expr) -> Type {
+        let exprs = match entry {
+            Expr::Group(group) => &group.exprs[..],
+            entry => std::slice::from_ref(entry),
+        };
+        let mut program = self.session();
+        if let Expr::Group(group) = &mut program {
+            group.exprs.extend(exprs.iter().cloned());
+        }
+        let resolution = resolve::resolve(&program);
+        let types = typeck::check(&program, &resolution);
+        let file_id = entry.span().file_id;
+        let diagnostics: Vec<Diagnostic> = resolution
+            .errors
+            .iter()
+            .filter(|error| error.span.file_id == file_id && !error.is_warning())
+            .map(Diagnostic::from)
+            .chain(
+                types
+                    .errors
+                    .iter()
+                    .filter(|error| error.span.file_id == file_id)
+                    .map(Diagnostic::from),
+            )
+            .collect();
+        self.driver.emit(&diagnostics);
+        exprs.last().map_or(Type::Void, |expr| types.of(expr))
+    }
+}
+
+#[test]
+fn test_repl_is_complete() {
+    assert!(is_complete("a: 1\n"));
+    assert!(!is_complete("f: Fn {\n    a: 1\n"));
+    assert!(!is_complete("s: String(\"two\nlines"));
+    assert!(is_complete("f: Fn {\n    a: Int(\n        1)\n}\n"));
+    // left for the parser to report
+    assert!(is_complete("a: )\n"));
+}
+
+#[test]
+fn test_repl_session() {
+    use crate::diagnostics::{Emitter, ErrorFormat};
+
+    let driver = Driver::new(Emitter::with_colour(ErrorFormat::Human, false));
+    let mut repl = Repl::new(driver);
+    let mut out = Vec::new();
+    assert!(repl.handle("a: Int(1)\n", &mut out));
+    // a bad entry is reported and the session carries on
+    assert!(repl.handle("b: )\n", &mut out));
+    assert!(repl.handle("b: a + 2\n", &mut out));
+    assert!(repl.handle("io.std.writeLine(b)\nb * 2", &mut out));
+    // nor does one that fails while running
+    assert!(repl.handle("c: b / 0\n", &mut out));
+    assert_eq!(String::from_utf8(std::mem::take(&mut out)).unwrap(), "3\n6\n");
+
+    assert!(repl.handle(":type b", &mut out));
+    assert!(repl.handle(":type a < b", &mut out));
+    assert!(repl.handle(":tokens a.b", &mut out));
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Int\nBool\n1:1 Identifier(\"a\")\n1:2 Dot\n1:3 Identifier(\"b\")\n1:4 EndOfFile\n"
+    );
+    assert_eq!(repl.bindings.len(), 4);
+    assert!(!repl.handle(":quit", &mut Vec::new()));
+    assert!(!repl.handle("exit()", &mut Vec::new()));
+}
@@ -0,0 +1,715 @@
+// Name resolution: links every identifier to the declaration it refers to,
+// with the same scoping rules as the interpreter.
+//
+// There is no shadowing, `name: value` only declares `name` when it is not
+// already visible, otherwise it assigns to the outer `name`. A function body
+// sees its parameters, its own locals and the file, not the locals of the
+// function it is written in. Only parameters can hide an outer name, which is
+// reported as a warning.
+
+use std::collections::HashMap;
+
+use crate::ast::{Atom, Binary, BinaryOp, Expr, Group, GroupOp, Ternary, Unary, UnaryOp};
+use crate::builtins;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+
+// the file scope is always the first one
+const FILE_SCOPE: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclKind {
+    Variable,
+    Type,
+    Function,
+    Param,
+    Field,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeKind {
+    File,
+    // the fields of a type or record, they are not visible as variables
+    Type,
+    Args,
+    Function,
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclKind,
+    pub span: Span,
+    pub scope: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    pub names: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveErrorKind {
+    UndefinedName(String),
+    // the span of the first definition
+    DuplicateField(String, Span),
+    Shadowing(String, Span),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub kind: ResolveErrorKind,
+    pub span: Span,
+}
+
+impl ResolveError {
+    pub fn is_warning(&self) -> bool {
+        matches!(self.kind, ResolveErrorKind::Shadowing(..))
+    }
+}
+
+impl std::fmt::Display for ResolveErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveErrorKind::UndefinedName(name) => write!(f, "`{}` is not defined", name),
+            ResolveErrorKind::DuplicateField(name, _) => {
+                write!(f, "`{}` is defined more than once", name)
+            }
+            ResolveErrorKind::Shadowing(name, _) => {
+                write!(f, "parameter `{}` hides a name from the file", name)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        let message = error.kind.to_string();
+        match &error.kind {
+            ResolveErrorKind::UndefinedName(_) => {
+                Diagnostic::error(message).with_primary(error.span, "not found in this scope")
+            }
+            ResolveErrorKind::DuplicateField(_, first) => Diagnostic::error(message)
+                .with_primary(error.span, "defined again here")
+                .with_secondary(*first, "first defined here"),
+            ResolveErrorKind::Shadowing(name, outer) => Diagnostic::warning(message)
+                .with_primary(error.span, "parameter declared here")
+                .with_secondary(*outer, "this is hidden inside the function")
+                .with_note(format!(
+                    "assigning to `{}` in the function changes the parameter, not the outer `{}`",
+                    name, name
+                )),
+        }
+    }
+}
+
+/// The scopes and declarations of a file, and what every identifier refers to.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub scopes: Vec<Scope>,
+    pub declarations: Vec<Declaration>,
+    // identifier span -> index into `declarations`, builtins are left out
+    pub uses: HashMap<Span, usize>,
+    pub errors: Vec<ResolveError>,
+}
+
+impl Resolution {
+    /// The declaration the identifier at `span` refers to.
+    pub fn declaration(&self, span: Span) -> Option<&Declaration> {
+        self.uses.get(&span).map(|id| &self.declarations[*id])
+    }
+}
+
+pub fn resolve(program: &Expr) -> Resolution {
+    let mut resolver = Resolver {
+        resolution: Resolution::default(),
+        scope: FILE_SCOPE,
+    };
+    resolver.push_scope(ScopeKind::File, None);
+    resolver.file(program);
+    resolver.resolution
+}
+
+fn identifier(expr: &Expr) -> Option<(&str, Span)> {
+    match expr {
+        Expr::Atom(Atom::Identifier(name), span, _) => Some((name, *span)),
+        _ => None,
+    }
+}
+
+fn items(expr: &Expr) -> &[Expr] {
+    match expr {
+        Expr::Group(group) => &group.exprs,
+        expr => std::slice::from_ref(expr),
+    }
+}
+
+// what `name: value` declares, going by the shape of `value`
+fn kind_of(name: &str, value: &Expr) -> DeclKind {
+    let capitalised = name.starts_with(|c: char| c.is_ascii_uppercase());
+    match value {
+        Expr::Binary(Binary {
+            op: BinaryOp::TypeDef,
+            left,
+            ..
+        }) => match identifier(left) {
+            Some(("Fn" | "Function", _)) => DeclKind::Function,
+            Some(("Type" | "Enum", _)) => DeclKind::Type,
+            _ => DeclKind::Variable,
+        },
+        Expr::Group(_) if capitalised => DeclKind::Type,
+        _ => DeclKind::Variable,
+    }
+}
+
+// a block like `{ a: 1, ...b, c }` that builds a record rather than running statements
+fn is_record(exprs: &[Expr]) -> bool {
+    exprs.iter().all(|expr| {
+        matches!(
+            expr,
+            Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                ..
+            }) | Expr::Unary(Unary {
+                op: UnaryOp::SpreadExpr,
+                ..
+            }) | Expr::Atom(Atom::Identifier(_), _, _)
+        )
+    })
+}
+
+struct Resolver {
+    resolution: Resolution,
+    scope: usize,
+}
+
+impl Resolver {
+    fn push_scope(&mut self, kind: ScopeKind, parent: Option<usize>) -> usize {
+        self.resolution.scopes.push(Scope {
+            kind,
+            parent,
+            names: HashMap::new(),
+        });
+        self.resolution.scopes.len() - 1
+    }
+
+    // runs `f` with `scope` as the current scope
+    fn within(&mut self, scope: usize, f: impl FnOnce(&mut Self)) {
+        let outer = self.scope;
+        self.scope = scope;
+        f(self);
+        self.scope = outer;
+    }
+
+    fn declare(&mut self, name: &str, kind: DeclKind, span: Span, scope: usize) -> usize {
+        let id = self.resolution.declarations.len();
+        self.resolution.declarations.push(Declaration {
+            name: name.to_string(),
+            kind,
+            span,
+            scope,
+        });
+        self.resolution.scopes[scope]
+            .names
+            .insert(name.to_string(), id);
+        id
+    }
+
+    // fields and parameters have to be unique within their type or `args`
+    fn declare_field(&mut self, name: &str, kind: DeclKind, span: Span, scope: usize) {
+        match self.resolution.scopes[scope].names.get(name) {
+            Some(first) => {
+                let first = self.resolution.declarations[*first].span;
+                self.error(
+                    ResolveErrorKind::DuplicateField(name.to_string(), first),
+                    span,
+                );
+            }
+            None => {
+                self.declare(name, kind, span, scope);
+            }
+        }
+    }
+
+    fn error(&mut self, kind: ResolveErrorKind, span: Span) {
+        self.resolution.errors.push(ResolveError { kind, span });
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        let mut scope = Some(self.scope);
+        while let Some(current) = scope {
+            let current = &self.resolution.scopes[current];
+            if let Some(id) = current.names.get(name) {
+                return Some(*id);
+            }
+            scope = current.parent;
+        }
+        None
+    }
+
+    fn link(&mut self, id: usize, span: Span) {
+        // a top level declaration is found again when its own assignment is resolved
+        if self.resolution.declarations[id].span != span {
+            self.resolution.uses.insert(span, id);
+        }
+    }
+
+    fn use_name(&mut self, name: &str, span: Span) {
+        match self.lookup(name) {
+            Some(id) => self.link(id, span),
+            None if builtins::is_builtin(name) => {}
+            None => self.error(ResolveErrorKind::UndefinedName(name.to_string()), span),
+        }
+    }
+
+    // `name: value` assigns to a visible `name`, or declares it in the current scope
+    fn bind(&mut self, name: &str, kind: DeclKind, span: Span) {
+        match self.lookup(name) {
+            Some(id) => self.link(id, span),
+            None => {
+                self.declare(name, kind, span, self.scope);
+            }
+        }
+    }
+
+    fn file(&mut self, program: &Expr) {
+        // top level names can be used before they are declared, a function body
+        // only runs once the whole file has
+        for expr in items(program) {
+            if let Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                left,
+                right,
+                ..
+            }) = expr
+            {
+                for target in items(left) {
+                    if let Some((name, span)) = identifier(target) {
+                        if self.lookup(name).is_none() {
+                            self.declare(name, kind_of(name, right), span, FILE_SCOPE);
+                        }
+                    }
+                }
+            }
+        }
+        for expr in items(program) {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Atom(Atom::Identifier(name), span, _) => self.use_name(name, *span),
+            Expr::Atom(..) | Expr::Error(_, _) => {}
+            Expr::Unary(unary) => self.expr(&unary.expr),
+            Expr::Binary(binary) => self.binary(binary),
+            Expr::Comparison(comparison) => {
+                for operand in &comparison.operands {
+                    self.expr(operand);
+                }
+            }
+            Expr::Ternary(Ternary {
+                left,
+                middle,
+                right,
+                ..
+            }) => self.invoke(left, middle, Some(right)),
+            Expr::Group(group) => self.group(group),
+            Expr::InterpolatedString(string) => {
+                for part in &string.parts {
+                    self.expr(part);
+                }
+            }
+            // the arguments are paths and source, not names
+            Expr::Directive(_) => {}
+        }
+    }
+
+    fn binary(&mut self, binary: &Binary) {
+        match binary.op {
+            BinaryOp::Assignment => {
+                self.expr(&binary.right);
+                self.assign(&binary.left, &binary.right);
+            }
+            BinaryOp::Reassignment => {
+                self.expr(&binary.right);
+                self.target(&binary.left);
+            }
+            BinaryOp::Accessor => self.accessor(binary),
+            BinaryOp::Invoke => self.invoke(&binary.left, &binary.right, None),
+            BinaryOp::TypeDef => self.typedef(&binary.left, &binary.right),
+            _ => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+            }
+        }
+    }
+
+    fn assign(&mut self, target: &Expr, value: &Expr) {
+        match target {
+            Expr::Atom(Atom::Identifier(name), span, _) => {
+                self.bind(name, kind_of(name, value), *span)
+            }
+            // `{ name, age }: person`
+            Expr::Group(group) => {
+                for expr in &group.exprs {
+                    match identifier(expr) {
+                        Some((name, span)) => self.bind(name, DeclKind::Variable, span),
+                        None => self.expr(expr),
+                    }
+                }
+            }
+            target => self.target(target),
+        }
+    }
+
+    // `a = b` and `a.b.c = d` need `a` to exist already
+    fn target(&mut self, target: &Expr) {
+        match target {
+            Expr::Binary(Binary {
+                op: BinaryOp::Accessor,
+                left,
+                ..
+            }) => self.target(left),
+            Expr::Group(group) => {
+                for expr in &group.exprs {
+                    self.target(expr);
+                }
+            }
+            target => self.expr(target),
+        }
+    }
+
+    fn group(&mut self, group: &Group) {
+        match group.op {
+            GroupOp::AnonymousType | GroupOp::AssignmentBlock => self.record(&group.exprs),
+            GroupOp::StatementBlock
+                if group.exprs.iter().any(|expr| {
+                    matches!(
+                        expr,
+                        Expr::Unary(Unary {
+                            op: UnaryOp::SpreadExpr,
+                            ..
+                        })
+                    )
+                }) =>
+            {
+                self.record(&group.exprs)
+            }
+            GroupOp::StatementBlock => self.block(&group.exprs),
+            GroupOp::ParamBlock => {
+                for expr in &group.exprs {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, exprs: &[Expr]) {
+        let scope = self.push_scope(ScopeKind::Block, Some(self.scope));
+        self.within(scope, |resolver| {
+            for expr in exprs {
+                resolver.expr(expr);
+            }
+        });
+    }
+
+    // the values are resolved in the enclosing scope, the field names are not variables
+    fn record(&mut self, exprs: &[Expr]) {
+        let fields = self.push_scope(ScopeKind::Type, Some(self.scope));
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    self.expr(right);
+                    let (name, span) = identifier(left).unwrap();
+                    self.declare_field(name, DeclKind::Field, span, fields);
+                }
+                // shorthand for `name: name`
+                Expr::Atom(Atom::Identifier(name), span, _) => {
+                    self.use_name(name, *span);
+                    self.declare_field(name, DeclKind::Field, *span, fields);
+                }
+                expr => self.expr(expr),
+            }
+        }
+    }
+
+    // `Rgb: Enum { Red, Green: "green" }`, bare names are variants rather than uses
+    fn variants(&mut self, exprs: &[Expr]) {
+        let variants = self.push_scope(ScopeKind::Type, Some(self.scope));
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    self.expr(right);
+                    let (name, span) = identifier(left).unwrap();
+                    self.declare_field(name, DeclKind::Field, span, variants);
+                }
+                Expr::Atom(Atom::Identifier(name), span, _) => {
+                    self.declare_field(name, DeclKind::Field, *span, variants)
+                }
+                expr => self.expr(expr),
+            }
+        }
+    }
+
+    // `Fn { args: { a: Int }, return: Int, body: { ... } }`
+    fn function(&mut self, exprs: &[Expr], span: Span) {
+        // the body can't see the locals of the function it is written in
+        let args = self.push_scope(ScopeKind::Args, Some(FILE_SCOPE));
+        let mut body = None;
+        for expr in exprs {
+            let (name, value) = match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => (identifier(left).unwrap().0, &**right),
+                expr => {
+                    self.expr(expr);
+                    continue;
+                }
+            };
+            match name {
+                "args" => {
+                    for arg in items(value) {
+                        let param = match arg {
+                            Expr::Binary(Binary {
+                                op: BinaryOp::Assignment,
+                                left,
+                                right,
+                                ..
+                            }) => {
+                                self.expr(right);
+                                identifier(left)
+                            }
+                            arg => identifier(arg),
+                        };
+                        if let Some((param, span)) = param {
+                            self.param(param, span, args);
+                        }
+                    }
+                }
+                "body" | "fn" => body = Some(value),
+                _ => self.expr(value),
+            }
+        }
+
+        let scope = self.push_scope(ScopeKind::Function, Some(args));
+        // set up by the interpreter for every call
+        self.declare("args", DeclKind::Variable, span, scope);
+        self.declare("return", DeclKind::Variable, span, scope);
+        if let Some(body) = body {
+            self.within(scope, |resolver| {
+                for expr in items(body) {
+                    resolver.expr(expr);
+                }
+            });
+        }
+    }
+
+    fn param(&mut self, name: &str, span: Span, args: usize) {
+        if let Some(outer) = self.resolution.scopes[FILE_SCOPE].names.get(name) {
+            let outer = self.resolution.declarations[*outer].span;
+            self.error(ResolveErrorKind::Shadowing(name.to_string(), outer), span);
+        }
+        self.declare_field(name, DeclKind::Param, span, args);
+    }
+
+    fn typedef(&mut self, left: &Expr, block: &Expr) {
+        let exprs = items(block);
+        match identifier(left) {
+            Some(("Type", _)) => return self.record(exprs),
+            Some(("Fn" | "Function", _)) => return self.function(exprs, block.span()),
+            Some(("Enum", _)) => return self.variants(exprs),
+            _ => {}
+        }
+        self.expr(left);
+        // `Person { name, age }` builds a record, `twice { ... }` passes a block
+        if self.kind_of_callee(left) != Some(DeclKind::Function) && is_record(exprs) {
+            self.record(exprs);
+        } else {
+            self.block(exprs);
+        }
+    }
+
+    fn kind_of_callee(&self, callee: &Expr) -> Option<DeclKind> {
+        let (name, _) = identifier(callee)?;
+        self.lookup(name)
+            .map(|id| self.resolution.declarations[id].kind)
+    }
+
+    fn arguments(&mut self, args: &Expr) {
+        for arg in items(args) {
+            match arg {
+                // the name is a parameter of the callee
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => self.expr(right),
+                arg => self.expr(arg),
+            }
+        }
+    }
+
+    fn invoke(&mut self, callee: &Expr, args: &Expr, block: Option<&Expr>) {
+        self.expr(callee);
+        self.arguments(args);
+        if let Some(block) = block {
+            self.trailing_block(callee, block);
+        }
+    }
+
+    // `Person(name: "John") { age: 30 }`, `if (a) { ... }` or
+    // `if (a) { body: { ... }, else: { ... } }`
+    fn trailing_block(&mut self, callee: &Expr, block: &Expr) {
+        let exprs = items(block);
+        if self.kind_of_callee(callee) == Some(DeclKind::Type) {
+            return self.record(exprs);
+        }
+        let branches = exprs.iter().all(|expr| {
+            matches!(expr, Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                left,
+                ..
+            }) if matches!(identifier(left), Some(("body" | "else", _))))
+        });
+        if branches && !exprs.is_empty() {
+            for expr in exprs {
+                if let Expr::Binary(Binary { right, .. }) = expr {
+                    self.block(items(right));
+                }
+            }
+        } else {
+            self.block(exprs);
+        }
+    }
+
+    // only the start of `a.b.c` is a name, the rest are fields. `a.f(x)` calls
+    // the function `f` if there is one, or else a field of `a`
+    fn accessor(&mut self, binary: &Binary) {
+        self.expr(&binary.left);
+        self.rest(&binary.right);
+    }
+
+    // `a.b.c` is right nested, `(. a (. b c))`
+    fn rest(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(
+                binary @ Binary {
+                    op: BinaryOp::Accessor,
+                    ..
+                },
+            ) => {
+                self.member(&binary.left);
+                self.rest(&binary.right);
+            }
+            expr => self.member(expr),
+        }
+    }
+
+    fn member(&mut self, expr: &Expr) {
+        let method = |resolver: &mut Self, callee: &Expr| {
+            if let Some((name, span)) = identifier(callee) {
+                if let Some(id) = resolver.lookup(name) {
+                    resolver.link(id, span);
+                }
+            }
+        };
+        match expr {
+            Expr::Atom(Atom::Identifier(_), _, _) => {}
+            Expr::Binary(Binary {
+                op: BinaryOp::Invoke,
+                left,
+                right,
+                ..
+            }) => {
+                method(self, left);
+                self.arguments(right);
+            }
+            Expr::Ternary(Ternary {
+                left,
+                middle,
+                right,
+                ..
+            }) => {
+                method(self, left);
+                self.arguments(middle);
+                self.trailing_block(left, right);
+            }
+            // `a.b: value`
+            Expr::Binary(Binary {
+                op: BinaryOp::Assignment | BinaryOp::Reassignment,
+                right,
+                ..
+            }) => self.expr(right),
+            expr => self.expr(expr),
+        }
+    }
+}
+
+#[test]
+fn test_resolve() {
+    let source = "\
+count: 0
+Person: { name: String, age: Int, name: String }
+rename: Fn {
+    args: { self: Person, count: Int }
+    body: {
+        total: count + missing
+        self.name: \"x\"
+    }
+}
+bump: Fn { body: { count: count + 1 } }
+{ name }: Person(name: \"a\", age: 1)
+main: Fn { body: { bump() later(1) } }
+later: Fn { args: { n: Int } body: { n } }";
+    let ast = crate::parser::Parser::new(source).parse().unwrap();
+    let resolution = resolve(&ast);
+    let errors: Vec<String> = resolution.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        [
+            "2:35: `name` is defined more than once",
+            "4:27: parameter `count` hides a name from the file",
+            "6:24: `missing` is not defined",
+        ]
+    );
+
+    // `count` in `rename` is the parameter, `bump` assigns to the global
+    let declaration = |line: usize, column: usize| {
+        let span = resolution
+            .uses
+            .keys()
+            .find(|span| span.line == line && span.column == column)
+            .unwrap();
+        let declaration = resolution.declaration(*span).unwrap();
+        (declaration.kind, declaration.span.line)
+    };
+    assert_eq!(declaration(6, 16), (DeclKind::Param, 4));
+    assert_eq!(declaration(10, 20), (DeclKind::Variable, 1));
+    assert_eq!(declaration(10, 27), (DeclKind::Variable, 1));
+    assert_eq!(declaration(11, 11), (DeclKind::Type, 2));
+    assert_eq!(declaration(12, 27), (DeclKind::Function, 13));
+}
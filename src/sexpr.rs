@@ -0,0 +1,107 @@
+// Prints the AST as S-expressions, a compact form for `zen ast --format sexpr`
+// and the repl:
+//
+//     greeting: String("hi" + name)  =>  (: greeting (invoke String (params (+ "hi" name))))
+
+use crate::ast::{Atom, Expr, GroupOp, Literal, NumberSuffix, TernaryOp};
+
+/// One line per top-level item.
+pub fn program(expr: &Expr) -> String {
+    match expr {
+        Expr::Group(group) if group.op == GroupOp::StatementBlock => group
+            .exprs
+            .iter()
+            .map(to_sexpr)
+            .collect::<Vec<String>>()
+            .join("\n"),
+        expr => to_sexpr(expr),
+    }
+}
+
+fn literal(literal: &Literal) -> String {
+    let suffix = |suffix: &Option<NumberSuffix>| suffix.map_or("", |suffix| suffix.name());
+    match literal {
+        Literal::IntLiteral(value, s) => format!("{}{}", value, suffix(s)),
+        Literal::FloatLiteral(value, s) => format!("{:?}{}", value, suffix(s)),
+        Literal::OctalLiteral(value, s) => format!("0o{:o}{}", value, suffix(s)),
+        Literal::HexLiteral(value, s) => format!("0x{:x}{}", value, suffix(s)),
+        Literal::BinaryLiteral(value, s) => format!("0b{:b}{}", value, suffix(s)),
+        Literal::BoolLiteral(value) => value.to_string(),
+        Literal::CharLiteral(value) => format!("{:?}", value),
+        Literal::StringLiteral(value) => format!("{:?}", value),
+    }
+}
+
+fn list(head: &str, exprs: &[Expr]) -> String {
+    let mut out = format!("({}", head);
+    for expr in exprs {
+        out.push(' ');
+        out.push_str(&to_sexpr(expr));
+    }
+    out.push(')');
+    out
+}
+
+pub fn to_sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Atom(Atom::Identifier(name), _, _) => name.clone(),
+        Expr::Atom(Atom::Literal(value), _, _) => literal(value),
+        Expr::Atom(Atom::EndOfFile, _, _) => "(eof)".to_string(),
+        Expr::Unary(unary) => format!("({} {})", unary.op.symbol(), to_sexpr(&unary.expr)),
+        Expr::Binary(binary) => format!(
+            "({} {} {})",
+            binary.op.symbol(),
+            to_sexpr(&binary.left),
+            to_sexpr(&binary.right)
+        ),
+        Expr::Comparison(comparison) => {
+            let mut out = format!("(compare {}", to_sexpr(&comparison.operands[0]));
+            for (op, operand) in comparison.ops.iter().zip(&comparison.operands[1..]) {
+                out += &format!(" {} {}", op.symbol(), to_sexpr(operand));
+            }
+            out + ")"
+        }
+        Expr::Ternary(ternary) => {
+            let head = match ternary.op {
+                TernaryOp::FuncDefInvoke => "invoke-def",
+            };
+            format!(
+                "({} {} {} {})",
+                head,
+                to_sexpr(&ternary.left),
+                to_sexpr(&ternary.middle),
+                to_sexpr(&ternary.right)
+            )
+        }
+        Expr::Group(group) => {
+            let head = match group.op {
+                GroupOp::AssignmentBlock => "assign",
+                GroupOp::StatementBlock => "block",
+                GroupOp::ParamBlock => "params",
+                GroupOp::AnonymousType => "type",
+            };
+            list(head, &group.exprs)
+        }
+        Expr::InterpolatedString(string) => list("interpolate", &string.parts),
+        Expr::Directive(directive) => match &directive.args {
+            Some(args) => format!("(@{} {})", directive.name, to_sexpr(args)),
+            None => format!("@{}", directive.name),
+        },
+        Expr::Error(_, _) => "(error)".to_string(),
+    }
+}
+
+#[test]
+fn test_sexpr() {
+    let ast = crate::parser::Parser::new(
+        "greeting: String(\"hi\" + name)\nok: 1 < x <= 0xffu8 && !done\n@import \"io.zen\"",
+    )
+    .parse()
+    .unwrap();
+    assert_eq!(
+        program(&ast),
+        "(: greeting (invoke String (params (+ \"hi\" name))))
+(: ok (&& (compare 1 < x <= 0xffu8) (! done)))
+(@import \"io.zen\")"
+    );
+}
@@ -0,0 +1,38 @@
+use serde::Serialize;
+
+/// A location in a source file.
+///
+/// `start` and `end` are byte offsets into the file, `line` and `column` are
+/// 1-based and point at `start`. Columns are counted in chars, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize)]
+pub struct Span {
+    pub file_id: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file_id: usize, start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            file_id,
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Joins two spans, keeping the start position of `self`.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..*self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
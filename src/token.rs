@@ -1,18 +1,26 @@
 
 
 use serde::Serialize;
-#[derive(Debug, PartialEq, Serialize)]
+
+use crate::lexer::LexError;
+use crate::span::Span;
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
 pub enum Token {
     // Literals
     StringLiteral(String),
+    InterpolatedString(Vec<StringPart>),
     NumberLiteral(String),
     CharLiteral(char),
     BoolLiteral(bool),
     Identifier(String),
+    // `@name`, the name is empty for a bare `@`
+    Directive(String),
 
     // Binary
     Colon,
     Dot,
+    Assign,
 
     // Arithmetic
     Plus,
@@ -31,6 +39,8 @@ pub enum Token {
     BitwiseOr,
     BitwiseXor,
     BitwiseNot,
+    ShiftLeft,
+    ShiftRight,
 
     // Comparison
     Equality,
@@ -57,4 +67,83 @@ pub enum Token {
     NewLine(usize),
     // Eof,
     EndOfFile,
-}
\ No newline at end of file
+    // something the lexer could not make sense of
+    Error(LexError),
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub enum StringPart {
+    // the text between interpolations with escapes resolved, `span` covers it as written
+    Literal { value: String, span: Span },
+    // "\(expr)" or "${expr}", `span` covers the expression inside the delimiters
+    Expr { source: String, span: Span },
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Token {
+    /// Name of the token kind, ignoring any payload. Used for expected-token sets.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Token::StringLiteral(_) => "string literal",
+            Token::InterpolatedString(_) => "interpolated string",
+            Token::NumberLiteral(_) => "number literal",
+            Token::CharLiteral(_) => "char literal",
+            Token::BoolLiteral(_) => "bool literal",
+            Token::Identifier(_) => "identifier",
+            Token::Directive(_) => "directive",
+            Token::Colon => "`:`",
+            Token::Dot => "`.`",
+            Token::Assign => "`=`",
+            Token::Plus => "`+`",
+            Token::Minus => "`-`",
+            Token::Multiply => "`*`",
+            Token::Divide => "`/`",
+            Token::Modulo => "`%`",
+            Token::And => "`&&`",
+            Token::Or => "`||`",
+            Token::BitwiseAnd => "`&`",
+            Token::BitwiseOr => "`|`",
+            Token::BitwiseXor => "`^`",
+            Token::BitwiseNot => "`~`",
+            Token::ShiftLeft => "`<<`",
+            Token::ShiftRight => "`>>`",
+            Token::Equality => "`==`",
+            Token::NotEqual => "`!=`",
+            Token::LessThan => "`<`",
+            Token::LessThanOrEqual => "`<=`",
+            Token::GreaterThan => "`>`",
+            Token::GreaterThanOrEqual => "`>=`",
+            Token::Not => "`!`",
+            Token::Ellipse => "`...`",
+            Token::CurlyBraceOpen => "`{`",
+            Token::CurlyBraceClose => "`}`",
+            Token::ParenOpen => "`(`",
+            Token::ParenClose => "`)`",
+            Token::Comma => "`,`",
+            Token::Comment(_) => "comment",
+            Token::WhiteSpace(_) => "whitespace",
+            Token::NewLine(_) => "newline",
+            Token::EndOfFile => "end of file",
+            Token::Error(_) => "invalid token",
+        }
+    }
+
+    /// Description of this token including its payload, e.g. identifier `foo`.
+    pub fn describe(&self) -> String {
+        match self {
+            Token::StringLiteral(value) => format!("string literal {:?}", value),
+            Token::NumberLiteral(value) => format!("number literal `{}`", value),
+            Token::CharLiteral(value) => format!("char literal {:?}", value),
+            Token::BoolLiteral(value) => format!("`{}`", value),
+            Token::Identifier(value) => format!("identifier `{}`", value),
+            Token::Directive(name) => format!("directive `@{}`", name),
+            Token::Error(error) => error.to_string(),
+            token => token.kind_name().to_string(),
+        }
+    }
+}
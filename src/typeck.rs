@@ -0,0 +1,1578 @@
+// Type checking: infers a type for every expression and checks assignments,
+// instantiations, calls and field accesses against it.
+//
+// Types are structural, a record fits anywhere its fields do. Whatever can't be
+// worked out, like a parameter written without a type, is `Unknown` and fits
+// anything, so only what is certainly wrong gets reported. A type used as a
+// value, `Int` in `Int(1)` or `Person` in `Person(name: "Jane")`, is `Meta`.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    Atom, Binary, BinaryOp, Expr, Group, GroupOp, Literal, NumberSuffix, Ternary, Unary, UnaryOp,
+};
+use crate::builtins::Builtin;
+use crate::diagnostics::Diagnostic;
+use crate::resolve::Resolution;
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+    // declared with a type rather than a default, an instance has to give it
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordType {
+    pub name: Option<String>,
+    pub fields: Vec<Field>,
+}
+
+impl RecordType {
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut Field> {
+        self.fields.iter_mut().find(|field| field.name == name)
+    }
+
+    // a later field replaces an earlier one with the same name, like a spread does
+    fn set(&mut self, field: Field) {
+        match self.field_mut(&field.name) {
+            Some(slot) => *slot = field,
+            None => self.fields.push(field),
+        }
+    }
+}
+
+// what a variant carries, `RED`, `GBP: "GBP"` or `Comment: String`
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantKind {
+    Plain,
+    Value(Type),
+    Payload(Type),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub kind: VariantKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnumType {
+    pub name: Option<String>,
+    pub variants: Vec<Variant>,
+}
+
+impl EnumType {
+    pub fn variant(&self, name: &str) -> Option<(usize, &Variant)> {
+        self.variants
+            .iter()
+            .enumerate()
+            .find(|(_, variant)| variant.name == name)
+    }
+
+    // what `.value` gives, unknown when the variants don't agree
+    pub fn value_type(&self) -> Option<Type> {
+        let mut types = self
+            .variants
+            .iter()
+            .filter_map(|variant| match &variant.kind {
+                VariantKind::Plain => None,
+                VariantKind::Value(ty) | VariantKind::Payload(ty) => Some(ty),
+            });
+        let first = types.next()?;
+        match types.all(|ty| compatible(first, ty) && compatible(ty, first)) {
+            true => Some(first.clone()),
+            false => Some(Type::Unknown),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub params: Vec<(String, Type)>,
+    pub ret: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Type {
+    #[default]
+    Unknown,
+    Void,
+    // `None` is the default width, it fits any other
+    Int(Option<NumberSuffix>),
+    Float(Option<NumberSuffix>),
+    Bool,
+    Char,
+    String,
+    Record(RecordType),
+    Enum(EnumType),
+    Function(Box<FunctionType>),
+    // a block passed to a function as its `body`
+    Body,
+    Meta(Box<Type>),
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unknown => write!(f, "unknown"),
+            Type::Void => write!(f, "Void"),
+            Type::Int(Some(suffix)) | Type::Float(Some(suffix)) => write!(f, "{}", suffix.name()),
+            Type::Int(None) => write!(f, "Int"),
+            Type::Float(None) => write!(f, "Float"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Char => write!(f, "Char"),
+            Type::String => write!(f, "String"),
+            Type::Record(RecordType {
+                name: Some(name), ..
+            }) => write!(f, "{}", name),
+            Type::Record(record) if record.fields.is_empty() => write!(f, "{{}}"),
+            Type::Record(record) => {
+                let fields: Vec<String> = record
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.name, field.ty))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Type::Enum(EnumType {
+                name: Some(name), ..
+            }) => write!(f, "{}", name),
+            Type::Enum(enumeration) => {
+                let variants: Vec<&str> = enumeration
+                    .variants
+                    .iter()
+                    .map(|variant| variant.name.as_str())
+                    .collect();
+                write!(f, "Enum {{ {} }}", variants.join(", "))
+            }
+            Type::Function(function) => {
+                let params: Vec<String> = function
+                    .params
+                    .iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect();
+                write!(f, "Fn {{ args: {{ {} }}", params.join(", "))?;
+                match &function.ret {
+                    Type::Unknown => write!(f, " }}"),
+                    ret => write!(f, ", return: {} }}", ret),
+                }
+            }
+            Type::Body => write!(f, "Body"),
+            Type::Meta(ty) => write!(f, "type {}", ty),
+        }
+    }
+}
+
+// whether a value of type `found` can be used where `expected` is
+pub fn compatible(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (Type::Unknown, _) | (_, Type::Unknown) => true,
+        (Type::Int(expected), Type::Int(found)) | (Type::Float(expected), Type::Float(found)) => {
+            expected.is_none() || found.is_none() || expected == found
+        }
+        (Type::Record(expected), Type::Record(found)) => expected.fields.iter().all(|field| {
+            found
+                .field(&field.name)
+                .is_some_and(|found| compatible(&field.ty, &found.ty))
+        }),
+        (Type::Function(_), Type::Function(_)) => true,
+        (Type::Meta(expected), Type::Meta(found)) => compatible(expected, found),
+        (expected, found) => expected == found,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+    // expected, found
+    Mismatch(Type, Type),
+    // the name, its type, what it is given and where it was declared
+    MismatchedAssignment(String, Type, Type, Span),
+    MismatchedOperands(&'static str, Type, Type),
+    InvalidOperand(&'static str, Type),
+    UnknownField(String, Type),
+    MissingField(String, Type),
+    UnknownArgument(String),
+    MissingArgument(String),
+    TooManyArguments(usize),
+    NotCallable(Type),
+    NotAType(Type),
+    ExpectedBool(Type),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl std::fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeErrorKind::Mismatch(expected, found) => {
+                write!(f, "mismatched types: expected `{}`, found `{}`", expected, found)
+            }
+            TypeErrorKind::MismatchedAssignment(name, expected, found, _) => write!(
+                f,
+                "mismatched types: `{}` is `{}`, found `{}`",
+                name, expected, found
+            ),
+            TypeErrorKind::MismatchedOperands(op, left, right) => {
+                write!(f, "cannot apply `{}` to `{}` and `{}`", op, left, right)
+            }
+            TypeErrorKind::InvalidOperand(op, ty) => write!(f, "cannot apply `{}` to `{}`", op, ty),
+            TypeErrorKind::UnknownField(name, ty) => write!(f, "`{}` has no field `{}`", ty, name),
+            TypeErrorKind::MissingField(name, ty) => {
+                write!(f, "missing field `{}` for `{}`", name, ty)
+            }
+            TypeErrorKind::UnknownArgument(name) => write!(f, "unknown argument `{}`", name),
+            TypeErrorKind::MissingArgument(name) => write!(f, "missing argument `{}`", name),
+            TypeErrorKind::TooManyArguments(count) => {
+                write!(f, "too many arguments, expected at most {}", count)
+            }
+            TypeErrorKind::NotCallable(ty) => write!(f, "`{}` can't be called", ty),
+            TypeErrorKind::NotAType(ty) => write!(f, "`{}` is not a type", ty),
+            TypeErrorKind::ExpectedBool(ty) => {
+                write!(f, "expected a `Bool` condition, found `{}`", ty)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(error: &TypeError) -> Self {
+        let diagnostic = Diagnostic::error(error.kind.to_string());
+        match &error.kind {
+            TypeErrorKind::Mismatch(expected, _) => {
+                diagnostic.with_primary(error.span, format!("expected `{}`", expected))
+            }
+            TypeErrorKind::MismatchedAssignment(name, expected, _, declared) => diagnostic
+                .with_primary(error.span, format!("expected `{}`", expected))
+                .with_secondary(*declared, format!("`{}` is declared here", name)),
+            TypeErrorKind::UnknownField(..) => diagnostic.with_primary(error.span, "unknown field"),
+            TypeErrorKind::MissingField(name, _) => {
+                diagnostic.with_primary(error.span, format!("`{}` is not given", name))
+            }
+            _ => diagnostic.with_primary(error.span, ""),
+        }
+    }
+}
+
+/// The types of a checked program, the backends work from these.
+#[derive(Debug, Default)]
+pub struct Types {
+    // `NodeId::index` of an expression -> type
+    pub exprs: HashMap<usize, Type>,
+    // index into `Resolution::declarations` -> type
+    pub declarations: HashMap<usize, Type>,
+    pub errors: Vec<TypeError>,
+}
+
+impl Types {
+    pub fn of(&self, expr: &Expr) -> Type {
+        self.exprs.get(&expr.id().index()).cloned().unwrap_or_default()
+    }
+}
+
+pub fn check(program: &Expr, resolution: &Resolution) -> Types {
+    let mut checker = Checker {
+        resolution,
+        declared: HashMap::new(),
+        types: Types::default(),
+        signatures: false,
+    };
+    for (id, declaration) in resolution.declarations.iter().enumerate() {
+        checker
+            .declared
+            .insert((declaration.span, declaration.name.as_str()), id);
+    }
+    checker.file(program);
+    checker.types
+}
+
+fn identifier(expr: &Expr) -> Option<(&str, Span)> {
+    match expr {
+        Expr::Atom(Atom::Identifier(name), span, _) => Some((name, *span)),
+        _ => None,
+    }
+}
+
+fn items(expr: &Expr) -> &[Expr] {
+    match expr {
+        Expr::Group(group) => &group.exprs,
+        expr => std::slice::from_ref(expr),
+    }
+}
+
+// `a.b.c` is right nested, `(. a (. b c))`, this flattens it to `[a, b, c]`
+fn accessor_chain<'e>(expr: &'e Expr, chain: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary(Binary {
+            op: BinaryOp::Accessor,
+            left,
+            right,
+            ..
+        }) => {
+            accessor_chain(left, chain);
+            accessor_chain(right, chain);
+        }
+        expr => chain.push(expr),
+    }
+}
+
+fn is_spread(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Unary(Unary {
+            op: UnaryOp::SpreadExpr,
+            ..
+        })
+    )
+}
+
+// a block like `{ a: 1, ...b, c }` that builds a record rather than running statements
+fn is_record(exprs: &[Expr]) -> bool {
+    exprs.iter().all(|expr| {
+        is_spread(expr)
+            || matches!(
+                expr,
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    ..
+                }) | Expr::Atom(Atom::Identifier(_), _, _)
+            )
+    })
+}
+
+// `if (a) { body: {...}, else: {...} }`
+fn branches(exprs: &[Expr]) -> Option<Vec<&Expr>> {
+    if exprs.is_empty() {
+        return None;
+    }
+    exprs
+        .iter()
+        .map(|expr| match expr {
+            Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                left,
+                right,
+                ..
+            }) if matches!(identifier(left), Some(("body" | "else", _))) => Some(&**right),
+            _ => None,
+        })
+        .collect()
+}
+
+fn literal(literal: &Literal) -> Type {
+    match literal {
+        Literal::IntLiteral(_, suffix)
+        | Literal::OctalLiteral(_, suffix)
+        | Literal::HexLiteral(_, suffix)
+        | Literal::BinaryLiteral(_, suffix)
+        | Literal::FloatLiteral(_, suffix)
+            if suffix.is_some_and(|suffix| suffix.is_float()) =>
+        {
+            Type::Float(*suffix)
+        }
+        Literal::FloatLiteral(..) => Type::Float(None),
+        Literal::IntLiteral(_, suffix)
+        | Literal::OctalLiteral(_, suffix)
+        | Literal::HexLiteral(_, suffix)
+        | Literal::BinaryLiteral(_, suffix) => Type::Int(*suffix),
+        Literal::BoolLiteral(_) => Type::Bool,
+        Literal::CharLiteral(_) => Type::Char,
+        Literal::StringLiteral(_) => Type::String,
+    }
+}
+
+fn builtin(name: &str) -> Type {
+    let ty = match Builtin::from_name(name) {
+        Some(Builtin::Int) => Type::Int(None),
+        Some(Builtin::Float) => Type::Float(None),
+        Some(Builtin::Bool) => Type::Bool,
+        Some(Builtin::Char) => Type::Char,
+        Some(Builtin::String) => Type::String,
+        _ => return Type::Unknown,
+    };
+    Type::Meta(Box::new(ty))
+}
+
+// a field written as a type is required, one written as a value is its default
+fn field(name: &str, ty: Type) -> Field {
+    match ty {
+        Type::Meta(ty) => Field {
+            name: name.to_string(),
+            ty: *ty,
+            required: true,
+        },
+        ty => Field {
+            name: name.to_string(),
+            ty,
+            required: false,
+        },
+    }
+}
+
+// `args: { a: Int }` and `return: Int` are types, `args: { a: 1 }` is a default
+pub fn as_type(ty: Type) -> Type {
+    match ty {
+        Type::Meta(ty) => *ty,
+        ty => ty,
+    }
+}
+
+// the type of a field a few records down, `a.b.c` without the `a`
+fn record_at<'t>(ty: &'t mut Type, path: &[&str]) -> Option<&'t mut RecordType> {
+    let record = match ty {
+        Type::Record(record) => record,
+        _ => return None,
+    };
+    match path.split_first() {
+        None => Some(record),
+        Some((name, rest)) => record_at(&mut record.field_mut(name)?.ty, rest),
+    }
+}
+
+// a field of a record or type, a sized number type like `Int.I32`, a variant
+// like `Rgb.RED` or `Token.Comment(text)`, or `Rgb.parse` and `String.parse`
+pub fn member(ty: &Type, name: &str) -> Option<Type> {
+    let ty = match ty {
+        Type::Record(record) => return record.field(name).map(|field| field.ty.clone()),
+        Type::Enum(enumeration) if name == "value" => return enumeration.value_type(),
+        Type::Meta(ty) => ty,
+        _ => return None,
+    };
+    let function = |param: &str, ty: Type, ret: Type| {
+        Type::Function(Box::new(FunctionType {
+            params: vec![(param.to_string(), ty)],
+            ret,
+        }))
+    };
+    match (&**ty, NumberSuffix::from_name(&name.to_lowercase())) {
+        (Type::Record(record), _) => record.field(name).map(|field| field.ty.clone()),
+        (Type::Enum(enumeration), _) => match enumeration.variant(name) {
+            Some((
+                _,
+                Variant {
+                    kind: VariantKind::Payload(payload),
+                    ..
+                },
+            )) => Some(function("value", payload.clone(), (**ty).clone())),
+            Some(_) => Some((**ty).clone()),
+            None if name == "parse" => Some(function("string", Type::String, (**ty).clone())),
+            None => None,
+        },
+        (Type::String, _) if name == "parse" => {
+            Some(function("value", Type::Unknown, Type::String))
+        }
+        (Type::Int(None), Some(suffix)) if !suffix.is_float() => {
+            Some(Type::Meta(Box::new(Type::Int(Some(suffix)))))
+        }
+        (Type::Float(None), Some(suffix)) if suffix.is_float() => {
+            Some(Type::Meta(Box::new(Type::Float(Some(suffix)))))
+        }
+        _ => None,
+    }
+}
+
+type Arguments = (Vec<(Type, Span)>, Vec<(String, Type, Span)>);
+
+struct Checker<'r> {
+    resolution: &'r Resolution,
+    // declaration span and name -> index into `Resolution::declarations`,
+    // the implicit `args` and `return` of a function share a span
+    declared: HashMap<(Span, &'r str), usize>,
+    types: Types,
+    // only work out what functions take and return, without their bodies
+    signatures: bool,
+}
+
+impl<'r> Checker<'r> {
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.types.errors.push(TypeError { kind, span });
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+        if !compatible(expected, found) {
+            self.error(
+                TypeErrorKind::Mismatch(expected.clone(), found.clone()),
+                span,
+            );
+        }
+    }
+
+    fn declaration_type(&self, id: usize) -> Type {
+        self.types.declarations.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn declare(&mut self, name: &str, span: Span, ty: Type) {
+        if let Some(id) = self.declared.get(&(span, name)) {
+            self.types.declarations.insert(*id, ty);
+        }
+    }
+
+    fn file(&mut self, program: &Expr) {
+        // types and signatures first, so a function can be called before the
+        // line that declares it, the bodies are checked on the second pass
+        let errors = self.types.errors.len();
+        self.signatures = true;
+        for expr in items(program) {
+            if let Expr::Binary(Binary {
+                op: BinaryOp::Assignment,
+                left,
+                right,
+                ..
+            }) = expr
+            {
+                let is_definition = match &**right {
+                    Expr::Binary(Binary {
+                        op: BinaryOp::TypeDef,
+                        left,
+                        ..
+                    }) => matches!(
+                        identifier(left),
+                        Some(("Type" | "Fn" | "Function", _))
+                    ),
+                    Expr::Group(_) => identifier(left)
+                        .is_some_and(|(name, _)| name.starts_with(|c: char| c.is_ascii_uppercase())),
+                    _ => false,
+                };
+                if is_definition {
+                    let value = self.expr(right);
+                    self.assign(left, value, right.span(), true);
+                }
+            }
+        }
+        self.types.errors.truncate(errors);
+        self.signatures = false;
+
+        for expr in items(program) {
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        let ty = match expr {
+            Expr::Atom(Atom::Literal(value), _, _) => literal(value),
+            Expr::Atom(Atom::Identifier(name), span, _) => self.identifier(name, *span),
+            Expr::Atom(Atom::EndOfFile, _, _) => Type::Void,
+            Expr::Unary(unary) => self.unary(unary),
+            Expr::Binary(binary) => self.binary(binary),
+            Expr::Comparison(comparison) => {
+                let mut left = self.expr(&comparison.operands[0]);
+                for (op, operand) in comparison.ops.iter().zip(&comparison.operands[1..]) {
+                    let right = self.expr(operand);
+                    self.compare(op, &left, &right, comparison.span);
+                    left = right;
+                }
+                Type::Bool
+            }
+            Expr::Ternary(Ternary {
+                left,
+                middle,
+                right,
+                span,
+                ..
+            }) => self.invoke(left, middle, Some(right), *span),
+            Expr::Group(group) => self.group(group),
+            Expr::InterpolatedString(string) => {
+                for part in &string.parts {
+                    self.expr(part);
+                }
+                Type::String
+            }
+            Expr::Directive(_) | Expr::Error(_, _) => Type::Unknown,
+        };
+        self.types.exprs.insert(expr.id().index(), ty.clone());
+        ty
+    }
+
+    fn identifier(&mut self, name: &str, span: Span) -> Type {
+        match self.resolution.uses.get(&span) {
+            Some(id) => self.declaration_type(*id),
+            None => builtin(name),
+        }
+    }
+
+    fn unary(&mut self, unary: &Unary) -> Type {
+        let ty = self.expr(&unary.expr);
+        match (&unary.op, &ty) {
+            // only means something in a record, see `fields`
+            (UnaryOp::SpreadExpr, _) | (_, Type::Unknown) => Type::Unknown,
+            (UnaryOp::Negate, Type::Int(_) | Type::Float(_))
+            | (UnaryOp::Not, Type::Bool)
+            | (UnaryOp::BitwiseNot, Type::Int(_)) => ty,
+            (op, _) => {
+                self.error(TypeErrorKind::InvalidOperand(op.symbol(), ty), unary.span);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn binary(&mut self, binary: &Binary) -> Type {
+        match &binary.op {
+            BinaryOp::Assignment | BinaryOp::Reassignment => {
+                let value = self.expr(&binary.right);
+                let declare = binary.op == BinaryOp::Assignment;
+                self.assign(&binary.left, value, binary.right.span(), declare);
+                Type::Void
+            }
+            BinaryOp::Accessor => self.accessor(binary),
+            BinaryOp::Invoke => self.invoke(&binary.left, &binary.right, None, binary.span),
+            BinaryOp::TypeDef => self.typedef(&binary.left, &binary.right, binary.span),
+            BinaryOp::FieldDef => {
+                self.expr(&binary.left);
+                self.expr(&binary.right);
+                Type::Unknown
+            }
+            BinaryOp::And | BinaryOp::Or => {
+                for operand in [&binary.left, &binary.right] {
+                    let ty = self.expr(operand);
+                    if !compatible(&Type::Bool, &ty) {
+                        self.error(
+                            TypeErrorKind::InvalidOperand(binary.op.symbol(), ty),
+                            operand.span(),
+                        );
+                    }
+                }
+                Type::Bool
+            }
+            op => {
+                let left = self.expr(&binary.left);
+                let right = self.expr(&binary.right);
+                match op.is_comparison() {
+                    true => {
+                        self.compare(op, &left, &right, binary.span);
+                        Type::Bool
+                    }
+                    false => self.arithmetic(op, left, right, binary.span),
+                }
+            }
+        }
+    }
+
+    fn compare(&mut self, op: &BinaryOp, left: &Type, right: &Type, span: Span) {
+        let ordered = |ty: &Type| {
+            matches!(
+                ty,
+                Type::Int(_) | Type::Float(_) | Type::String | Type::Char | Type::Bool
+            )
+        };
+        let comparable = match op {
+            BinaryOp::Equal | BinaryOp::NotEqual => compatible(left, right),
+            _ => {
+                *left == Type::Unknown
+                    || *right == Type::Unknown
+                    || (ordered(left) && compatible(left, right))
+            }
+        };
+        if !comparable {
+            self.error(
+                TypeErrorKind::MismatchedOperands(op.symbol(), left.clone(), right.clone()),
+                span,
+            );
+        }
+    }
+
+    fn arithmetic(&mut self, op: &BinaryOp, left: Type, right: Type, span: Span) -> Type {
+        let bitwise = matches!(
+            op,
+            BinaryOp::BitwiseAnd
+                | BinaryOp::BitwiseOr
+                | BinaryOp::BitwiseXor
+                | BinaryOp::ShiftLeft
+                | BinaryOp::ShiftRight
+        );
+        match (&left, &right) {
+            // the operator decides, whatever the known side is
+            (Type::Unknown, ty) | (ty, Type::Unknown) => ty.clone(),
+            (Type::Int(a), Type::Int(b)) if compatible(&left, &right) => Type::Int(a.or(*b)),
+            (Type::Float(a), Type::Float(b)) if !bitwise && compatible(&left, &right) => {
+                Type::Float(a.or(*b))
+            }
+            (Type::String, Type::String) if *op == BinaryOp::Add => Type::String,
+            _ => {
+                self.error(
+                    TypeErrorKind::MismatchedOperands(op.symbol(), left, right),
+                    span,
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    // `name: value` declares or assigns, `name = value` assigns. A name keeps
+    // the type it is declared with
+    fn assign(&mut self, target: &Expr, value: Type, value_span: Span, declare: bool) {
+        match target {
+            Expr::Atom(Atom::Identifier(name), span, _) => {
+                // `Person: { ... }` names the record
+                let value = match value {
+                    Type::Meta(ty) => match *ty {
+                        Type::Record(record) if name.starts_with(|c: char| c.is_ascii_uppercase()) => {
+                            Type::Meta(Box::new(Type::Record(RecordType {
+                                name: record.name.or(Some(name.clone())),
+                                fields: record.fields,
+                            })))
+                        }
+                        Type::Enum(enumeration)
+                            if name.starts_with(|c: char| c.is_ascii_uppercase()) =>
+                        {
+                            Type::Meta(Box::new(Type::Enum(EnumType {
+                                name: enumeration.name.or(Some(name.clone())),
+                                variants: enumeration.variants,
+                            })))
+                        }
+                        ty => Type::Meta(Box::new(ty)),
+                    },
+                    Type::Record(record) if name.starts_with(|c: char| c.is_ascii_uppercase()) => {
+                        Type::Record(RecordType {
+                            name: record.name.or(Some(name.clone())),
+                            fields: record.fields,
+                        })
+                    }
+                    value => value,
+                };
+                self.types.exprs.insert(target.id().index(), value.clone());
+                if let Some(id) = self.declared.get(&(*span, name.as_str())) {
+                    self.types.declarations.insert(*id, value);
+                    return;
+                }
+                let (id, declaration) = match (
+                    self.resolution.uses.get(span),
+                    self.resolution.declaration(*span),
+                ) {
+                    (Some(id), Some(declaration)) => (*id, declaration),
+                    _ => return,
+                };
+                match self.declaration_type(id) {
+                    // the first value that is known settles it
+                    Type::Unknown => {
+                        self.types.declarations.insert(id, value);
+                    }
+                    expected if !compatible(&expected, &value) => self.error(
+                        TypeErrorKind::MismatchedAssignment(
+                            name.clone(),
+                            expected,
+                            value,
+                            declaration.span,
+                        ),
+                        value_span,
+                    ),
+                    _ => {}
+                }
+            }
+            Expr::Binary(Binary {
+                op: BinaryOp::Accessor,
+                ..
+            }) => {
+                let mut chain = Vec::new();
+                accessor_chain(target, &mut chain);
+                self.assign_path(&chain, value, value_span, declare);
+            }
+            // `{ name, age }: person`
+            Expr::Group(group) => {
+                if !matches!(value, Type::Record(_) | Type::Unknown) {
+                    self.error(TypeErrorKind::InvalidOperand("{}", value), group.span);
+                    return;
+                }
+                for expr in &group.exprs {
+                    if let Some((name, span)) = identifier(expr) {
+                        let ty = self.field(&value, name, span);
+                        self.assign(expr, ty, value_span, declare);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // `a.b.c: value`, only `:` can add a field and the record's type grows with it
+    fn assign_path(&mut self, chain: &[&Expr], value: Type, value_span: Span, declare: bool) {
+        let mut path = Vec::new();
+        for expr in chain {
+            match identifier(expr) {
+                Some(name) => path.push(name),
+                None => return,
+            }
+        }
+        let root = self.expr(chain[0]);
+        let mut ty = root;
+        for (i, (name, span)) in path[1..].iter().enumerate() {
+            let last = i == path.len() - 2;
+            let field = match &ty {
+                Type::Record(record) => record.field(name).map(|field| field.ty.clone()),
+                Type::Unknown => return,
+                _ => None,
+            };
+            ty = match field {
+                Some(field) => field,
+                None if last && declare && matches!(ty, Type::Record(_)) => {
+                    let names: Vec<&str> = path[1..i + 1].iter().map(|(name, _)| *name).collect();
+                    let id = self.resolution.uses.get(&path[0].1).copied();
+                    if let Some(record) = id
+                        .and_then(|id| self.types.declarations.get_mut(&id))
+                        .and_then(|ty| record_at(ty, &names))
+                    {
+                        record.set(Field {
+                            name: name.to_string(),
+                            ty: value,
+                            required: false,
+                        });
+                    }
+                    return;
+                }
+                None => {
+                    self.error(TypeErrorKind::UnknownField(name.to_string(), ty), *span);
+                    return;
+                }
+            };
+        }
+        self.expect(&ty, &value, value_span);
+    }
+
+    fn group(&mut self, group: &Group) -> Type {
+        match group.op {
+            GroupOp::AnonymousType | GroupOp::AssignmentBlock => self.record(&group.exprs),
+            // `{ ...a, b: 1 }` comes out of the parser as a block
+            GroupOp::StatementBlock if group.exprs.iter().any(is_spread) => {
+                self.record(&group.exprs)
+            }
+            GroupOp::StatementBlock => self.block(&group.exprs),
+            GroupOp::ParamBlock => {
+                for expr in &group.exprs {
+                    self.expr(expr);
+                }
+                Type::Unknown
+            }
+        }
+    }
+
+    // the type of the last statement
+    fn block(&mut self, exprs: &[Expr]) -> Type {
+        let mut ty = Type::Void;
+        for expr in exprs {
+            ty = self.expr(expr);
+        }
+        ty
+    }
+
+    // a record with a required field describes a type, otherwise it is a value
+    fn record(&mut self, exprs: &[Expr]) -> Type {
+        let record = self.record_type(exprs);
+        match record.fields.iter().any(|field| field.required) {
+            true => Type::Meta(Box::new(Type::Record(record))),
+            false => Type::Record(record),
+        }
+    }
+
+    fn record_type(&mut self, exprs: &[Expr]) -> RecordType {
+        let mut record = RecordType::default();
+        for (name, ty, _) in self.fields(exprs) {
+            record.set(field(&name, ty));
+        }
+        record
+    }
+
+    // `{ name: "John", ...address, age }` as names, types and where each is given
+    fn fields(&mut self, exprs: &[Expr]) -> Vec<(String, Type, Span)> {
+        let mut fields = Vec::new();
+        for expr in exprs {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    let ty = self.expr(right);
+                    fields.push((identifier(left).unwrap().0.to_string(), ty, right.span()));
+                }
+                Expr::Unary(Unary {
+                    op: UnaryOp::SpreadExpr,
+                    expr,
+                    span,
+                    ..
+                }) => match as_type(self.expr(expr)) {
+                    Type::Record(spread) => {
+                        for field in spread.fields {
+                            let ty = match field.required {
+                                true => Type::Meta(Box::new(field.ty)),
+                                false => field.ty,
+                            };
+                            fields.push((field.name, ty, *span));
+                        }
+                    }
+                    Type::Unknown => {}
+                    ty => self.error(TypeErrorKind::InvalidOperand("...", ty), *span),
+                },
+                // shorthand for `name: name`
+                Expr::Atom(Atom::Identifier(name), span, _) => {
+                    let ty = self.expr(expr);
+                    fields.push((name.clone(), ty, *span));
+                }
+                expr => {
+                    self.expr(expr);
+                }
+            }
+        }
+        fields
+    }
+
+    // `Fn { args: { a: Int }, return: Int, body: { ... } }`
+    fn function(&mut self, exprs: &[Expr], span: Span) -> Type {
+        let mut function = FunctionType {
+            params: Vec::new(),
+            ret: Type::Unknown,
+        };
+        let mut body = None;
+        for expr in exprs {
+            let (name, value) = match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => (identifier(left).unwrap().0, &**right),
+                expr => {
+                    self.expr(expr);
+                    continue;
+                }
+            };
+            match name {
+                "args" => {
+                    for arg in items(value) {
+                        let (param, ty) = match arg {
+                            Expr::Binary(Binary {
+                                op: BinaryOp::Assignment,
+                                left,
+                                right,
+                                ..
+                            }) => (identifier(left), as_type(self.expr(right))),
+                            arg => (identifier(arg), Type::Unknown),
+                        };
+                        if let Some((param, span)) = param {
+                            self.declare(param, span, ty.clone());
+                            function.params.push((param.to_string(), ty));
+                        }
+                    }
+                }
+                "return" | "returns" => function.ret = as_type(self.expr(value)),
+                "body" | "fn" => body = Some(value),
+                _ => {
+                    self.expr(value);
+                }
+            }
+        }
+
+        if let (Some(body), false) = (body, self.signatures) {
+            let args = RecordType {
+                name: None,
+                fields: function
+                    .params
+                    .iter()
+                    .map(|(name, ty)| field(name, ty.clone()))
+                    .collect(),
+            };
+            self.declare("args", span, Type::Record(args));
+            self.declare("return", span, function.ret.clone());
+            let last = self.block(items(body));
+            // without a declared type it returns what `return` is given, or
+            // else its last value
+            if function.ret == Type::Unknown {
+                function.ret = match self.declared.get(&(span, "return")) {
+                    Some(id) if self.declaration_type(*id) != Type::Unknown => {
+                        self.declaration_type(*id)
+                    }
+                    _ => last,
+                };
+            }
+        }
+        Type::Function(Box::new(function))
+    }
+
+    fn typedef(&mut self, left: &Expr, block: &Expr, span: Span) -> Type {
+        let exprs = items(block);
+        let builtin = identifier(left)
+            .filter(|(_, span)| !self.resolution.uses.contains_key(span))
+            .and_then(|(name, _)| Builtin::from_name(name));
+        match builtin {
+            Some(Builtin::Type) => return Type::Meta(Box::new(Type::Record(self.record_type(exprs)))),
+            Some(Builtin::Fn) => return self.function(exprs, block.span()),
+            Some(Builtin::Enum) => return Type::Meta(Box::new(Type::Enum(self.enum_type(exprs)))),
+            _ => {}
+        }
+        match self.expr(left) {
+            // `Person { name: "John", age }`
+            Type::Meta(ty) if matches!(*ty, Type::Record(_)) => {
+                let named = self.fields(exprs);
+                self.instantiate(&as_type(Type::Meta(ty)), Vec::new(), named, span)
+            }
+            ty @ Type::Record(_) => {
+                let named = self.fields(exprs);
+                self.instantiate(&ty, Vec::new(), named, span)
+            }
+            // `twice { ... }` passes the block as `body`
+            function @ Type::Function(_) => self.call(function, None, None, Some(block), span),
+            Type::Unknown => {
+                match is_record(exprs) {
+                    true => self.record(exprs),
+                    false => self.block(exprs),
+                };
+                Type::Unknown
+            }
+            ty => {
+                self.error(TypeErrorKind::NotAType(ty), left.span());
+                Type::Unknown
+            }
+        }
+    }
+
+    // `Rgb: Enum { RED, GREEN }`, a variant given a type carries a payload of it
+    // and one given a value always has that value
+    fn enum_type(&mut self, exprs: &[Expr]) -> EnumType {
+        let mut variants = Vec::new();
+        for expr in exprs {
+            let (name, kind) = match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    let kind = match self.expr(right) {
+                        Type::Meta(ty) => VariantKind::Payload(*ty),
+                        ty => VariantKind::Value(ty),
+                    };
+                    (identifier(left).unwrap().0, kind)
+                }
+                expr => match identifier(expr) {
+                    Some((name, _)) => (name, VariantKind::Plain),
+                    None => {
+                        self.expr(expr);
+                        continue;
+                    }
+                },
+            };
+            variants.push(Variant {
+                name: name.to_string(),
+                kind,
+            });
+        }
+        EnumType {
+            name: None,
+            variants,
+        }
+    }
+
+    fn arguments(&mut self, args: &Expr) -> Arguments {
+        let (mut positional, mut named) = (Vec::new(), Vec::new());
+        for expr in items(args) {
+            match expr {
+                Expr::Binary(Binary {
+                    op: BinaryOp::Assignment,
+                    left,
+                    right,
+                    ..
+                }) if identifier(left).is_some() => {
+                    let ty = self.expr(right);
+                    named.push((identifier(left).unwrap().0.to_string(), ty, right.span()));
+                }
+                expr => {
+                    let ty = self.expr(expr);
+                    positional.push((ty, expr.span()));
+                }
+            }
+        }
+        (positional, named)
+    }
+
+    fn invoke(&mut self, callee: &Expr, args: &Expr, block: Option<&Expr>, span: Span) -> Type {
+        let builtin = identifier(callee)
+            .filter(|(name, span)| *name == "return" || !self.resolution.uses.contains_key(span));
+        match builtin {
+            Some(("if" | "while", _)) => {
+                self.condition(args, span);
+                if let Some(block) = block {
+                    match branches(items(block)) {
+                        Some(branches) => {
+                            for branch in branches {
+                                self.block(items(branch));
+                            }
+                        }
+                        None => {
+                            self.block(items(block));
+                        }
+                    }
+                }
+                return Type::Unknown;
+            }
+            // `return` is also a variable in every function
+            Some(("return", callee_span)) => {
+                let (positional, _) = self.arguments(args);
+                let (value, value_span) = positional.into_iter().next().unwrap_or((Type::Void, span));
+                if let Some(id) = self.resolution.uses.get(&callee_span) {
+                    match self.declaration_type(*id) {
+                        Type::Unknown => {
+                            self.types.declarations.insert(*id, value);
+                        }
+                        expected => self.expect(&expected, &value, value_span),
+                    }
+                }
+                return Type::Void;
+            }
+            Some(("exit", _)) => {
+                let (positional, _) = self.arguments(args);
+                if let Some((status, status_span)) = positional.first() {
+                    self.expect(&Type::Int(None), status, *status_span);
+                }
+                return Type::Void;
+            }
+            _ => {}
+        }
+        let function = self.expr(callee);
+        self.call(function, None, Some(args), block, span)
+    }
+
+    fn condition(&mut self, args: &Expr, span: Span) {
+        let (positional, named) = self.arguments(args);
+        if let Some((name, _, span)) = named.first() {
+            self.error(TypeErrorKind::UnknownArgument(name.clone()), *span);
+        }
+        match &positional[..] {
+            [(ty, span)] if !compatible(&Type::Bool, ty) => {
+                self.error(TypeErrorKind::ExpectedBool(ty.clone()), *span)
+            }
+            [_] => {}
+            [] => self.error(TypeErrorKind::MissingArgument("value".to_string()), span),
+            _ => self.error(TypeErrorKind::TooManyArguments(1), span),
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: Type,
+        receiver: Option<Type>,
+        args: Option<&Expr>,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Type {
+        let (mut positional, mut named) = match args {
+            Some(args) => self.arguments(args),
+            None => (Vec::new(), Vec::new()),
+        };
+        let record = matches!(as_type(callee.clone()), Type::Record(_));
+        if let Some(block) = block {
+            match record {
+                // `Person(name: "John") { age: 30 }`
+                true => named.extend(self.fields(items(block))),
+                false => {
+                    self.block(items(block));
+                    named.push(("body".to_string(), Type::Body, block.span()));
+                }
+            }
+        }
+        if let (Some(receiver), false) = (&receiver, matches!(callee, Type::Function(_))) {
+            positional.insert(0, (receiver.clone(), span));
+        }
+
+        match callee {
+            Type::Function(function) => {
+                self.call_function(&function, receiver, positional, named, span)
+            }
+            ty if record => self.instantiate(&as_type(ty), positional, named, span),
+            Type::Meta(ty) => self.convert(*ty, positional, named, span),
+            Type::Unknown | Type::Body => Type::Unknown,
+            ty => {
+                self.error(TypeErrorKind::NotCallable(ty), span);
+                Type::Unknown
+            }
+        }
+    }
+
+    // `Person(name: "John", age: 30)`, by name or in the order of the fields
+    fn instantiate(
+        &mut self,
+        ty: &Type,
+        positional: Vec<(Type, Span)>,
+        named: Vec<(String, Type, Span)>,
+        span: Span,
+    ) -> Type {
+        let record = match ty {
+            Type::Record(record) => record,
+            _ => return Type::Unknown,
+        };
+        let mut instance = record.clone();
+        let mut given = vec![false; record.fields.len()];
+        if positional.len() > record.fields.len() {
+            self.error(TypeErrorKind::TooManyArguments(record.fields.len()), span);
+        }
+        let positional = positional
+            .into_iter()
+            .take(record.fields.len())
+            .enumerate()
+            .map(|(i, (found, span))| (i, found, span));
+        let named: Vec<(usize, Type, Span)> = named
+            .into_iter()
+            .filter_map(|(name, found, span)| {
+                match record.fields.iter().position(|field| field.name == name) {
+                    Some(i) => Some((i, found, span)),
+                    None => {
+                        self.error(TypeErrorKind::UnknownField(name, ty.clone()), span);
+                        None
+                    }
+                }
+            })
+            .collect();
+        for (i, found, span) in positional.chain(named) {
+            self.expect(&record.fields[i].ty, &found, span);
+            given[i] = true;
+            if instance.fields[i].ty == Type::Unknown {
+                instance.fields[i].ty = found;
+            }
+        }
+        for (field, given) in record.fields.iter().zip(given) {
+            if field.required && !given {
+                self.error(
+                    TypeErrorKind::MissingField(field.name.clone(), ty.clone()),
+                    span,
+                );
+            }
+        }
+        for field in &mut instance.fields {
+            field.required = false;
+        }
+        Type::Record(instance)
+    }
+
+    // `Int(1.5)`, `String(age)`
+    fn convert(
+        &mut self,
+        ty: Type,
+        positional: Vec<(Type, Span)>,
+        named: Vec<(String, Type, Span)>,
+        span: Span,
+    ) -> Type {
+        if let Some((name, _, span)) = named.first() {
+            self.error(TypeErrorKind::UnknownArgument(name.clone()), *span);
+        }
+        if positional.len() > 1 {
+            self.error(TypeErrorKind::TooManyArguments(1), span);
+        }
+        let converts = match (&ty, positional.first()) {
+            (Type::Char | Type::Enum(_), None) => false,
+            (_, None) | (_, Some((Type::Unknown, _))) => true,
+            // `Int(color)` is the index of the variant, `Rgb(0)` the variant at it
+            (Type::Int(_), Some((Type::Int(_) | Type::Float(_) | Type::Char, _)))
+            | (Type::Int(_), Some((Type::Enum(_), _)))
+            | (Type::Enum(_), Some((Type::Int(_), _)))
+            | (Type::Float(_), Some((Type::Int(_) | Type::Float(_), _)))
+            | (Type::Bool, Some((Type::Bool, _)))
+            | (Type::Char, Some((Type::Char, _)))
+            | (Type::String, Some(_)) => true,
+            _ => false,
+        };
+        if !converts {
+            match positional.first() {
+                Some((found, span)) => self.expect(&ty, found, *span),
+                None => self.error(TypeErrorKind::MissingArgument(ty.to_string()), span),
+            }
+        }
+        match ty {
+            Type::Record(_) | Type::Function(_) | Type::Meta(_) | Type::Unknown => Type::Unknown,
+            ty => ty,
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &FunctionType,
+        receiver: Option<Type>,
+        positional: Vec<(Type, Span)>,
+        named: Vec<(String, Type, Span)>,
+        span: Span,
+    ) -> Type {
+        let params = &function.params;
+        let mut bound: Vec<Option<(Type, Span)>> = vec![None; params.len()];
+        if let Some(receiver) = receiver {
+            let index = params
+                .iter()
+                .position(|(param, _)| param == "self")
+                .unwrap_or(0);
+            match bound.get_mut(index) {
+                Some(slot) => *slot = Some((receiver, span)),
+                None => self.error(TypeErrorKind::TooManyArguments(0), span),
+            }
+        }
+        for (ty, span) in positional {
+            match bound.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => *slot = Some((ty, span)),
+                None => {
+                    self.error(TypeErrorKind::TooManyArguments(params.len()), span);
+                    break;
+                }
+            }
+        }
+        for (name, ty, span) in named {
+            match params.iter().position(|(param, _)| *param == name) {
+                Some(index) => bound[index] = Some((ty, span)),
+                None => self.error(TypeErrorKind::UnknownArgument(name), span),
+            }
+        }
+        for ((param, expected), value) in params.iter().zip(bound) {
+            match value {
+                Some((found, span)) => self.expect(expected, &found, span),
+                None => self.error(TypeErrorKind::MissingArgument(param.clone()), span),
+            }
+        }
+        function.ret.clone()
+    }
+
+    fn field(&mut self, ty: &Type, name: &str, span: Span) -> Type {
+        match (member(ty, name), ty) {
+            (Some(field), _) => field,
+            (None, Type::Unknown) => Type::Unknown,
+            (None, _) if name == "type" => Type::String,
+            (None, ty) => {
+                self.error(TypeErrorKind::UnknownField(name.to_string(), ty.clone()), span);
+                Type::Unknown
+            }
+        }
+    }
+
+    // `value.name(args)` calls a function field of `value`, or else the function
+    // `name` with `value` as its `self`
+    fn method(
+        &mut self,
+        receiver: Type,
+        callee: &Expr,
+        args: &Expr,
+        block: Option<&Expr>,
+        span: Span,
+    ) -> Type {
+        let (name, name_span) = match identifier(callee) {
+            Some(callee) => callee,
+            None => return Type::Unknown,
+        };
+        match member(&receiver, name) {
+            Some(Type::Function(function))
+                if function.params.iter().any(|(param, _)| param == "self") =>
+            {
+                self.call(
+                    Type::Function(function),
+                    Some(receiver),
+                    Some(args),
+                    block,
+                    span,
+                )
+            }
+            Some(field) => self.call(field, None, Some(args), block, span),
+            None => match self.resolution.uses.get(&name_span) {
+                Some(id) => {
+                    let function = self.declaration_type(*id);
+                    self.call(function, Some(receiver), Some(args), block, span)
+                }
+                None => {
+                    if receiver != Type::Unknown {
+                        self.error(
+                            TypeErrorKind::UnknownField(name.to_string(), receiver),
+                            name_span,
+                        );
+                    }
+                    self.call(Type::Unknown, None, Some(args), block, span)
+                }
+            },
+        }
+    }
+
+    fn accessor(&mut self, binary: &Binary) -> Type {
+        let mut chain = Vec::new();
+        accessor_chain(&binary.left, &mut chain);
+        accessor_chain(&binary.right, &mut chain);
+        // `a.b: 1` is parsed as `a.(b: 1)`
+        if let Some(Expr::Binary(Binary {
+            op: op @ (BinaryOp::Assignment | BinaryOp::Reassignment),
+            left,
+            right,
+            ..
+        })) = chain.last()
+        {
+            let value = self.expr(right);
+            let mut path = chain[..chain.len() - 1].to_vec();
+            path.push(left);
+            self.assign_path(&path, value, right.span(), *op == BinaryOp::Assignment);
+            return Type::Void;
+        }
+
+        let mut ty = self.expr(chain[0]);
+        for expr in &chain[1..] {
+            ty = match expr {
+                Expr::Atom(Atom::Identifier(name), span, _) => self.field(&ty, name, *span),
+                Expr::Binary(Binary {
+                    op: BinaryOp::Invoke,
+                    left,
+                    right,
+                    span,
+                    ..
+                }) => self.method(ty, left, right, None, *span),
+                Expr::Ternary(Ternary {
+                    left,
+                    middle,
+                    right,
+                    span,
+                    ..
+                }) => self.method(ty, left, middle, Some(right), *span),
+                expr => {
+                    self.expr(expr);
+                    Type::Unknown
+                }
+            };
+        }
+        if self.is_read_line(&chain) {
+            return Type::String;
+        }
+        ty
+    }
+
+    // `std.io.stdin.readLine()`, when `std` isn't declared in the program
+    fn is_read_line(&self, chain: &[&Expr]) -> bool {
+        let [std, io, stdin, Expr::Binary(Binary { op: BinaryOp::Invoke, left, .. })] = chain
+        else {
+            return false;
+        };
+        matches!(identifier(std), Some(("std", span)) if !self.resolution.uses.contains_key(&span))
+            && matches!(identifier(io), Some(("io", _)))
+            && matches!(identifier(stdin), Some(("stdin", _)))
+            && matches!(identifier(left), Some(("readLine", _)))
+    }
+}
+
+#[cfg(test)]
+fn check_source(source: &str) -> (Expr, Types) {
+    let ast = crate::parser::Parser::new(source).parse().unwrap();
+    let resolution = crate::resolve::resolve(&ast);
+    let types = check(&ast, &resolution);
+    (ast, types)
+}
+
+#[test]
+fn test_typeck() {
+    let source = "\
+Address: { city: String, zip: Int }
+Person: Type { name: String, age: Int, ...Address, nickname: \"\" }
+greet: Fn {
+    args: { self: Person, greeting: String }
+    return: String
+    body: { greeting + self.name }
+}
+jane: Person(name: \"Jane\", age: 7, city: \"Oslo\", zip: 1234)
+text: jane.greet(\"hi \")
+older: jane.age + 1
+big: Int.I32(1)
+half: 7.0 / 2.0";
+    let (ast, types) = check_source(source);
+    assert_eq!(types.errors, []);
+    let items = items(&ast);
+    let declared = |item: usize| match &items[item] {
+        Expr::Binary(binary) => types.of(&binary.left).to_string(),
+        _ => unreachable!(),
+    };
+    assert_eq!(declared(1), "type Person");
+    assert_eq!(
+        declared(2),
+        "Fn { args: { self: Person, greeting: String }, return: String }"
+    );
+    assert_eq!(declared(3), "Person");
+    assert_eq!(declared(4), "String");
+    assert_eq!(declared(5), "Int");
+    assert_eq!(declared(6), "i32");
+    assert_eq!(declared(7), "Float");
+}
+
+#[test]
+fn test_typeck_errors() {
+    let source = "\
+Person: { name: String, age: Int }
+count: 0
+jane: Person(name: \"Jane\", agee: 7)
+count: \"one\"
+total: jane.name * 2
+jane.height
+add: Fn { args: { a: Int, b: Int } body: { a + b } }
+add(1, \"2\")
+add(1)
+if (count) { count: 1 }";
+    let (_, types) = check_source(source);
+    let errors: Vec<String> = types.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        [
+            "3:34: `Person` has no field `agee`",
+            "3:7: missing field `age` for `Person`",
+            "4:8: mismatched types: `count` is `Int`, found `String`",
+            "5:8: cannot apply `*` to `String` and `Int`",
+            "6:6: `Person` has no field `height`",
+            "8:8: mismatched types: expected `Int`, found `String`",
+            "9:1: missing argument `b`",
+            "10:5: expected a `Bool` condition, found `Int`",
+        ]
+    );
+}
+
+#[test]
+fn test_typeck_enums() {
+    let source = "\
+Rgb: Enum { RED, GREEN, BLUE }
+Token: Enum { Comment: String, Name: String }
+color: Rgb(0)
+parsed: Rgb.parse(\"RED\")
+token: Token.Comment(\"hi\")
+text: token.value
+name: String.parse(color)
+index: Int(Rgb.BLUE)
+Token.Name(1)
+color.value";
+    let (ast, types) = check_source(source);
+    let errors: Vec<String> = types.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        [
+            "9:12: mismatched types: expected `String`, found `Int`",
+            "10:7: `Rgb` has no field `value`",
+        ]
+    );
+    let items = items(&ast);
+    let declared = |item: usize| match &items[item] {
+        Expr::Binary(binary) => types.of(&binary.left).to_string(),
+        _ => unreachable!(),
+    };
+    assert_eq!(declared(0), "type Rgb");
+    assert_eq!(declared(2), "Rgb");
+    assert_eq!(declared(3), "Rgb");
+    assert_eq!(declared(4), "Token");
+    assert_eq!(declared(5), "String");
+    assert_eq!(declared(6), "String");
+    assert_eq!(declared(7), "Int");
+}
+
+#[test]
+fn test_typeck_node_ids() {
+    // the program and its only item share a span, only the item is typed
+    let (ast, types) = check_source("{ a: 1, b: \"b\" }");
+    let record = &items(&ast)[0];
+    assert_eq!(ast.span(), record.span());
+    assert_eq!(types.of(record).to_string(), "{ a: Int, b: String }");
+    assert_eq!(types.of(&ast).to_string(), "unknown");
+}
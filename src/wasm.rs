@@ -0,0 +1,278 @@
+// Runs the modules `wat_codegen` writes with wasmi, an interpreter for
+// WebAssembly in Rust, so that backend can be tried without a browser or a
+// runtime installed. The WASI functions the modules import are provided here,
+// reading from `input` and writing to `out` rather than the process's stdin
+// and stdout.
+
+use std::io::{Read, Write};
+
+use wasmi::{Caller, Engine, Extern, Linker, Module, Store};
+
+const WASI: &str = "wasi_snapshot_preview1";
+// the WASI errno for a file descriptor that isn't open
+const BADF: i32 = 8;
+
+// what the WASI functions read from and write to
+struct Host<R, W> {
+    input: R,
+    out: W,
+}
+
+/// Runs the module's `_start` and returns the status it exits with, 0 when
+/// it returns without calling `proc_exit`, and `out` back.
+pub fn run<R: Read + 'static, W: Write + 'static>(
+    wasm: &[u8],
+    input: R,
+    out: W,
+) -> Result<(i32, W), wasmi::Error> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm)?;
+    let mut store = Store::new(&engine, Host { input, out });
+    let mut linker = Linker::<Host<R, W>>::new(&engine);
+    linker.func_wrap(WASI, "fd_read", fd_read::<R, W>)?;
+    linker.func_wrap(WASI, "fd_write", fd_write::<R, W>)?;
+    linker.func_wrap(
+        WASI,
+        "proc_exit",
+        |status: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::i32_exit(status)) },
+    )?;
+    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+
+    let start = instance.get_typed_func::<(), ()>(&store, "_start")?;
+    let status = match start.call(&mut store, ()) {
+        Ok(()) => 0,
+        Err(error) => match error.i32_exit_status() {
+            Some(status) => status,
+            None => return Err(error),
+        },
+    };
+    let mut out = store.into_data().out;
+    let _ = out.flush();
+    Ok((status, out))
+}
+
+fn memory<T>(caller: &Caller<'_, T>) -> Result<wasmi::Memory, wasmi::Error> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmi::Error::new("the module doesn't export its memory")),
+    }
+}
+
+fn word(memory: &[u8], address: usize) -> Result<usize, wasmi::Error> {
+    match memory.get(address..address + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize),
+        None => Err(wasmi::Error::new("a WASI call outside the module's memory")),
+    }
+}
+
+// only stdin is open, each (address, length) pair is filled from `input` in
+// turn until a read comes up short
+fn fd_read<R: Read, W>(
+    mut caller: Caller<'_, Host<R, W>>,
+    fd: i32,
+    iovs: i32,
+    iovs_len: i32,
+    read: i32,
+) -> Result<i32, wasmi::Error> {
+    if fd != 0 {
+        return Ok(BADF);
+    }
+    let memory = memory(&caller)?;
+    let mut total = 0;
+    for i in 0..iovs_len as usize {
+        let iov = iovs as usize + i * 8;
+        let (address, length) = {
+            let data = memory.data(&caller);
+            (word(data, iov)?, word(data, iov + 4)?)
+        };
+        let mut buffer = vec![0; length];
+        let count = match caller.data_mut().input.read(&mut buffer) {
+            Ok(count) => count,
+            Err(_) => return Ok(BADF),
+        };
+        match memory
+            .data_mut(&mut caller)
+            .get_mut(address..address + count)
+        {
+            Some(slice) => slice.copy_from_slice(&buffer[..count]),
+            None => return Err(wasmi::Error::new("fd_read outside the module's memory")),
+        }
+        total += count;
+        if count < length {
+            break;
+        }
+    }
+    match memory
+        .data_mut(&mut caller)
+        .get_mut(read as usize..read as usize + 4)
+    {
+        Some(slot) => slot.copy_from_slice(&(total as u32).to_le_bytes()),
+        None => return Err(wasmi::Error::new("fd_read outside the module's memory")),
+    }
+    Ok(0)
+}
+
+// only stdout is open, the bytes of each (address, length) pair are written to `out`
+fn fd_write<R, W: Write>(
+    mut caller: Caller<'_, Host<R, W>>,
+    fd: i32,
+    iovs: i32,
+    iovs_len: i32,
+    written: i32,
+) -> Result<i32, wasmi::Error> {
+    if fd != 1 {
+        return Ok(BADF);
+    }
+    let memory = memory(&caller)?;
+    let mut bytes = Vec::new();
+    {
+        let data = memory.data(&caller);
+        for i in 0..iovs_len as usize {
+            let iov = iovs as usize + i * 8;
+            let (address, length) = (word(data, iov)?, word(data, iov + 4)?);
+            match data.get(address..address + length) {
+                Some(slice) => bytes.extend_from_slice(slice),
+                None => return Err(wasmi::Error::new("fd_write outside the module's memory")),
+            }
+        }
+    }
+    if caller.data_mut().out.write_all(&bytes).is_err() {
+        return Ok(BADF);
+    }
+    let data = memory.data_mut(&mut caller);
+    match data.get_mut(written as usize..written as usize + 4) {
+        Some(slot) => slot.copy_from_slice(&(bytes.len() as u32).to_le_bytes()),
+        None => return Err(wasmi::Error::new("fd_write outside the module's memory")),
+    }
+    Ok(0)
+}
+
+#[test]
+fn test_run_wasm() {
+    use crate::{ir, wat_codegen};
+
+    let program = ir::lower_source(
+        "\
+Point: { x: Int, y: Int }
+sum: Fn { args: { self: Point } body: { self.x + self.y } }
+main: Fn {
+    return: Int
+    body: {
+        point: Point(x: 2, y: 5)
+        io.std.writeLine(\"sum\", point.sum(), -3, true, 'é')
+        std.io.stdout.write(\"${point.x}!\")
+        point.sum()
+    }
+}",
+    )
+    .unwrap();
+    let wasm = wat_codegen::wasm(&program).unwrap();
+    assert_eq!(&wasm[..4], b"\0asm");
+    let (status, out) = run(&wasm, std::io::empty(), Vec::new()).unwrap();
+    assert_eq!(status, 7);
+    assert_eq!(String::from_utf8(out).unwrap(), "sum 7 -3 true é\n2!");
+}
+
+#[test]
+fn test_run_wasm_exit() {
+    use crate::{ir, wat_codegen};
+
+    let program = ir::lower_source(
+        "\
+main: Fn {
+    body: {
+        io.println(\"before\")
+        std.io.stdout.write(\"no newline\")
+        exit(3)
+        io.println(\"after\")
+    }
+}",
+    )
+    .unwrap();
+    let wasm = wat_codegen::wasm(&program).unwrap();
+    let (status, out) = run(&wasm, std::io::empty(), Vec::new()).unwrap();
+    assert_eq!(status, 3);
+    assert_eq!(String::from_utf8(out).unwrap(), "before\nno newline");
+}
+
+// runs `source` as WebAssembly and with the interpreter, both reading
+// `input`, a runtime error in the interpreter is the status a compiled
+// program exits with
+#[cfg(test)]
+fn run_both(source: &str, input: &'static str) -> ((i32, String), (i32, String)) {
+    use crate::{driver, interp, ir, parser, wat_codegen};
+
+    let wasm = wat_codegen::wasm(&ir::lower_source(source).unwrap()).unwrap();
+    let (status, out) = run(&wasm, input.as_bytes(), Vec::new()).unwrap();
+    let ast = parser::Parser::new(source).parse().unwrap();
+    let mut interpreter = interp::Interpreter::new(input.as_bytes(), Vec::new());
+    let interpreted =
+        interp::with_stack(|| interpreter.run(&ast)).unwrap_or(driver::EXIT_RUNTIME_ERROR as i32);
+    (
+        (status, String::from_utf8(out).unwrap()),
+        (interpreted, String::from_utf8(interpreter.out).unwrap()),
+    )
+}
+
+#[test]
+fn test_run_wasm_enums() {
+    let (wasm, interpreted) = run_both(
+        "\
+Rgb: Enum { RED, GREEN }
+Currency: Enum { GBP: \"GBP\", USD: \"USD\" }
+Token: Enum { Comment: String, Number: Int, NewLine }
+color: Rgb(1)
+comment: Token.Comment(\"a comment\")
+number: Token.Number(42)
+io.std.writeLine(color, Int(color), String.parse(Currency.GBP), Rgb.parse(\"RED\"), String.parse(color))
+io.std.writeLine(comment, comment.value, number.value, \"${number.value}!\", String(comment.value))
+io.std.writeLine(comment == Token.Comment(\"a comment\"), comment == Token.Comment(\"other\"))
+io.std.writeLine(comment != number, number == Token.Number(42), Token.NewLine == Token.NewLine)
+io.std.writeLine(color == Rgb.GREEN, comment.value == \"a comment\", number.value == 42)",
+        "",
+    );
+    assert_eq!(
+        wasm,
+        (
+            0,
+            "\
+Rgb.GREEN 1 GBP Rgb.RED GREEN
+Token.Comment a comment 42 42! a comment
+true false
+true true true
+true true true
+"
+            .to_string()
+        )
+    );
+    assert_eq!(wasm, interpreted);
+}
+
+#[test]
+fn test_run_wasm_enum_errors() {
+    for source in [
+        "Rgb: Enum { RED, GREEN }\nio.std.writeLine(Rgb.RED)\nRgb.parse(\"PINK\")",
+        "Rgb: Enum { RED, GREEN }\nio.std.writeLine(Rgb.RED)\nRgb(2)",
+        "\
+Token: Enum { Comment: String, Number: Int, NewLine }
+io.std.writeLine(Token.Comment(\"a\"))
+io.std.writeLine(Token.NewLine.value)",
+    ] {
+        let (wasm, interpreted) = run_both(source, "");
+        assert_eq!(wasm.0, 101);
+        assert_eq!(wasm, interpreted);
+    }
+}
+
+#[test]
+fn test_run_wasm_read_line() {
+    let source = "\
+name: std.io.stdin.readLine()
+io.println(\"hello ${name}!\")
+second: std.io.stdin.readLine()
+io.println(\"${second}.\")
+io.println(std.io.stdin.readLine() == \"\")";
+    let (wasm, interpreted) = run_both(source, "world\nsecond line");
+    assert_eq!(wasm, (0, "hello world!\nsecond line.\ntrue\n".to_string()));
+    assert_eq!(wasm, interpreted);
+}
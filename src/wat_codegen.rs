@@ -1,35 +1,167 @@
-use std::collections::HashMap;
-use crate::ast::*;
+// WebAssembly text for a lowered program.
+//
+// Everything is in one module. Numbers map onto the four value types, and
+// everything else is an i32: bools, chars, and pointers into linear memory.
+// Strings are pointers to a length followed by UTF-8 bytes, kept in a data
+// segment. Records are pointers to 8 byte slots, one per field, allocated
+// from a bump heap that starts after the strings. Enum values are laid out
+// like a record of two fields, the index of the variant and its payload.
+// Function values are indexes into the function table.
+//
+// Output and exiting go through WASI imports, so a module runs under any WASI
+// runtime, and `_start` runs the program like a command.
 
-use crate::parser;
+use std::collections::{BTreeSet, HashMap};
 
+use crate::ast::{BinaryOp, NumberSuffix, UnaryOp};
+use crate::diagnostics::Diagnostic;
+use crate::ir::{self, Callee, Node, Symbol};
+use crate::typeck::{FunctionType, RecordType, Type, VariantKind};
+
+const PAGE_BITS: u32 = 16;
+// every record field takes a slot this size, whatever its type
+const FIELD_SIZE: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ValueType {
-    Int,
-    Float,
-    Bool,
-    Char,
-    Octal,
-    Hex,
-    Binary,
-    String,
+    I32,
+    I64,
+    F32,
+    F64,
 }
 
+impl ValueType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::I32 => "i32",
+            ValueType::I64 => "i64",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+        }
+    }
+}
 
 pub struct FunctionDefinition {
     name: String,
-    params: Vec<(String, ValueType)>,
-    return_type: ValueType,
-    body: Vec<Expr>,
+    // index in the function table, for function values
+    index: usize,
+    signature: FunctionType,
+}
+
+#[derive(Debug)]
+pub enum WatError {
+    // what the WebAssembly backend can't compile yet, for the message
+    Unsupported(String),
+    // the text didn't assemble, which is a bug here rather than in the program
+    Invalid(String),
+}
+
+impl From<&WatError> for Diagnostic {
+    fn from(error: &WatError) -> Self {
+        match error {
+            WatError::Unsupported(what) => Diagnostic::error(format!(
+                "the WebAssembly backend can't compile {} yet",
+                what
+            ))
+            .with_note("`zen run` can still run it with the interpreter"),
+            WatError::Invalid(message) => Diagnostic::error(format!(
+                "the WebAssembly backend wrote an invalid module: {}",
+                message
+            )),
+        }
+    }
+}
+
+/// The program as a binary module, what `zen build --target wasm32` writes.
+pub fn wasm(program: &ir::Program) -> Result<Vec<u8>, WatError> {
+    let wat = watCodeGen::new().generate(program)?;
+    wat::parse_str(&wat).map_err(|error| WatError::Invalid(error.to_string()))
+}
+
+fn unsupported<T>(what: impl Into<String>) -> Result<T, WatError> {
+    Err(WatError::Unsupported(what.into()))
+}
+
+fn value_type(ty: &Type) -> Result<Option<ValueType>, WatError> {
+    Ok(Some(match ty {
+        Type::Void | Type::Unknown => return Ok(None),
+        Type::Int(None) => ValueType::I64,
+        Type::Int(Some(suffix)) => match suffix.bits() {
+            // narrower ints are kept in an i32 without wrapping
+            0..=32 => ValueType::I32,
+            64 => ValueType::I64,
+            _ => return unsupported(format!("`{}`", ty)),
+        },
+        Type::Float(Some(NumberSuffix::F32)) => ValueType::F32,
+        Type::Float(_) => ValueType::F64,
+        Type::Bool
+        | Type::Char
+        | Type::String
+        | Type::Record(_)
+        | Type::Enum(_)
+        | Type::Function(_) => ValueType::I32,
+        ty => return unsupported(format!("values of type `{}`", ty)),
+    }))
+}
+
+fn is_signed(ty: &Type) -> bool {
+    match ty {
+        Type::Int(Some(suffix)) => suffix.is_signed(),
+        Type::Bool | Type::Char => false,
+        _ => true,
+    }
+}
+
+// the program's own functions are exported under their names, lifted ones
+// are named after the function they were written in
+fn is_exported(name: &str) -> bool {
+    !name.contains('.')
+}
+
+fn float_literal(value: f64) -> String {
+    format!("{:?}", value).to_lowercase()
+}
+
+fn params(signature: &FunctionType) -> Result<String, WatError> {
+    let mut wat = String::new();
+    for (name, ty) in &signature.params {
+        match value_type(ty)? {
+            Some(value_type) => wat.push_str(&format!(" (param {})", value_type.name())),
+            None => return unsupported(format!("the parameter `{}` without a type", name)),
+        }
+    }
+    if let Some(ret) = value_type(&signature.ret)? {
+        wat.push_str(&format!(" (result {})", ret.name()));
+    }
+    Ok(wat)
 }
 
 pub struct watCodeGen {
     indentation_level: usize,
-    symbol_table: HashMap<String, ValueType>,
+    // symbol id -> the variable's name in the function or module, and its type
+    symbol_table: HashMap<usize, (String, Type)>,
     function_table: HashMap<String, FunctionDefinition>,
     current_function: Option<String>,
     label_counter: usize,
+    out: String,
+    // the string literals, laid out from `DATA_START`, and where each one is
+    strings: HashMap<String, u32>,
+    data: Vec<u8>,
+    // locals a function needs besides its variables, like the record being built
+    scratch: Vec<(String, ValueType)>,
+    // the runtime functions the program calls, written once after `$zen.alloc`
+    runtime: BTreeSet<&'static str>,
 }
 
+// `fd_write` and `fd_read` take the bytes to write or read into from the
+// first 8 bytes and put the count after them, so no string or record starts
+// at address 0
+const DATA_START: u32 = 16;
+const IOVEC: u32 = 0;
+const WRITTEN: u32 = 8;
+const STDIN: u32 = 0;
+const STDOUT: u32 = 1;
+
 impl watCodeGen {
     pub fn new() -> Self {
         Self {
@@ -38,99 +170,1310 @@ impl watCodeGen {
             function_table: HashMap::new(),
             current_function: None,
             label_counter: 0,
+            out: String::new(),
+            strings: HashMap::new(),
+            data: Vec::new(),
+            scratch: Vec::new(),
+            runtime: BTreeSet::new(),
         }
     }
-    pub fn generate(&self, expr: &Expr) -> String {
-        match expr {
-            Expr::Atom(atom) => self.atom_to_wat(atom),
-            Expr::Unary(unary) => self.unary_to_wat(unary),
-            Expr::Binary(binary) => self.binary_to_wat(binary),
-            Expr::Ternary(ternary) => self.ternary_to_wat(ternary),
-            Expr::Group(group) => self.group_to_wat(group),
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        for _ in 0..self.indentation_level {
+            self.out.push_str("  ");
         }
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn label(&mut self, name: &str) -> String {
+        self.label_counter += 1;
+        format!("${}{}", name, self.label_counter)
     }
 
-    fn literal_to_wat(&self, literal: &Literal) -> String {
-        match literal {
-            Literal::IntLiteral(i) => format!("(int {})", i),
-            Literal::FloatLiteral(f) => format!("(float {})", f),
-            Literal::BoolLiteral(b) => format!("(bool {})", b),
-            Literal::CharLiteral(c) => format!("(char {})", c),
-            Literal::StringLiteral(s) => format!("(string {})", s),
-            Literal::OctalLiteral(o) => format!("(octal {})", o),
-            Literal::HexLiteral(h) => format!("(hex {})", h),
-            Literal::BinaryLiteral(b) => format!("(binary {})", b),
+    pub fn generate(&mut self, program: &ir::Program) -> Result<String, WatError> {
+        let functions: Vec<&ir::FnDecl> = program.functions.iter().chain([&program.init]).collect();
+        for (index, function) in functions.iter().enumerate() {
+            let signature = FunctionType {
+                params: function
+                    .params
+                    .iter()
+                    .map(|(symbol, ty)| (symbol.name.clone(), ty.clone()))
+                    .collect(),
+                ret: function.ret.clone(),
+            };
+            let definition = FunctionDefinition {
+                name: function.name.clone(),
+                index,
+                signature,
+            };
+            self.function_table
+                .insert(function.name.clone(), definition);
+        }
+        for (symbol, ty) in &program.globals {
+            self.symbol_table
+                .insert(symbol.id, (format!("${}", symbol.name), ty.clone()));
+        }
+
+        // the functions first, since they decide what goes in the data segment
+        let mut bodies = String::new();
+        for function in &functions {
+            self.indentation_level = 1;
+            self.function(function)?;
+            bodies.push_str(&std::mem::take(&mut self.out));
         }
+        self.start(program.main.as_deref())?;
+        self.runtime();
+        bodies.push_str(&std::mem::take(&mut self.out));
+
+        self.indentation_level = 0;
+        self.line("(module");
+        self.indentation_level = 1;
+        for import in WASI {
+            self.line(format!("(import \"wasi_snapshot_preview1\" {})", import));
+        }
+        let heap = (DATA_START + self.data.len() as u32 + 7) & !7;
+        let pages = (heap >> PAGE_BITS) + 1;
+        self.line(format!("(memory (export \"memory\") {})", pages));
+        self.line(format!("(global $zen.heap (mut i32) (i32.const {}))", heap));
+        for (symbol, ty) in &program.globals {
+            let value_type = match value_type(ty)? {
+                Some(value_type) => value_type,
+                None => return unsupported(format!("the variable `{}`", symbol.name)),
+            };
+            self.line(format!(
+                "(global ${} (mut {}) ({}.const 0))",
+                symbol.name,
+                value_type.name(),
+                value_type.name()
+            ));
+        }
+        if !self.data.is_empty() {
+            let bytes: String = self
+                .data
+                .iter()
+                .map(|byte| format!("\\{:02x}", byte))
+                .collect();
+            self.line(format!("(data (i32.const {}) \"{}\")", DATA_START, bytes));
+        }
+        let names: Vec<String> = functions
+            .iter()
+            .map(|function| format!("${}", function.name))
+            .collect();
+        self.line(format!("(table {} funcref)", names.len()));
+        self.line(format!("(elem (i32.const 0) func {})", names.join(" ")));
+        self.alloc();
+        let mut wat = std::mem::take(&mut self.out);
+        wat.push_str(&bodies);
+        wat.push(')');
+        Ok(wat)
     }
 
-    fn atom_to_wat(&self, atom: &Atom) -> String {
-        match atom {
-            Atom::Identifier(id) => format!("(identifier {})", id),
-            Atom::Literal(literal) => self.literal_to_wat(literal),
-            Atom::EndOfFile => "(end)".to_string(),
+    // `_start`, what a WASI runtime calls, exits with what `main` returns
+    fn start(&mut self, main: Option<&str>) -> Result<(), WatError> {
+        self.line("(func $_start (export \"_start\")");
+        self.indentation_level += 1;
+        self.line(format!("call ${}", ir::INIT));
+        // a `main` with parameters is left for the host to call with them
+        let main = main.filter(|main| self.function_table[*main].signature.params.is_empty());
+        if let Some(main) = main {
+            let signature = self.function_table[main].signature.clone();
+            self.line(format!("call ${}", main));
+            match value_type(&signature.ret)? {
+                Some(_) if matches!(signature.ret, Type::Int(_)) => {
+                    self.coerce(&signature.ret, &Type::Int(Some(NumberSuffix::I32)))?;
+                    self.line("call $wasi.proc_exit");
+                }
+                Some(_) => self.line("drop"),
+                None => {}
+            }
         }
+        self.indentation_level -= 1;
+        self.line(")");
+        Ok(())
     }
-    fn unary_to_wat(&self, unary: &Unary) -> String {
-        let expr = self.generate(&*unary.expr);
 
-        match unary.op {
-            UnaryOp::SpreadExpr => format!("(spread {})", expr),
+    // the runtime functions that were used, and the ones those call
+    fn runtime(&mut self) {
+        if self.runtime.contains("$zen.string.i64") {
+            self.runtime.extend(["$zen.string.u64", "$zen.concat"]);
+        }
+        for name in std::mem::take(&mut self.runtime) {
+            let text = match name {
+                "$zen.write" => WRITE
+                    .replace("{IOVEC}", &IOVEC.to_string())
+                    .replace("{WRITTEN}", &WRITTEN.to_string())
+                    .replace("{STDOUT}", &STDOUT.to_string()),
+                "$zen.read_line" => READ_LINE
+                    .replace("{IOVEC}", &IOVEC.to_string())
+                    .replace("{WRITTEN}", &WRITTEN.to_string())
+                    .replace("{STDIN}", &STDIN.to_string())
+                    .replace("{PAGE_BITS}", &PAGE_BITS.to_string()),
+                "$zen.concat" => CONCAT.to_string(),
+                "$zen.string.u64" => STRING_U64.to_string(),
+                "$zen.string.i64" => {
+                    let minus = self.string("-");
+                    STRING_I64.replace("{MINUS}", &minus.to_string())
+                }
+                "$zen.string.char" => STRING_CHAR.to_string(),
+                "$zen.string.eq" => STRING_EQ.to_string(),
+                name => unreachable!("no runtime function {}", name),
+            };
+            for line in text.lines() {
+                self.line(line);
+            }
         }
     }
 
+    // `$zen.alloc`, bumps the heap and grows the memory when it runs out,
+    // sizes are rounded up to 8 bytes so records stay aligned
+    fn alloc(&mut self) {
+        let lines = [
+            "(func $zen.alloc (param $size i32) (result i32)",
+            "  (local $top i32)",
+            "  global.get $zen.heap",
+            "  local.get $size",
+            "  i32.const 7",
+            "  i32.add",
+            "  i32.const -8",
+            "  i32.and",
+            "  i32.add",
+            "  local.set $top",
+            "  block $enough",
+            "    local.get $top",
+            "    memory.size",
+            &format!("    i32.const {}", PAGE_BITS),
+            "    i32.shl",
+            "    i32.le_u",
+            "    br_if $enough",
+            "    local.get $top",
+            &format!("    i32.const {}", PAGE_BITS),
+            "    i32.shr_u",
+            "    memory.size",
+            "    i32.sub",
+            "    i32.const 1",
+            "    i32.add",
+            "    memory.grow",
+            "    drop",
+            "  end",
+            "  global.get $zen.heap",
+            "  local.get $top",
+            "  global.set $zen.heap",
+            ")",
+        ];
+        for line in lines {
+            self.line(line);
+        }
+    }
 
-    fn binary_to_wat(&self, binary: &Binary) -> String {
-        let left = self.generate(&*binary.left);
-        let right = self.generate(&*binary.right);
+    fn function(&mut self, function: &ir::FnDecl) -> Result<(), WatError> {
+        self.current_function = Some(function.name.clone());
+        let mut header = format!("(func ${}", function.name);
+        if is_exported(&function.name) || function.name == ir::INIT {
+            header.push_str(&format!(" (export \"{}\")", function.name));
+        }
+        // a parameter is named after itself, unless another variable of the
+        // function has its name
+        let variables: Vec<&(Symbol, Type)> =
+            function.params.iter().chain(&function.locals).collect();
+        for (symbol, ty) in &variables {
+            let shadowed = variables
+                .iter()
+                .any(|(other, _)| other.name == symbol.name && other.id != symbol.id);
+            let name = match shadowed {
+                true => format!("${}.{}", symbol.name, symbol.id),
+                false => format!("${}", symbol.name),
+            };
+            self.symbol_table.insert(symbol.id, (name, ty.clone()));
+        }
+        for (symbol, ty) in &function.params {
+            let value_type = match value_type(ty)? {
+                Some(value_type) => value_type,
+                None => {
+                    return unsupported(format!("the parameter `{}` without a type", symbol.name))
+                }
+            };
+            let name = &self.symbol_table[&symbol.id].0;
+            header.push_str(&format!(" (param {} {})", name, value_type.name()));
+        }
+        let ret = value_type(&function.ret)?;
+        if let Some(ret) = ret {
+            header.push_str(&format!(" (result {})", ret.name()));
+        }
 
-        // if Ident: Fn { } we are funcDef
-        // if Ident: Fn() { } we are funcCall
+        // the body goes first, so the scratch locals it needs are known
+        let outer = std::mem::take(&mut self.out);
+        let indentation_level = self.indentation_level;
+        self.indentation_level += 1;
+        let body = match &function.result {
+            // `return:` was given a value, that is the result
+            Some(result) => Node::Block(
+                vec![
+                    function.body.clone(),
+                    Node::Var(result.clone(), function.ret.clone()),
+                ],
+                function.ret.clone(),
+            ),
+            None => function.body.clone(),
+        };
+        let left = self.node(&body)?;
+        match (ret, left) {
+            (Some(_), Some(_)) => self.coerce(&body.ty(), &function.ret)?,
+            // the body ends in a `return` or `exit`
+            (Some(_), None) => self.line("unreachable"),
+            (None, Some(_)) => self.line("drop"),
+            (None, None) => {}
+        }
+        let body = std::mem::replace(&mut self.out, outer);
+        self.indentation_level = indentation_level;
 
-        "".to_string()
+        self.line(header);
+        self.indentation_level += 1;
+        for (symbol, ty) in &function.locals {
+            if let Some(value_type) = value_type(ty)? {
+                let name = self.symbol_table[&symbol.id].0.clone();
+                self.line(format!("(local {} {})", name, value_type.name()));
+            }
+        }
+        for (name, value_type) in std::mem::take(&mut self.scratch) {
+            self.line(format!("(local {} {})", name, value_type.name()));
+        }
+        self.indentation_level -= 1;
+        self.out.push_str(&body);
+        self.line(")");
+        Ok(())
+    }
 
+    fn variable(&self, symbol: &Symbol) -> Result<(String, Type), WatError> {
+        match self.symbol_table.get(&symbol.id) {
+            Some(variable) => Ok(variable.clone()),
+            None => unsupported(format!("`{}`", symbol.name)),
+        }
     }
-    fn ternary_to_wat(&self, ternary: &Ternary) -> String {
-        let left = self.generate(&*ternary.left);
-        let middle = self.generate(&*ternary.middle);
-        let right = self.generate(&*ternary.right);
 
-        match ternary.op {
-            TernaryOp::FuncDefInvoke => format!("(invoke {} {} {})", left, middle, right),
+    // where the string is in memory, it is added to the data segment the first time
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(address) = self.strings.get(value) {
+            return *address;
         }
+        let address = DATA_START + self.data.len() as u32;
+        self.data.extend((value.len() as u32).to_le_bytes());
+        self.data.extend(value.as_bytes());
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+        self.strings.insert(value.to_string(), address);
+        address
     }
 
-    fn group_to_wat(&self, group: &Group) -> String {
-        let exprs = group.exprs.iter().map(|expr| self.generate(expr)).collect::<Vec<_>>().join(" ");
+    // converts the value on the stack from `from` to `to`, a literal without
+    // a suffix takes the width of the sized number it is used with
+    fn coerce(&mut self, from: &Type, to: &Type) -> Result<(), WatError> {
+        match (from, to) {
+            (Type::Record(from), Type::Record(to)) => {
+                let same_layout = from
+                    .fields
+                    .iter()
+                    .map(|field| &field.name)
+                    .eq(to.fields.iter().map(|field| &field.name));
+                if !same_layout {
+                    return unsupported("a record where one with other fields is expected");
+                }
+            }
+            (Type::Int(_), Type::Int(_)) | (Type::Float(_), Type::Float(_)) => {
+                match (value_type(from)?, value_type(to)?) {
+                    (Some(ValueType::I64), Some(ValueType::I32)) => self.line("i32.wrap_i64"),
+                    (Some(ValueType::I32), Some(ValueType::I64)) => match is_signed(from) {
+                        true => self.line("i64.extend_i32_s"),
+                        false => self.line("i64.extend_i32_u"),
+                    },
+                    (Some(ValueType::F64), Some(ValueType::F32)) => self.line("f32.demote_f64"),
+                    (Some(ValueType::F32), Some(ValueType::F64)) => self.line("f64.promote_f32"),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-        match group.op {
-            GroupOp::AssignmentBlock => format!("(block {})", exprs),
-            GroupOp::StatementBlock => format!("(block {})", exprs),
-            GroupOp::ParamBlock => format!("(block {})", exprs),
-            GroupOp::AnonymousType => format!("(type {})", exprs),
+    // leaves the node's value on the stack, and returns its type, `None` for
+    // nodes without a value
+    fn value(&mut self, node: &Node) -> Result<ValueType, WatError> {
+        match self.node(node)? {
+            Some(value_type) => Ok(value_type),
+            None => unsupported(format!("`{}` as a value", node)),
         }
     }
 
+    fn coerced(&mut self, node: &Node, to: &Type) -> Result<(), WatError> {
+        // a literal is written at the width it's used with
+        if let (Node::Int(value, Type::Int(None)), Type::Int(_)) = (node, to) {
+            return self.value(&Node::Int(*value, to.clone())).map(|_| ());
+        }
+        self.value(node)?;
+        self.coerce(&node.ty(), to)
+    }
+
+    fn node(&mut self, node: &Node) -> Result<Option<ValueType>, WatError> {
+        let result = value_type(&node.ty())?;
+        match node {
+            Node::Int(value, ty) => match result {
+                Some(ValueType::I32) => self.line(format!("i32.const {}", *value as i32)),
+                Some(ValueType::I64) => self.line(format!("i64.const {}", *value as i64)),
+                _ => return unsupported(format!("`{}`", ty)),
+            },
+            Node::Float(value, _) => {
+                let value_type = result.unwrap();
+                self.line(format!(
+                    "{}.const {}",
+                    value_type.name(),
+                    float_literal(*value)
+                ));
+            }
+            Node::Bool(value) => self.line(format!("i32.const {}", *value as i32)),
+            Node::Char(value) => self.line(format!("i32.const {}", *value as u32)),
+            Node::String(value) => {
+                let address = self.string(value);
+                self.line(format!("i32.const {}", address));
+            }
+            Node::Void => {}
+            Node::Var(symbol, _) => {
+                let (name, _) = self.variable(symbol)?;
+                match symbol.global {
+                    true => self.line(format!("global.get {}", name)),
+                    false => self.line(format!("local.get {}", name)),
+                }
+            }
+            Node::FnRef(name, _) => {
+                let index = self.function_table[name].index;
+                self.line(format!("i32.const {}", index));
+            }
+            Node::Let(symbol, value) | Node::Set(symbol, value) => {
+                let (name, ty) = self.variable(symbol)?;
+                self.coerced(value, &ty)?;
+                match symbol.global {
+                    true => self.line(format!("global.set {}", name)),
+                    false => self.line(format!("local.set {}", name)),
+                }
+            }
+            Node::Construct { ty, fields } => self.construct(ty, fields)?,
+            Node::FieldGet { record, field, .. } => {
+                let (offset, _) = self.field(record, field)?;
+                let value_type = result.unwrap();
+                self.line(format!("{}.load offset={}", value_type.name(), offset));
+            }
+            Node::SetField {
+                record,
+                field,
+                value,
+            } => {
+                let (offset, ty) = self.field(record, field)?;
+                self.coerced(value, &ty)?;
+                let value_type = value_type_of(&ty)?;
+                self.line(format!("{}.store offset={}", value_type.name(), offset));
+            }
+            Node::Unary { op, operand, ty } => self.unary(op, operand, ty)?,
+            Node::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                left,
+                right,
+                ..
+            } => {
+                // the right side is only evaluated when the left doesn't decide it
+                self.value(left)?;
+                self.line("if (result i32)");
+                self.indentation_level += 1;
+                match op {
+                    BinaryOp::And => self.value(right)?,
+                    _ => {
+                        self.line("i32.const 1");
+                        ValueType::I32
+                    }
+                };
+                self.indentation_level -= 1;
+                self.line("else");
+                self.indentation_level += 1;
+                match op {
+                    BinaryOp::And => {
+                        self.line("i32.const 0");
+                        ValueType::I32
+                    }
+                    _ => self.value(right)?,
+                };
+                self.indentation_level -= 1;
+                self.line("end");
+            }
+            Node::Binary {
+                op, left, right, ..
+            } => self.binary(op, left, right)?,
+            Node::Convert(value, ty) => self.convert(value, ty)?,
+            Node::Variant { ty, tag, payload } => self.variant(ty, *tag, payload.as_deref())?,
+            Node::Tag(value, ty) => {
+                self.value(value)?;
+                self.line("i32.load");
+                self.coerce(&Type::Int(Some(NumberSuffix::I32)), ty)?;
+            }
+            Node::Payload(value, ty) => {
+                let value_type = value_type_of(ty)?;
+                self.value(value)?;
+                self.line(format!("{}.load offset={}", value_type.name(), FIELD_SIZE));
+            }
+            Node::Call { callee, args, .. } => self.call(callee, args)?,
+            Node::Block(nodes, _) => {
+                let mut left = None;
+                for (i, node) in nodes.iter().enumerate() {
+                    left = self.node(node)?;
+                    if left.is_some() && i + 1 < nodes.len() {
+                        self.line("drop");
+                    }
+                }
+                return Ok(left);
+            }
+            Node::If {
+                cond,
+                then,
+                otherwise,
+                ty,
+            } => self.if_(cond, then, otherwise.as_deref(), ty)?,
+            Node::Loop { cond, body } => {
+                let (exit, repeat) = (self.label("break"), self.label("loop"));
+                self.line(format!("block {}", exit));
+                self.indentation_level += 1;
+                self.line(format!("loop {}", repeat));
+                self.indentation_level += 1;
+                self.value(cond)?;
+                self.line("i32.eqz");
+                self.line(format!("br_if {}", exit));
+                if self.node(body)?.is_some() {
+                    self.line("drop");
+                }
+                self.line(format!("br {}", repeat));
+                self.indentation_level -= 1;
+                self.line("end");
+                self.indentation_level -= 1;
+                self.line("end");
+            }
+            Node::Return(value) => {
+                let name = self.current_function.clone().unwrap();
+                let ret = self.function_table[&name].signature.ret.clone();
+                match value_type(&ret)? {
+                    Some(_) => self.coerced(value, &ret)?,
+                    None => {
+                        if self.node(value)?.is_some() {
+                            self.line("drop");
+                        }
+                    }
+                }
+                self.line("return");
+                return Ok(None);
+            }
+            Node::Exit(status) => {
+                self.coerced(status, &Type::Int(Some(NumberSuffix::I32)))?;
+                self.line("call $wasi.proc_exit");
+                self.line("unreachable");
+                return Ok(None);
+            }
+            Node::Print { args, newline } => {
+                // separated by spaces, like the interpreter
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        let space = self.string(" ");
+                        self.line(format!("i32.const {}", space));
+                        self.call_runtime("$zen.write");
+                    }
+                    self.text(arg)?;
+                    self.call_runtime("$zen.write");
+                }
+                if *newline {
+                    let newline = self.string("\n");
+                    self.line(format!("i32.const {}", newline));
+                    self.call_runtime("$zen.write");
+                }
+                return Ok(None);
+            }
+            Node::ReadLine => self.call_runtime("$zen.read_line"),
+            Node::Format(parts) => {
+                if parts.is_empty() {
+                    let empty = self.string("");
+                    self.line(format!("i32.const {}", empty));
+                }
+                for (i, part) in parts.iter().enumerate() {
+                    self.text(part)?;
+                    if i > 0 {
+                        self.call_runtime("$zen.concat");
+                    }
+                }
+            }
+        }
+        Ok(match node {
+            Node::Let(..) | Node::Set(..) | Node::SetField { .. } | Node::Loop { .. } => None,
+            _ => result,
+        })
+    }
+
+    // leaves the value as a string on the stack, written the way the interpreter writes it
+    fn text(&mut self, node: &Node) -> Result<(), WatError> {
+        let ty = node.ty();
+        match &ty {
+            Type::String => {
+                self.value(node)?;
+            }
+            Type::Int(_) => {
+                self.coerced(node, &Type::Int(Some(NumberSuffix::I64)))?;
+                match matches!(ty, Type::Int(Some(NumberSuffix::U64 | NumberSuffix::Usize))) {
+                    true => self.call_runtime("$zen.string.u64"),
+                    false => self.call_runtime("$zen.string.i64"),
+                }
+            }
+            Type::Bool => {
+                let (yes, no) = (self.string("true"), self.string("false"));
+                self.line(format!("i32.const {}", yes));
+                self.line(format!("i32.const {}", no));
+                self.value(node)?;
+                self.line("select");
+            }
+            Type::Char => {
+                self.value(node)?;
+                self.call_runtime("$zen.string.char");
+            }
+            ty => return unsupported(format!("writing a `{}`", ty)),
+        }
+        Ok(())
+    }
+
+    fn call_runtime(&mut self, name: &'static str) {
+        self.line(format!("call {}", name));
+        self.runtime.insert(name);
+    }
+
+    // the field's offset in the record, with the record's address left on the stack
+    fn field(&mut self, record: &Node, field: &str) -> Result<(u32, Type), WatError> {
+        let record_type = match record.ty() {
+            Type::Record(record_type) => record_type,
+            ty => return unsupported(format!("the fields of `{}`", ty)),
+        };
+        let index = match record_type.fields.iter().position(|f| f.name == field) {
+            Some(index) => index,
+            None => return unsupported(format!("the field `{}`", field)),
+        };
+        self.value(record)?;
+        Ok((
+            index as u32 * FIELD_SIZE,
+            record_type.fields[index].ty.clone(),
+        ))
+    }
+
+    fn construct(&mut self, ty: &Type, fields: &[(String, Node)]) -> Result<(), WatError> {
+        let record: &RecordType = match ty {
+            Type::Record(record) => record,
+            ty => return unsupported(format!("building a `{}`", ty)),
+        };
+        let local = self.label("record");
+        self.scratch.push((local.clone(), ValueType::I32));
+        self.line(format!(
+            "i32.const {}",
+            record.fields.len() as u32 * FIELD_SIZE
+        ));
+        self.line("call $zen.alloc");
+        self.line(format!("local.set {}", local));
+        for (index, field) in record.fields.iter().enumerate() {
+            let value = match fields.iter().find(|(name, _)| *name == field.name) {
+                Some((_, value)) => value,
+                None => return unsupported(format!("a record without `{}`", field.name)),
+            };
+            self.line(format!("local.get {}", local));
+            self.coerced(value, &field.ty)?;
+            let value_type = value_type_of(&field.ty)?;
+            self.line(format!(
+                "{}.store offset={}",
+                value_type.name(),
+                index as u32 * FIELD_SIZE
+            ));
+        }
+        self.line(format!("local.get {}", local));
+        Ok(())
+    }
+
+    // the tag goes in the first slot and the payload, if the variant has one,
+    // in the second
+    fn variant(&mut self, ty: &Type, tag: usize, payload: Option<&Node>) -> Result<(), WatError> {
+        let enumeration = match ty {
+            Type::Enum(enumeration) => enumeration,
+            ty => return unsupported(format!("building a `{}`", ty)),
+        };
+        let local = self.label("enum");
+        self.scratch.push((local.clone(), ValueType::I32));
+        self.line(format!("i32.const {}", 2 * FIELD_SIZE));
+        self.line("call $zen.alloc");
+        self.line(format!("local.tee {}", local));
+        self.line(format!("i32.const {}", tag));
+        self.line("i32.store");
+        if let Some(payload) = payload {
+            let payload_ty = match &enumeration.variants[tag].kind {
+                VariantKind::Value(ty) | VariantKind::Payload(ty) => ty.clone(),
+                VariantKind::Plain => payload.ty(),
+            };
+            self.line(format!("local.get {}", local));
+            self.coerced(payload, &payload_ty)?;
+            let value_type = value_type_of(&payload_ty)?;
+            self.line(format!("{}.store offset={}", value_type.name(), FIELD_SIZE));
+        }
+        self.line(format!("local.get {}", local));
+        Ok(())
+    }
+
+    fn unary(&mut self, op: &UnaryOp, operand: &Node, ty: &Type) -> Result<(), WatError> {
+        let value_type = value_type_of(ty)?;
+        let name = value_type.name();
+        match (op, value_type) {
+            (UnaryOp::Negate, ValueType::F32 | ValueType::F64) => {
+                self.value(operand)?;
+                self.line(format!("{}.neg", name));
+            }
+            (UnaryOp::Negate, _) => {
+                self.line(format!("{}.const 0", name));
+                self.coerced(operand, ty)?;
+                self.line(format!("{}.sub", name));
+            }
+            (UnaryOp::Not, ValueType::I32) if *ty == Type::Bool => {
+                self.value(operand)?;
+                self.line("i32.eqz");
+            }
+            (UnaryOp::BitwiseNot, ValueType::I32 | ValueType::I64) => {
+                self.coerced(operand, ty)?;
+                self.line(format!("{}.const -1", name));
+                self.line(format!("{}.xor", name));
+            }
+            (op, _) => return unsupported(format!("`{}` on `{}`", op.symbol(), ty)),
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, op: &BinaryOp, left: &Node, right: &Node) -> Result<(), WatError> {
+        let ty = match (left.ty(), right.ty()) {
+            (Type::Int(None), ty @ Type::Int(Some(_))) => ty,
+            (Type::Float(None), ty @ Type::Float(Some(_))) => ty,
+            (ty, _) => ty,
+        };
+        if let (Type::String, BinaryOp::Equal | BinaryOp::NotEqual) = (&ty, op) {
+            self.value(left)?;
+            self.value(right)?;
+            self.call_runtime("$zen.string.eq");
+            if let BinaryOp::NotEqual = op {
+                self.line("i32.eqz");
+            }
+            return Ok(());
+        }
+        if !matches!(ty, Type::Int(_) | Type::Float(_) | Type::Bool | Type::Char) {
+            return unsupported(format!("`{}` on `{}`", op.symbol(), ty));
+        }
+        self.coerced(left, &ty)?;
+        self.coerced(right, &ty)?;
+        let value_type = value_type_of(&ty)?;
+        let float = matches!(value_type, ValueType::F32 | ValueType::F64);
+        let sign = match is_signed(&ty) {
+            true => "_s",
+            false => "_u",
+        };
+        let instruction = match (op, float) {
+            (BinaryOp::Add, _) => "add".to_string(),
+            (BinaryOp::Subtract, _) => "sub".to_string(),
+            (BinaryOp::Multiply, _) => "mul".to_string(),
+            (BinaryOp::Divide, true) => "div".to_string(),
+            (BinaryOp::Divide, false) => format!("div{}", sign),
+            (BinaryOp::Modulo, false) => format!("rem{}", sign),
+            (BinaryOp::BitwiseAnd, false) => "and".to_string(),
+            (BinaryOp::BitwiseOr, false) => "or".to_string(),
+            (BinaryOp::BitwiseXor, false) => "xor".to_string(),
+            (BinaryOp::ShiftLeft, false) => "shl".to_string(),
+            (BinaryOp::ShiftRight, false) => format!("shr{}", sign),
+            (BinaryOp::Equal, _) => "eq".to_string(),
+            (BinaryOp::NotEqual, _) => "ne".to_string(),
+            (BinaryOp::LessThan, true) => "lt".to_string(),
+            (BinaryOp::LessThan, false) => format!("lt{}", sign),
+            (BinaryOp::LessThanOrEqual, true) => "le".to_string(),
+            (BinaryOp::LessThanOrEqual, false) => format!("le{}", sign),
+            (BinaryOp::GreaterThan, true) => "gt".to_string(),
+            (BinaryOp::GreaterThan, false) => format!("gt{}", sign),
+            (BinaryOp::GreaterThanOrEqual, true) => "ge".to_string(),
+            (BinaryOp::GreaterThanOrEqual, false) => format!("ge{}", sign),
+            (op, _) => return unsupported(format!("`{}` on `{}`", op.symbol(), ty)),
+        };
+        self.line(format!("{}.{}", value_type.name(), instruction));
+        Ok(())
+    }
+
+    // `Int(x)`, `Float(x)` and the sized conversions between numbers
+    fn convert(&mut self, value: &Node, ty: &Type) -> Result<(), WatError> {
+        let from = value.ty();
+        let (source, target) = (value_type_of(&from)?, value_type_of(ty)?);
+        self.value(value)?;
+        let float = |value_type| matches!(value_type, ValueType::F32 | ValueType::F64);
+        match (&from, ty) {
+            (Type::Int(_), Type::Int(_)) | (Type::Float(_), Type::Float(_)) => {
+                self.coerce(&from, ty)?
+            }
+            (Type::Bool | Type::Char, Type::Int(_)) => {
+                self.coerce(&Type::Int(Some(NumberSuffix::U32)), ty)?
+            }
+            (Type::Int(_) | Type::Bool | Type::Char, Type::Float(_)) => {
+                let sign = match is_signed(&from) {
+                    true => "s",
+                    false => "u",
+                };
+                self.line(format!(
+                    "{}.convert_{}_{}",
+                    target.name(),
+                    source.name(),
+                    sign
+                ));
+            }
+            (Type::Float(_), Type::Int(_)) if float(source) => {
+                let sign = match is_signed(ty) {
+                    true => "s",
+                    false => "u",
+                };
+                self.line(format!(
+                    "{}.trunc_sat_{}_{}",
+                    target.name(),
+                    source.name(),
+                    sign
+                ));
+            }
+            (from, to) => return unsupported(format!("converting `{}` to `{}`", from, to)),
+        }
+        Ok(())
+    }
+
+    // arguments are already in parameter order, with the receiver bound to `self`
+    fn call(&mut self, callee: &Callee, args: &[Node]) -> Result<(), WatError> {
+        let signature = match callee {
+            Callee::Fn(name) => self.function_table[name].signature.clone(),
+            Callee::Value(value) => match value.ty() {
+                Type::Function(function) => *function,
+                ty => return unsupported(format!("calling `{}`", ty)),
+            },
+        };
+        for (arg, (_, ty)) in args.iter().zip(&signature.params) {
+            self.coerced(arg, ty)?;
+        }
+        match callee {
+            Callee::Fn(name) => {
+                let name = self.function_table[name].name.clone();
+                self.line(format!("call ${}", name));
+            }
+            Callee::Value(value) => {
+                self.value(value)?;
+                let params = params(&signature)?;
+                self.line(format!("call_indirect{}", params));
+            }
+        }
+        Ok(())
+    }
+
+    fn if_(
+        &mut self,
+        cond: &Node,
+        then: &Node,
+        otherwise: Option<&Node>,
+        ty: &Type,
+    ) -> Result<(), WatError> {
+        let result = value_type(ty)?;
+        self.value(cond)?;
+        match result {
+            Some(result) => self.line(format!("if (result {})", result.name())),
+            None => self.line("if"),
+        }
+        for (i, branch) in [Some(then), otherwise].into_iter().enumerate() {
+            let branch = match branch {
+                Some(branch) => branch,
+                None => continue,
+            };
+            if i == 1 {
+                self.line("else");
+            }
+            self.indentation_level += 1;
+            match (self.node(branch)?, result) {
+                (Some(_), Some(_)) => self.coerce(&branch.ty(), ty)?,
+                (Some(_), None) => self.line("drop"),
+                // ends in a `return`
+                (None, Some(_)) => self.line("unreachable"),
+                (None, None) => {}
+            }
+            self.indentation_level -= 1;
+        }
+        self.line("end");
+        Ok(())
+    }
+}
+
+fn value_type_of(ty: &Type) -> Result<ValueType, WatError> {
+    match value_type(ty)? {
+        Some(value_type) => Ok(value_type),
+        None => unsupported(format!("a value of type `{}`", ty)),
+    }
 }
 
+const WASI: [&str; 3] = [
+    "\"fd_read\" (func $wasi.fd_read (param i32 i32 i32 i32) (result i32))",
+    "\"fd_write\" (func $wasi.fd_write (param i32 i32 i32 i32) (result i32))",
+    "\"proc_exit\" (func $wasi.proc_exit (param i32))",
+];
+
+// writes a string to stdout
+const WRITE: &str = "\
+(func $zen.write (param $string i32)
+  i32.const {IOVEC}
+  local.get $string
+  i32.const 4
+  i32.add
+  i32.store
+  i32.const {IOVEC}
+  local.get $string
+  i32.load
+  i32.store offset=4
+  i32.const {STDOUT}
+  i32.const {IOVEC}
+  i32.const 1
+  i32.const {WRITTEN}
+  call $wasi.fd_write
+  drop
+)";
+
+// reads stdin a byte at a time into the free memory at the heap, growing it
+// as needed, up to a newline or the end of the input, then allocates what
+// was read as a string, which nothing else has allocated over in between
+const READ_LINE: &str = "\
+(func $zen.read_line (result i32)
+  (local $string i32)
+  (local $length i32)
+  global.get $zen.heap
+  local.set $string
+  block $done
+    loop $read
+      local.get $string
+      local.get $length
+      i32.add
+      i32.const 5
+      i32.add
+      memory.size
+      i32.const {PAGE_BITS}
+      i32.shl
+      i32.gt_u
+      if
+        i32.const 1
+        memory.grow
+        drop
+      end
+      i32.const {IOVEC}
+      local.get $string
+      i32.const 4
+      i32.add
+      local.get $length
+      i32.add
+      i32.store
+      i32.const {IOVEC}
+      i32.const 1
+      i32.store offset=4
+      i32.const {STDIN}
+      i32.const {IOVEC}
+      i32.const 1
+      i32.const {WRITTEN}
+      call $wasi.fd_read
+      br_if $done
+      i32.const {WRITTEN}
+      i32.load
+      i32.eqz
+      br_if $done
+      local.get $string
+      i32.const 4
+      i32.add
+      local.get $length
+      i32.add
+      i32.load8_u
+      i32.const 10
+      i32.eq
+      br_if $done
+      local.get $length
+      i32.const 1
+      i32.add
+      local.set $length
+      br $read
+    end
+  end
+  local.get $string
+  local.get $length
+  i32.store
+  local.get $length
+  i32.const 4
+  i32.add
+  call $zen.alloc
+)";
+
+// a new string with the bytes of both
+const CONCAT: &str = "\
+(func $zen.concat (param $a i32) (param $b i32) (result i32)
+  (local $length i32)
+  (local $string i32)
+  local.get $a
+  i32.load
+  local.get $b
+  i32.load
+  i32.add
+  local.set $length
+  local.get $length
+  i32.const 4
+  i32.add
+  call $zen.alloc
+  local.set $string
+  local.get $string
+  local.get $length
+  i32.store
+  local.get $string
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.load
+  memory.copy
+  local.get $string
+  i32.const 4
+  i32.add
+  local.get $a
+  i32.load
+  i32.add
+  local.get $b
+  i32.const 4
+  i32.add
+  local.get $b
+  i32.load
+  memory.copy
+  local.get $string
+)";
+
+// the digits of an unsigned number, counted first so they can be written
+// from the last one
+const STRING_U64: &str = "\
+(func $zen.string.u64 (param $n i64) (result i32)
+  (local $length i32)
+  (local $rest i64)
+  (local $string i32)
+  (local $digit i32)
+  i32.const 1
+  local.set $length
+  local.get $n
+  local.set $rest
+  block $counted
+    loop $count
+      local.get $rest
+      i64.const 10
+      i64.lt_u
+      br_if $counted
+      local.get $rest
+      i64.const 10
+      i64.div_u
+      local.set $rest
+      local.get $length
+      i32.const 1
+      i32.add
+      local.set $length
+      br $count
+    end
+  end
+  local.get $length
+  i32.const 4
+  i32.add
+  call $zen.alloc
+  local.set $string
+  local.get $string
+  local.get $length
+  i32.store
+  local.get $string
+  local.get $length
+  i32.add
+  i32.const 3
+  i32.add
+  local.set $digit
+  loop $write
+    local.get $digit
+    local.get $n
+    i64.const 10
+    i64.rem_u
+    i32.wrap_i64
+    i32.const 48
+    i32.add
+    i32.store8
+    local.get $digit
+    i32.const 1
+    i32.sub
+    local.set $digit
+    local.get $n
+    i64.const 10
+    i64.div_u
+    local.tee $n
+    i64.const 0
+    i64.ne
+    br_if $write
+  end
+  local.get $string
+)";
+
+const STRING_I64: &str = "\
+(func $zen.string.i64 (param $n i64) (result i32)
+  local.get $n
+  i64.const 0
+  i64.lt_s
+  if (result i32)
+    i32.const {MINUS}
+    i64.const 0
+    local.get $n
+    i64.sub
+    call $zen.string.u64
+    call $zen.concat
+  else
+    local.get $n
+    call $zen.string.u64
+  end
+)";
 
+// 1 when both strings have the same bytes, compared from the last one
+const STRING_EQ: &str = "\
+(func $zen.string.eq (param $a i32) (param $b i32) (result i32)
+  (local $i i32)
+  local.get $a
+  i32.load
+  local.tee $i
+  local.get $b
+  i32.load
+  i32.ne
+  if
+    i32.const 0
+    return
+  end
+  block $equal
+    loop $bytes
+      local.get $i
+      i32.eqz
+      br_if $equal
+      local.get $i
+      i32.const 1
+      i32.sub
+      local.set $i
+      local.get $a
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      local.get $b
+      local.get $i
+      i32.add
+      i32.load8_u offset=4
+      i32.ne
+      if
+        i32.const 0
+        return
+      end
+      br $bytes
+    end
+  end
+  i32.const 1
+)";
+
+// the char as UTF-8, one to four bytes
+const STRING_CHAR: &str = "\
+(func $zen.string.char (param $char i32) (result i32)
+  (local $string i32)
+  i32.const 8
+  call $zen.alloc
+  local.set $string
+  block $four
+    block $three
+      block $two
+        block $one
+          local.get $char
+          i32.const 0x80
+          i32.lt_u
+          br_if $one
+          local.get $char
+          i32.const 0x800
+          i32.lt_u
+          br_if $two
+          local.get $char
+          i32.const 0x10000
+          i32.lt_u
+          br_if $three
+          br $four
+        end
+        local.get $string
+        local.get $char
+        i32.store8 offset=4
+        local.get $string
+        i32.const 1
+        i32.store
+        local.get $string
+        return
+      end
+      local.get $string
+      local.get $char
+      i32.const 6
+      i32.shr_u
+      i32.const 0xc0
+      i32.or
+      i32.store8 offset=4
+      local.get $string
+      local.get $char
+      i32.const 0x3f
+      i32.and
+      i32.const 0x80
+      i32.or
+      i32.store8 offset=5
+      local.get $string
+      i32.const 2
+      i32.store
+      local.get $string
+      return
+    end
+    local.get $string
+    local.get $char
+    i32.const 12
+    i32.shr_u
+    i32.const 0xe0
+    i32.or
+    i32.store8 offset=4
+    local.get $string
+    local.get $char
+    i32.const 6
+    i32.shr_u
+    i32.const 0x3f
+    i32.and
+    i32.const 0x80
+    i32.or
+    i32.store8 offset=5
+    local.get $string
+    local.get $char
+    i32.const 0x3f
+    i32.and
+    i32.const 0x80
+    i32.or
+    i32.store8 offset=6
+    local.get $string
+    i32.const 3
+    i32.store
+    local.get $string
+    return
+  end
+  local.get $string
+  local.get $char
+  i32.const 18
+  i32.shr_u
+  i32.const 0xf0
+  i32.or
+  i32.store8 offset=4
+  local.get $string
+  local.get $char
+  i32.const 12
+  i32.shr_u
+  i32.const 0x3f
+  i32.and
+  i32.const 0x80
+  i32.or
+  i32.store8 offset=5
+  local.get $string
+  local.get $char
+  i32.const 6
+  i32.shr_u
+  i32.const 0x3f
+  i32.and
+  i32.const 0x80
+  i32.or
+  i32.store8 offset=6
+  local.get $string
+  local.get $char
+  i32.const 0x3f
+  i32.and
+  i32.const 0x80
+  i32.or
+  i32.store8 offset=7
+  local.get $string
+  i32.const 4
+  i32.store
+  local.get $string
+)";
 
 #[test]
 fn test_generate_binary() {
-    let codegen = watCodeGen::new();
+    let mut codegen = watCodeGen::new();
     // let file = std::fs::read_to_string(path).unwrap();
     let file = "main: Fn {
-    a: void,
-    r: int,
+    args: { a: Int }
     fn: {
-        a: 1
-        return(a + 3)
-    }
-   }".to_string();
-   
-    let mut parser = parser::Parser::new(&file);
-    let ast = parser.parse().unwrap();
-    println!("{:#?}", ast);
-    let wat = codegen.generate(&ast);
+        b: a + 3
+        return(b)
+    }
+   }"
+    .to_string();
+
+    let program = ir::lower_source(&file).unwrap();
+    let wat = codegen.generate(&program).unwrap();
     println!("{}", wat);
-    
-}
\ No newline at end of file
+    assert!(wat.contains("(func $main (export \"main\") (param $a i64) (result i64)"));
+    assert!(wat.contains("    local.get $a\n    i64.const 3\n    i64.add\n    local.set $b\n"));
+}
+
+// `wat` only checks the text, wasmi validates the module like a runtime would
+#[cfg(test)]
+fn assemble(source: &str) -> String {
+    let program = ir::lower_source(source).unwrap();
+    let wat = watCodeGen::new().generate(&program).unwrap();
+    let wasm = wat::parse_str(&wat).unwrap_or_else(|error| panic!("{}\n{}", error, wat));
+    if let Err(error) = wasmi::Module::new(&wasmi::Engine::default(), &wasm[..]) {
+        panic!("{}\n{}", error, wat);
+    }
+    wat
+}
+
+#[test]
+fn test_generate_valid_control_flow() {
+    let wat = assemble(
+        "\
+greeting: \"hello\"
+count: Fn {
+    args: { limit: Int }
+    return: Int
+    body: {
+        n: 0
+        while (n < limit) { n = n + 1 }
+        if (n > 2) { body: { io.std.writeLine(greeting, n) }, else: { return(0) } }
+        n
+    }
+}",
+    );
+    // strings are a length and then the bytes, after the 16 bytes `fd_write` uses
+    assert!(wat.contains("(data (i32.const 16) \""));
+    assert!(wat.contains("\\05\\00\\00\\00\\68\\65\\6c\\6c\\6f"));
+    assert!(wat.contains("(global $greeting (mut i32) (i32.const 0))"));
+    assert!(wat.contains("(local $n i64)"));
+    assert!(wat.contains("local.set $n"));
+    assert!(wat.contains("loop $loop2"));
+    assert!(wat.contains("br_if $break1"));
+    assert!(wat.contains("if\n"));
+    assert!(wat.contains("else\n"));
+}
+
+#[test]
+fn test_generate_valid_calls() {
+    let wat = assemble(
+        "\
+add: Fn { args: { a: Int, b: Int } return: Int fn: { a + b } }
+twice: Fn { args: { self: Int } body: { self.add(b: self) } }
+apply: Fn { args: { f: Fn { args: { a: Int, b: Int } return: Int }, x: Int } return: Int fn: { f(x, x) } }
+main: Fn { return: Int body: { n: 3\n apply(add, n.twice()) } }",
+    );
+    assert!(wat.contains("(func $add (export \"add\") (param $a i64) (param $b i64) (result i64)"));
+    assert!(wat.contains("(func $main (export \"main\") (result i64)"));
+    assert!(wat.contains("(func $_start (export \"_start\")"));
+    assert!(wat.contains("call $add"));
+    assert!(wat.contains("call_indirect (param i64) (param i64) (result i64)"));
+}